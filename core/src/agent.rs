@@ -1,6 +1,8 @@
 use async_trait::async_trait;
 use anyhow::Result;
+use crate::callbacks::{trace_chain, trace_llm, trace_tool};
 use crate::llm::LLM;
+use crate::schema::PromptValue;
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -30,6 +32,12 @@ impl AgentExecutor {
     }
 
     pub async fn execute(&self, input: &str) -> Result<String> {
+        let inputs = HashMap::from([("input".to_string(), input.to_string())]);
+        let outputs = |output: &String| HashMap::from([("output".to_string(), output.clone())]);
+        trace_chain("AgentExecutor", &inputs, outputs, self.execute_inner(input)).await
+    }
+
+    async fn execute_inner(&self, input: &str) -> Result<String> {
         // Very simple "Zero-Shot" style agent:
         // 1. Ask LLM what to do.
         // 2. Parse response (expecting "Action: [Name] Input: [Value]").
@@ -41,7 +49,7 @@ impl AgentExecutor {
             self.tools.keys().cloned().collect::<Vec<_>>().join(", ")
         );
 
-        let response = self.llm.generate(&prompt).await?;
+        let response = trace_llm(self.llm.as_ref(), PromptValue::Text(prompt)).await?.text;
         
         if let Some(final_answer) = response.split("Final Answer:").nth(1) {
             return Ok(final_answer.trim().to_string());
@@ -54,7 +62,7 @@ impl AgentExecutor {
                 let tool_input = parts[1].trim();
 
                 if let Some(tool) = self.tools.get(tool_name) {
-                    let tool_output = tool.call(tool_input).await?;
+                    let tool_output = trace_tool(tool_name, tool_input, tool.call(tool_input)).await?;
                     // In a real agent, we'd loop back. Here we just return the tool output for simplicity.
                     return Ok(format!("Tool Output: {}", tool_output));
                 }
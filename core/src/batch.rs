@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+/// Counts reported after every finished item. `completed` includes failures and items
+/// restored from a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchProgress {
+    pub completed: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(BatchProgress) + Send + Sync>;
+
+/// How `LLMChain::batch` runs its inputs.
+#[derive(Clone)]
+pub struct BatchOptions {
+    concurrency: usize,
+    on_progress: Option<ProgressCallback>,
+    checkpoint: Option<PathBuf>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self { concurrency: 4, on_progress: None, checkpoint: None }
+    }
+}
+
+impl BatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum items in flight at once. Defaults to 4.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(BatchProgress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Appends each successful output to this JSONL file. Rerunning the same batch with the
+    /// same file skips items already recorded there (with identical inputs), so an
+    /// interrupted batch resumes where it stopped. Failed items are retried.
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+}
+
+/// One line of a checkpoint file.
+#[derive(Serialize, Deserialize)]
+struct CheckpointEntry {
+    index: usize,
+    inputs: HashMap<String, String>,
+    output: String,
+}
+
+/// Runs `f` over `inputs` as configured by `options` and returns one result per input, in
+/// input order. Item failures are captured in place; only checkpoint I/O fails the batch.
+pub async fn run_batch<F, Fut>(
+    inputs: Vec<HashMap<String, String>>,
+    options: &BatchOptions,
+    f: F,
+) -> Result<Vec<Result<String>>>
+where
+    F: Fn(HashMap<String, String>) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let total = inputs.len();
+    let mut results: Vec<Option<Result<String>>> = (0..total).map(|_| None).collect();
+
+    let mut checkpoint = None;
+    if let Some(path) = &options.checkpoint {
+        if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read checkpoint {}", path.display()))?;
+            // A line cut short by a crash is simply redone.
+            for entry in contents.lines().filter_map(|l| serde_json::from_str::<CheckpointEntry>(l).ok()) {
+                if inputs.get(entry.index) == Some(&entry.inputs) {
+                    results[entry.index] = Some(Ok(entry.output));
+                }
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open checkpoint {}", path.display()))?;
+        checkpoint = Some(file);
+    }
+
+    let pending: Vec<usize> = (0..total).filter(|&i| results[i].is_none()).collect();
+    let mut completed = total - pending.len();
+    let mut failed = 0;
+
+    let mut outputs = stream::iter(pending)
+        .map(|i| {
+            let future = f(inputs[i].clone());
+            async move { (i, future.await) }
+        })
+        .buffer_unordered(options.concurrency);
+
+    while let Some((index, result)) = outputs.next().await {
+        match (&result, checkpoint.as_mut()) {
+            (Ok(output), Some(file)) => {
+                let entry = CheckpointEntry { index, inputs: inputs[index].clone(), output: output.clone() };
+                writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+                file.flush()?;
+            }
+            (Err(_), _) => failed += 1,
+            _ => {}
+        }
+        completed += 1;
+        results[index] = Some(result);
+        if let Some(callback) = &options.on_progress {
+            callback(BatchProgress { completed, failed, total });
+        }
+    }
+
+    Ok(results.into_iter().map(|r| r.expect("every item is restored or run")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn inputs(n: usize) -> Vec<HashMap<String, String>> {
+        (0..n).map(|i| HashMap::from([("n".to_string(), i.to_string())])).collect()
+    }
+
+    #[tokio::test]
+    async fn test_results_in_order_with_errors() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let recorded = progress.clone();
+        let options = BatchOptions::new()
+            .with_concurrency(2)
+            .with_progress(move |p| recorded.lock().unwrap().push(p));
+
+        let results = run_batch(inputs(6), &options, |input| {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                let n: u64 = input["n"].parse().unwrap();
+                // Later items finish first, so completion order differs from input order.
+                tokio::time::sleep(std::time::Duration::from_millis(20 - n * 3)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                if n == 3 { anyhow::bail!("boom") } else { Ok(format!("out {}", n)) }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(results[0].as_ref().unwrap(), "out 0");
+        assert_eq!(results[5].as_ref().unwrap(), "out 5");
+        assert_eq!(results[3].as_ref().unwrap_err().to_string(), "boom");
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress.len(), 6);
+        assert_eq!(*progress.last().unwrap(), BatchProgress { completed: 6, failed: 1, total: 6 });
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_checkpoint() {
+        let path = std::env::temp_dir().join(format!("mini_langchain_batch_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = BatchOptions::new().with_checkpoint(&path);
+
+        let first = run_batch(inputs(4), &options, |input| async move {
+            if input["n"] == "2" { anyhow::bail!("interrupted") } else { Ok(format!("first {}", input["n"])) }
+        })
+        .await
+        .unwrap();
+        assert!(first[2].is_err());
+
+        let calls = AtomicUsize::new(0);
+        let second = run_batch(inputs(4), &options, |input| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move { Ok(format!("second {}", input["n"])) }
+        })
+        .await
+        .unwrap();
+        let outputs: Vec<String> = second.into_iter().map(Result::unwrap).collect();
+        assert_eq!(outputs, ["first 0", "first 1", "second 2", "first 3"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
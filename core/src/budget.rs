@@ -0,0 +1,377 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use async_trait::async_trait;
+use crate::cost::PricingTable;
+use crate::llm::{messages_to_prompt, Generation, TokenUsage, LLM};
+use crate::schema::ChatMessage;
+use crate::token::TokenCounter;
+
+/// What a `BudgetGuard` limits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetLimit {
+    /// USD, priced with the guard's `PricingTable`.
+    Dollars(f64),
+    /// Prompt plus completion tokens.
+    Tokens(u64),
+}
+
+impl BudgetLimit {
+    fn amount(&self) -> f64 {
+        match self {
+            BudgetLimit::Dollars(dollars) => *dollars,
+            BudgetLimit::Tokens(tokens) => *tokens as f64,
+        }
+    }
+}
+
+/// Window after which spending starts again from zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    /// Never resets (until `BudgetGuard::reset`).
+    Lifetime,
+    /// Resets once this long has passed since the window opened (at creation, `reset` or the
+    /// previous rollover).
+    Every(Duration),
+}
+
+impl BudgetPeriod {
+    pub fn hourly() -> Self {
+        BudgetPeriod::Every(Duration::from_secs(60 * 60))
+    }
+
+    pub fn daily() -> Self {
+        BudgetPeriod::Every(Duration::from_secs(24 * 60 * 60))
+    }
+}
+
+/// Returned (inside `anyhow::Error`) when a call would exceed the budget; the call is not made.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub struct BudgetExceededError {
+    pub limit: BudgetLimit,
+    /// Spent in the current period, including calls still in flight.
+    pub spent: f64,
+    /// Estimated cost of the refused call.
+    pub estimated: f64,
+}
+
+impl fmt::Display for BudgetExceededError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            BudgetLimit::Dollars(limit) => write!(
+                f,
+                "Budget exceeded: ${:.4} spent + ${:.4} estimated exceeds the ${:.4} limit",
+                self.spent, self.estimated, limit
+            ),
+            BudgetLimit::Tokens(limit) => write!(
+                f,
+                "Budget exceeded: {} tokens spent + {} estimated exceeds the {} token limit",
+                self.spent, self.estimated, limit
+            ),
+        }
+    }
+}
+
+/// Returned (inside `anyhow::Error`) when a dollar budget cannot price a call because the
+/// model is unnamed or missing from the pricing table; the call is not made.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Cannot enforce a dollar budget: {}", match .model {
+    Some(model) => format!("no pricing for model '{}'", model),
+    None => "the LLM does not report a model name".to_string(),
+})]
+pub struct UnpricedModelError {
+    pub model: Option<String>,
+}
+
+struct BudgetState {
+    window_start: Instant,
+    /// Incremented on every reset so reservations from an earlier window are not released twice.
+    window: u64,
+    spent: f64,
+    reserved: f64,
+}
+
+/// A reserved estimate for an in-flight call.
+struct Reservation {
+    window: u64,
+    amount: f64,
+}
+
+/// Shared spending limit for one or more `BudgetedLLM`s.
+///
+/// Before each call the prompt is counted with `TokenCounter` and, together with
+/// `expected_output_tokens`, reserved against the budget; calls that would exceed it are
+/// refused with `BudgetExceededError`. Afterwards the reservation is replaced by the usage
+/// the provider reported, or by counting the completion when it reports none. With a dollar
+/// limit, calls to unnamed models or models missing from the pricing table are refused with
+/// `UnpricedModelError`; add their prices with `with_pricing`.
+pub struct BudgetGuard {
+    limit: BudgetLimit,
+    period: BudgetPeriod,
+    pricing: PricingTable,
+    expected_output_tokens: u32,
+    state: Mutex<BudgetState>,
+}
+
+impl BudgetGuard {
+    pub fn new(limit: BudgetLimit, period: BudgetPeriod) -> Self {
+        Self {
+            limit,
+            period,
+            pricing: PricingTable::builtin(),
+            expected_output_tokens: 256,
+            state: Mutex::new(BudgetState { window_start: Instant::now(), window: 0, spent: 0.0, reserved: 0.0 }),
+        }
+    }
+
+    /// Prices for dollar limits. Defaults to `PricingTable::builtin`.
+    pub fn with_pricing(mut self, pricing: PricingTable) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Completion tokens assumed when estimating a call. Defaults to 256.
+    pub fn with_expected_output_tokens(mut self, tokens: u32) -> Self {
+        self.expected_output_tokens = tokens;
+        self
+    }
+
+    pub fn limit(&self) -> BudgetLimit {
+        self.limit
+    }
+
+    /// Spent in the current period, excluding calls in flight.
+    pub fn spent(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        self.roll_window(&mut state);
+        state.spent
+    }
+
+    /// Left in the current period, after calls in flight.
+    pub fn remaining(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        self.roll_window(&mut state);
+        (self.limit.amount() - state.spent - state.reserved).max(0.0)
+    }
+
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.window_start = Instant::now();
+        state.window += 1;
+        state.spent = 0.0;
+        state.reserved = 0.0;
+    }
+
+    fn roll_window(&self, state: &mut BudgetState) {
+        if let BudgetPeriod::Every(period) = self.period {
+            if state.window_start.elapsed() >= period {
+                state.window_start = Instant::now();
+                state.window += 1;
+                state.spent = 0.0;
+                state.reserved = 0.0;
+            }
+        }
+    }
+
+    /// Amount a call with this usage counts against the budget.
+    fn amount(&self, model: Option<&str>, usage: &TokenUsage) -> Result<f64> {
+        match self.limit {
+            BudgetLimit::Dollars(_) => model
+                .and_then(|m| self.pricing.cost(m, usage))
+                .ok_or_else(|| UnpricedModelError { model: model.map(str::to_string) }.into()),
+            BudgetLimit::Tokens(_) => Ok((usage.prompt_tokens + usage.completion_tokens) as f64),
+        }
+    }
+
+    /// Estimated usage of sending `prompt`. Fails with `UnpricedModelError` when a dollar
+    /// limit cannot price `model`.
+    pub fn estimate(&self, model: Option<&str>, prompt: &str) -> Result<f64> {
+        let prompt_tokens = TokenCounter::count(prompt) as u32;
+        let usage = TokenUsage {
+            prompt_tokens,
+            completion_tokens: self.expected_output_tokens,
+            total_tokens: prompt_tokens + self.expected_output_tokens,
+            ..Default::default()
+        };
+        self.amount(model, &usage)
+    }
+
+    fn reserve(&self, model: Option<&str>, prompt: &str) -> Result<Reservation> {
+        let estimated = self.estimate(model, prompt)?;
+        let mut state = self.state.lock().unwrap();
+        self.roll_window(&mut state);
+        let committed = state.spent + state.reserved;
+        if committed + estimated > self.limit.amount() {
+            return Err(BudgetExceededError { limit: self.limit, spent: committed, estimated }.into());
+        }
+        state.reserved += estimated;
+        Ok(Reservation { window: state.window, amount: estimated })
+    }
+
+    /// Replaces the reservation with the actual amount, or just releases it when the call failed.
+    /// A response from a model the guard cannot price is charged the reserved estimate.
+    fn reconcile(&self, reservation: Reservation, model: Option<&str>, prompt: &str, generation: Option<&Generation>) {
+        let actual = generation.map(|g| {
+            let usage = g.usage.clone().unwrap_or_else(|| {
+                let prompt_tokens = TokenCounter::count(prompt) as u32;
+                let completion_tokens = TokenCounter::count(&g.text) as u32;
+                TokenUsage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens, ..Default::default() }
+            });
+            self.amount(g.model.as_deref().or(model), &usage).unwrap_or(reservation.amount)
+        });
+
+        let mut state = self.state.lock().unwrap();
+        self.roll_window(&mut state);
+        if state.window == reservation.window {
+            state.reserved = (state.reserved - reservation.amount).max(0.0);
+        }
+        state.spent += actual.unwrap_or(0.0);
+    }
+}
+
+/// An `LLM` that refuses calls once its `BudgetGuard` is exhausted.
+pub struct BudgetedLLM {
+    llm: Arc<dyn LLM>,
+    guard: Arc<BudgetGuard>,
+}
+
+impl BudgetedLLM {
+    pub fn new(llm: Arc<dyn LLM>, guard: Arc<BudgetGuard>) -> Self {
+        Self { llm, guard }
+    }
+
+    pub fn guard(&self) -> &Arc<BudgetGuard> {
+        &self.guard
+    }
+}
+
+#[async_trait]
+impl LLM for BudgetedLLM {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        Ok(self.generate_with_usage(prompt).await?.text)
+    }
+
+    fn model_name(&self) -> Option<String> {
+        self.llm.model_name()
+    }
+
+    async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+        let model = self.llm.model_name();
+        let reservation = self.guard.reserve(model.as_deref(), prompt)?;
+        let result = self.llm.generate_with_usage(prompt).await;
+        self.guard.reconcile(reservation, model.as_deref(), prompt, result.as_ref().ok());
+        result
+    }
+
+    async fn generate_messages(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        let model = self.llm.model_name();
+        let prompt = messages_to_prompt(messages);
+        let reservation = self.guard.reserve(model.as_deref(), &prompt)?;
+        let result = self.llm.generate_messages(messages).await;
+        self.guard.reconcile(reservation, model.as_deref(), &prompt, result.as_ref().ok());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost::ModelPricing;
+
+    /// Reports 1000 prompt and 100 completion tokens per call.
+    struct MeteredLLM;
+
+    #[async_trait]
+    impl LLM for MeteredLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            Ok("done".to_string())
+        }
+
+        fn model_name(&self) -> Option<String> {
+            Some("metered".to_string())
+        }
+
+        async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+            let mut generation = Generation::new(self.generate(prompt).await?);
+            generation.usage = Some(TokenUsage { prompt_tokens: 1000, completion_tokens: 100, total_tokens: 1100, ..Default::default() });
+            Ok(generation)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_reconciles_reported_usage() {
+        let guard = Arc::new(
+            BudgetGuard::new(BudgetLimit::Tokens(2500), BudgetPeriod::Lifetime).with_expected_output_tokens(10),
+        );
+        let llm = BudgetedLLM::new(Arc::new(MeteredLLM), guard.clone());
+
+        // Each call is estimated at a few tokens but reconciled to the 1100 reported.
+        llm.generate("hello").await.unwrap();
+        llm.generate("hello").await.unwrap();
+        assert_eq!(guard.spent(), 2200.0);
+
+        let refused = llm.generate(&"word ".repeat(400)).await.unwrap_err();
+        let error = refused.downcast_ref::<BudgetExceededError>().unwrap();
+        assert_eq!(error.spent, 2200.0);
+        assert!(error.estimated > 300.0);
+        assert_eq!(guard.spent(), 2200.0);
+
+        // A small call still fits, and overshoots the limit only by what was actually reported.
+        llm.generate("hi").await.unwrap();
+        assert_eq!(guard.remaining(), 0.0);
+        assert!(llm.generate("hi").await.is_err());
+
+        guard.reset();
+        assert!(llm.generate("hi").await.is_ok());
+    }
+
+    struct UnnamedLLM;
+
+    #[async_trait]
+    impl LLM for UnnamedLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            Ok("done".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dollar_budget_refuses_unpriced_models() {
+        let guard = Arc::new(BudgetGuard::new(BudgetLimit::Dollars(1.0), BudgetPeriod::Lifetime));
+
+        let unpriced = BudgetedLLM::new(Arc::new(MeteredLLM), guard.clone());
+        let err = unpriced.generate("hello").await.unwrap_err();
+        assert_eq!(err.downcast_ref::<UnpricedModelError>(), Some(&UnpricedModelError { model: Some("metered".to_string()) }));
+        assert_eq!(err.to_string(), "Cannot enforce a dollar budget: no pricing for model 'metered'");
+
+        let unnamed = BudgetedLLM::new(Arc::new(UnnamedLLM), guard.clone());
+        let err = unnamed.generate("hello").await.unwrap_err();
+        assert_eq!(err.downcast_ref::<UnpricedModelError>(), Some(&UnpricedModelError { model: None }));
+        assert_eq!(guard.remaining(), 1.0);
+
+        // Token budgets need no prices.
+        let tokens = Arc::new(BudgetGuard::new(BudgetLimit::Tokens(1000), BudgetPeriod::Lifetime));
+        assert!(BudgetedLLM::new(Arc::new(UnnamedLLM), tokens).generate("hello").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_dollar_budget_resets_each_period() {
+        let pricing = PricingTable::new().with_model("metered", ModelPricing::new(1000.0, 1000.0));
+        let guard = Arc::new(
+            BudgetGuard::new(BudgetLimit::Dollars(1.5), BudgetPeriod::Every(Duration::from_millis(100)))
+                .with_pricing(pricing)
+                .with_expected_output_tokens(0),
+        );
+        let llm = BudgetedLLM::new(Arc::new(MeteredLLM), guard.clone());
+
+        // 1100 tokens at $1000 per million is $1.10 per call.
+        llm.generate("hello").await.unwrap();
+        assert!((guard.spent() - 1.1).abs() < 1e-9);
+        let refused = llm.generate(&"word ".repeat(500)).await.unwrap_err();
+        assert!(refused.to_string().starts_with("Budget exceeded: $1.1000 spent"));
+
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert_eq!(guard.spent(), 0.0);
+        llm.generate("hello").await.unwrap();
+    }
+}
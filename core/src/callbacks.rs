@@ -0,0 +1,640 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use anyhow::Result;
+use futures::stream::{self, BoxStream, StreamExt};
+use uuid::Uuid;
+use crate::llm::{Generation, LLM};
+use crate::schema::{ChatMessage, Document, PromptValue};
+
+pub type RunId = Uuid;
+
+/// Identifies one chain, LLM, tool or retriever run and the run it was started from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunInfo {
+    pub run_id: RunId,
+    pub parent_run_id: Option<RunId>,
+}
+
+/// Receives events from chains, LLM calls, tools and retrievers. Every method defaults to
+/// doing nothing, so handlers only implement what they need.
+///
+/// Handlers are called synchronously on the calling task and should return quickly.
+#[allow(unused_variables)]
+pub trait CallbackHandler: Send + Sync {
+    fn on_chain_start(&self, run: &RunInfo, name: &str, inputs: &HashMap<String, String>) {}
+    fn on_chain_end(&self, run: &RunInfo, outputs: &HashMap<String, String>) {}
+    fn on_chain_error(&self, run: &RunInfo, error: &anyhow::Error) {}
+
+    fn on_llm_start(&self, run: &RunInfo, prompt: &PromptValue) {}
+    /// A streamed chunk of the completion, reported by `trace_llm_stream`.
+    fn on_llm_new_token(&self, run: &RunInfo, token: &str) {}
+    fn on_llm_end(&self, run: &RunInfo, generation: &Generation) {}
+    fn on_llm_error(&self, run: &RunInfo, error: &anyhow::Error) {}
+
+    fn on_tool_start(&self, run: &RunInfo, tool: &str, input: &str) {}
+    fn on_tool_end(&self, run: &RunInfo, output: &str) {}
+    fn on_tool_error(&self, run: &RunInfo, error: &anyhow::Error) {}
+
+    fn on_retriever_start(&self, run: &RunInfo, query: &str) {}
+    fn on_retriever_end(&self, run: &RunInfo, documents: &[Document]) {}
+    fn on_retriever_error(&self, run: &RunInfo, error: &anyhow::Error) {}
+
+    /// A chain's cache lookup; `parent_run_id` is the chain's run.
+    fn on_cache_hit(&self, parent_run_id: Option<RunId>, key: &str) {}
+    fn on_cache_miss(&self, parent_run_id: Option<RunId>, key: &str) {}
+}
+
+static GLOBAL_HANDLERS: RwLock<Vec<Arc<dyn CallbackHandler>>> = RwLock::new(Vec::new());
+
+/// Registers a handler that receives events from every run in the process.
+pub fn add_global_handler(handler: Arc<dyn CallbackHandler>) {
+    GLOBAL_HANDLERS.write().unwrap().push(handler);
+}
+
+/// Unregisters `handler` (compared by pointer), leaving other global handlers in place.
+pub fn remove_global_handler(handler: &Arc<dyn CallbackHandler>) {
+    GLOBAL_HANDLERS.write().unwrap().retain(|h| !Arc::ptr_eq(h, handler));
+}
+
+pub fn clear_global_handlers() {
+    GLOBAL_HANDLERS.write().unwrap().clear();
+}
+
+tokio::task_local! {
+    static CURRENT: Callbacks;
+}
+
+/// Handlers for the runs within a `scope`, in addition to the global ones.
+///
+/// Scopes nest: each run executes its inner work in a child scope, so events of nested runs
+/// carry their parent's run id.
+#[derive(Clone, Default)]
+pub struct Callbacks {
+    handlers: Vec<Arc<dyn CallbackHandler>>,
+    parent_run_id: Option<RunId>,
+}
+
+impl Callbacks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_handler(mut self, handler: Arc<dyn CallbackHandler>) -> Self {
+        self.handlers.push(handler);
+        self
+    }
+
+    /// Callbacks of the enclosing scope, or none outside any scope.
+    pub fn current() -> Self {
+        CURRENT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// Runs `future` with these callbacks, e.g. to observe a single chain call:
+    /// `Callbacks::new().with_handler(handler).scope(chain.call(inputs)).await`.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    pub fn parent_run_id(&self) -> Option<RunId> {
+        self.parent_run_id
+    }
+
+    fn new_run(&self) -> RunInfo {
+        RunInfo { run_id: Uuid::new_v4(), parent_run_id: self.parent_run_id }
+    }
+
+    fn child(&self, run: &RunInfo) -> Self {
+        Self { handlers: self.handlers.clone(), parent_run_id: Some(run.run_id) }
+    }
+
+    /// Calls `f` on every global and scoped handler.
+    pub fn emit<F: Fn(&dyn CallbackHandler)>(&self, f: F) {
+        for handler in GLOBAL_HANDLERS.read().unwrap().iter() {
+            f(handler.as_ref());
+        }
+        for handler in &self.handlers {
+            f(handler.as_ref());
+        }
+    }
+}
+
+/// Runs `future` as a chain run named `name`, reporting `outputs(&result)` when it succeeds.
+pub async fn trace_chain<T, F, O>(name: &str, inputs: &HashMap<String, String>, outputs: O, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+    O: FnOnce(&T) -> HashMap<String, String>,
+{
+    let callbacks = Callbacks::current();
+    let run = callbacks.new_run();
+    callbacks.emit(|h| h.on_chain_start(&run, name, inputs));
+    let result = callbacks.child(&run).scope(future).await;
+    match &result {
+        Ok(value) => {
+            let outputs = outputs(value);
+            callbacks.emit(|h| h.on_chain_end(&run, &outputs));
+        }
+        Err(e) => callbacks.emit(|h| h.on_chain_error(&run, e)),
+    }
+    result
+}
+
+/// Calls `llm` with `prompt` as an LLM run. Text goes through `generate_with_usage`,
+/// messages through `generate_messages`. The generation's `model` defaults to `llm.model_name()`.
+/// `trace_llm_stream` is the streaming counterpart that also reports tokens.
+pub async fn trace_llm(llm: &dyn LLM, prompt: PromptValue) -> Result<Generation> {
+    let callbacks = Callbacks::current();
+    let run = callbacks.new_run();
+    callbacks.emit(|h| h.on_llm_start(&run, &prompt));
+    let result = callbacks.child(&run).scope(async {
+        match &prompt {
+            PromptValue::Text(text) => llm.generate_with_usage(text).await,
+            PromptValue::Messages(messages) => llm.generate_messages(messages).await,
+        }
+    }).await.map(|mut generation| {
+        generation.model = generation.model.or_else(|| llm.model_name());
+        generation
+    });
+    match &result {
+        Ok(generation) => callbacks.emit(|h| h.on_llm_end(&run, generation)),
+        Err(e) => callbacks.emit(|h| h.on_llm_error(&run, e)),
+    }
+    result
+}
+
+/// Streams `llm`'s response to `prompt` as an LLM run (see `LLM::stream_messages`), reporting
+/// each chunk with `on_llm_new_token` and the combined generation with `on_llm_end`. The run
+/// starts, in the callbacks scope it is polled from, when the stream is first polled.
+pub fn trace_llm_stream(llm: &dyn LLM, prompt: PromptValue) -> BoxStream<'_, Result<Generation>> {
+    struct Running<'a> {
+        chunks: BoxStream<'a, Result<Generation>>,
+        callbacks: Callbacks,
+        run: RunInfo,
+        combined: Generation,
+    }
+
+    enum State<'a> {
+        Pending(PromptValue),
+        Running(Running<'a>),
+        Finished,
+    }
+
+    stream::unfold(State::Pending(prompt), move |state| async move {
+        let mut running = match state {
+            State::Finished => return None,
+            State::Running(running) => running,
+            State::Pending(prompt) => {
+                let callbacks = Callbacks::current();
+                let run = callbacks.new_run();
+                callbacks.emit(|h| h.on_llm_start(&run, &prompt));
+                let messages = match prompt {
+                    PromptValue::Text(text) => vec![ChatMessage::human(&text)],
+                    PromptValue::Messages(messages) => messages,
+                };
+                let chunks = llm.stream_messages(messages);
+                Running { chunks, callbacks, run, combined: Generation::new(String::new()) }
+            }
+        };
+        match running.chunks.next().await {
+            Some(Ok(chunk)) => {
+                if !chunk.text.is_empty() {
+                    running.callbacks.emit(|h| h.on_llm_new_token(&running.run, &chunk.text));
+                }
+                let combined = &mut running.combined;
+                combined.text.push_str(&chunk.text);
+                combined.usage = chunk.usage.clone().or(combined.usage.take());
+                combined.model = chunk.model.clone().or(combined.model.take());
+                Some((Ok(chunk), State::Running(running)))
+            }
+            Some(Err(e)) => {
+                running.callbacks.emit(|h| h.on_llm_error(&running.run, &e));
+                Some((Err(e), State::Finished))
+            }
+            None => {
+                let mut combined = running.combined;
+                combined.model = combined.model.or_else(|| llm.model_name());
+                running.callbacks.emit(|h| h.on_llm_end(&running.run, &combined));
+                None
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Runs `future` as a run of the tool `name` on `input`.
+pub async fn trace_tool<F>(name: &str, input: &str, future: F) -> Result<String>
+where
+    F: Future<Output = Result<String>>,
+{
+    let callbacks = Callbacks::current();
+    let run = callbacks.new_run();
+    callbacks.emit(|h| h.on_tool_start(&run, name, input));
+    let result = callbacks.child(&run).scope(future).await;
+    match &result {
+        Ok(output) => callbacks.emit(|h| h.on_tool_end(&run, output)),
+        Err(e) => callbacks.emit(|h| h.on_tool_error(&run, e)),
+    }
+    result
+}
+
+/// Runs `future` as a retrieval for `query`.
+pub async fn trace_retriever<F>(query: &str, future: F) -> Result<Vec<Document>>
+where
+    F: Future<Output = Result<Vec<Document>>>,
+{
+    let callbacks = Callbacks::current();
+    let run = callbacks.new_run();
+    callbacks.emit(|h| h.on_retriever_start(&run, query));
+    let result = callbacks.child(&run).scope(future).await;
+    match &result {
+        Ok(documents) => callbacks.emit(|h| h.on_retriever_end(&run, documents)),
+        Err(e) => callbacks.emit(|h| h.on_retriever_error(&run, e)),
+    }
+    result
+}
+
+fn preview(text: &str) -> String {
+    const MAX_CHARS: usize = 200;
+    match text.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Prints a line per event to stdout, indented by nesting depth.
+#[derive(Default)]
+pub struct StdOutCallbackHandler {
+    depth: std::sync::Mutex<HashMap<RunId, usize>>,
+}
+
+impl StdOutCallbackHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn enter(&self, run: &RunInfo) -> String {
+        let mut depth = self.depth.lock().unwrap();
+        let level = run.parent_run_id.and_then(|p| depth.get(&p).copied()).map_or(0, |d| d + 1);
+        depth.insert(run.run_id, level);
+        "  ".repeat(level)
+    }
+
+    fn exit(&self, run: &RunInfo) -> String {
+        let level = self.depth.lock().unwrap().remove(&run.run_id).unwrap_or(0);
+        "  ".repeat(level)
+    }
+}
+
+impl CallbackHandler for StdOutCallbackHandler {
+    fn on_chain_start(&self, run: &RunInfo, name: &str, inputs: &HashMap<String, String>) {
+        let mut keys: Vec<&String> = inputs.keys().collect();
+        keys.sort();
+        println!("{}> Entering {} (inputs: {:?})", self.enter(run), name, keys);
+    }
+
+    fn on_chain_end(&self, run: &RunInfo, _outputs: &HashMap<String, String>) {
+        println!("{}< Finished chain", self.exit(run));
+    }
+
+    fn on_chain_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        println!("{}! Chain failed: {}", self.exit(run), error);
+    }
+
+    fn on_llm_start(&self, run: &RunInfo, prompt: &PromptValue) {
+        println!("{}> LLM prompt: {}", self.enter(run), preview(&prompt.to_string()));
+    }
+
+    fn on_llm_end(&self, run: &RunInfo, generation: &Generation) {
+        println!("{}< LLM output: {}", self.exit(run), preview(&generation.text));
+    }
+
+    fn on_llm_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        println!("{}! LLM failed: {}", self.exit(run), error);
+    }
+
+    fn on_tool_start(&self, run: &RunInfo, tool: &str, input: &str) {
+        println!("{}> Tool {}: {}", self.enter(run), tool, preview(input));
+    }
+
+    fn on_tool_end(&self, run: &RunInfo, output: &str) {
+        println!("{}< Tool output: {}", self.exit(run), preview(output));
+    }
+
+    fn on_tool_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        println!("{}! Tool failed: {}", self.exit(run), error);
+    }
+
+    fn on_retriever_start(&self, run: &RunInfo, query: &str) {
+        println!("{}> Retrieving: {}", self.enter(run), preview(query));
+    }
+
+    fn on_retriever_end(&self, run: &RunInfo, documents: &[Document]) {
+        println!("{}< Retrieved {} documents", self.exit(run), documents.len());
+    }
+
+    fn on_retriever_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        println!("{}! Retrieval failed: {}", self.exit(run), error);
+    }
+
+    fn on_cache_hit(&self, _parent_run_id: Option<RunId>, _key: &str) {
+        println!("  (cache hit)");
+    }
+}
+
+/// Forwards events to `tracing` under the `mini_langchain` target: starts and ends at
+/// `debug`, errors at `warn`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingCallbackHandler;
+
+impl CallbackHandler for TracingCallbackHandler {
+    fn on_chain_start(&self, run: &RunInfo, name: &str, _inputs: &HashMap<String, String>) {
+        tracing::debug!(target: "mini_langchain", run_id = %run.run_id, parent_run_id = ?run.parent_run_id, name, "chain start");
+    }
+
+    fn on_chain_end(&self, run: &RunInfo, _outputs: &HashMap<String, String>) {
+        tracing::debug!(target: "mini_langchain", run_id = %run.run_id, "chain end");
+    }
+
+    fn on_chain_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        tracing::warn!(target: "mini_langchain", run_id = %run.run_id, error = %error, "chain error");
+    }
+
+    fn on_llm_start(&self, run: &RunInfo, prompt: &PromptValue) {
+        tracing::debug!(target: "mini_langchain", run_id = %run.run_id, parent_run_id = ?run.parent_run_id, prompt = %prompt, "llm start");
+    }
+
+    fn on_llm_new_token(&self, run: &RunInfo, token: &str) {
+        tracing::trace!(target: "mini_langchain", run_id = %run.run_id, token, "llm token");
+    }
+
+    fn on_llm_end(&self, run: &RunInfo, generation: &Generation) {
+        let usage = generation.usage.as_ref();
+        tracing::debug!(
+            target: "mini_langchain",
+            run_id = %run.run_id,
+            prompt_tokens = usage.map(|u| u.prompt_tokens),
+            completion_tokens = usage.map(|u| u.completion_tokens),
+            "llm end"
+        );
+    }
+
+    fn on_llm_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        tracing::warn!(target: "mini_langchain", run_id = %run.run_id, error = %error, "llm error");
+    }
+
+    fn on_tool_start(&self, run: &RunInfo, tool: &str, input: &str) {
+        tracing::debug!(target: "mini_langchain", run_id = %run.run_id, parent_run_id = ?run.parent_run_id, tool, input, "tool start");
+    }
+
+    fn on_tool_end(&self, run: &RunInfo, output: &str) {
+        tracing::debug!(target: "mini_langchain", run_id = %run.run_id, output, "tool end");
+    }
+
+    fn on_tool_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        tracing::warn!(target: "mini_langchain", run_id = %run.run_id, error = %error, "tool error");
+    }
+
+    fn on_retriever_start(&self, run: &RunInfo, query: &str) {
+        tracing::debug!(target: "mini_langchain", run_id = %run.run_id, parent_run_id = ?run.parent_run_id, query, "retriever start");
+    }
+
+    fn on_retriever_end(&self, run: &RunInfo, documents: &[Document]) {
+        tracing::debug!(target: "mini_langchain", run_id = %run.run_id, documents = documents.len(), "retriever end");
+    }
+
+    fn on_retriever_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        tracing::warn!(target: "mini_langchain", run_id = %run.run_id, error = %error, "retriever error");
+    }
+
+    fn on_cache_hit(&self, parent_run_id: Option<RunId>, key: &str) {
+        tracing::debug!(target: "mini_langchain", parent_run_id = ?parent_run_id, key_len = key.len(), "cache hit");
+    }
+
+    fn on_cache_miss(&self, parent_run_id: Option<RunId>, key: &str) {
+        tracing::debug!(target: "mini_langchain", parent_run_id = ?parent_run_id, key_len = key.len(), "cache miss");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use async_trait::async_trait;
+    use crate::agent::{AgentExecutor, Tool};
+    use crate::cache::InMemoryCache;
+    use crate::chain::LLMChain;
+    use crate::prompt::PromptTemplate;
+    use crate::llm::TokenUsage;
+    use crate::runnable::{Runnable, RunnableExt};
+
+    /// `(event, run_id, parent_run_id)`
+    type Event = (String, Option<RunId>, Option<RunId>);
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        events: Mutex<Vec<Event>>,
+        generations: Mutex<Vec<Generation>>,
+    }
+
+    impl RecordingHandler {
+        fn record(&self, event: &str, run: Option<&RunInfo>, parent: Option<RunId>) {
+            let parent = run.map_or(parent, |r| r.parent_run_id);
+            self.events.lock().unwrap().push((event.to_string(), run.map(|r| r.run_id), parent));
+        }
+
+        fn names(&self) -> Vec<String> {
+            self.events.lock().unwrap().iter().map(|e| e.0.clone()).collect()
+        }
+    }
+
+    impl CallbackHandler for RecordingHandler {
+        fn on_chain_start(&self, run: &RunInfo, name: &str, _inputs: &HashMap<String, String>) {
+            self.record(&format!("chain_start {}", name), Some(run), None);
+        }
+        fn on_chain_end(&self, run: &RunInfo, _outputs: &HashMap<String, String>) {
+            self.record("chain_end", Some(run), None);
+        }
+        fn on_chain_error(&self, run: &RunInfo, _error: &anyhow::Error) {
+            self.record("chain_error", Some(run), None);
+        }
+        fn on_llm_start(&self, run: &RunInfo, _prompt: &PromptValue) {
+            self.record("llm_start", Some(run), None);
+        }
+        fn on_llm_new_token(&self, run: &RunInfo, token: &str) {
+            self.record(&format!("llm_token {}", token), Some(run), None);
+        }
+        fn on_llm_end(&self, run: &RunInfo, generation: &Generation) {
+            self.record("llm_end", Some(run), None);
+            self.generations.lock().unwrap().push(generation.clone());
+        }
+        fn on_llm_error(&self, run: &RunInfo, _error: &anyhow::Error) {
+            self.record("llm_error", Some(run), None);
+        }
+        fn on_tool_start(&self, run: &RunInfo, tool: &str, _input: &str) {
+            self.record(&format!("tool_start {}", tool), Some(run), None);
+        }
+        fn on_tool_end(&self, run: &RunInfo, _output: &str) {
+            self.record("tool_end", Some(run), None);
+        }
+        fn on_cache_hit(&self, parent_run_id: Option<RunId>, _key: &str) {
+            self.record("cache_hit", None, parent_run_id);
+        }
+        fn on_cache_miss(&self, parent_run_id: Option<RunId>, _key: &str) {
+            self.record("cache_miss", None, parent_run_id);
+        }
+    }
+
+    struct ScriptedLLM(&'static str);
+
+    #[async_trait]
+    impl LLM for ScriptedLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    /// Streams its reply in the given chunks, then usage; `None` fails mid-stream.
+    struct StreamingLLM(&'static [Option<&'static str>]);
+
+    #[async_trait]
+    impl LLM for StreamingLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            unreachable!("only streamed")
+        }
+
+        fn model_name(&self) -> Option<String> {
+            Some("streamer".to_string())
+        }
+
+        fn stream_messages(&self, _messages: Vec<ChatMessage>) -> BoxStream<'_, Result<Generation>> {
+            let usage = TokenUsage { prompt_tokens: 4, completion_tokens: 2, total_tokens: 6, ..Default::default() };
+            let chunks = self.0.iter().map(|chunk| match chunk {
+                Some(text) => Ok(Generation::new(text.to_string())),
+                None => Err(anyhow::anyhow!("connection reset")),
+            });
+            let last = Generation { text: String::new(), usage: Some(usage), model: None };
+            stream::iter(chunks.chain([Ok(last)])).boxed()
+        }
+    }
+
+    struct UpperTool;
+
+    #[async_trait]
+    impl Tool for UpperTool {
+        fn name(&self) -> String {
+            "upper".to_string()
+        }
+        fn description(&self) -> String {
+            "Uppercases the input".to_string()
+        }
+        async fn call(&self, input: &str) -> Result<String> {
+            Ok(input.to_uppercase())
+        }
+    }
+
+    fn chain() -> LLMChain {
+        LLMChain::new(PromptTemplate::from_template("Say {word}").unwrap(), Arc::new(ScriptedLLM("hi")))
+            .with_cache(Arc::new(InMemoryCache::new()))
+    }
+
+    #[tokio::test]
+    async fn test_chain_events_nest_under_the_chain_run() {
+        let handler = Arc::new(RecordingHandler::default());
+        let chain = chain();
+        let inputs = HashMap::from([("word".to_string(), "hi".to_string())]);
+        for _ in 0..2 {
+            let callbacks = Callbacks::new().with_handler(handler.clone());
+            chain.call_with_callbacks(inputs.clone(), callbacks).await.unwrap();
+        }
+
+        assert_eq!(
+            handler.names(),
+            ["chain_start LLMChain", "cache_miss", "llm_start", "llm_end", "chain_end",
+             "chain_start LLMChain", "cache_hit", "chain_end"]
+        );
+        let events = handler.events.lock().unwrap().clone();
+        let chain_run = events[0].1;
+        assert_eq!(events[0].2, None);
+        assert_eq!(events[1].2, chain_run);
+        assert_eq!(events[2].2, chain_run);
+        assert_eq!(events[2].1, events[3].1);
+        assert_eq!(events[4].1, chain_run);
+        assert_ne!(events[5].1, chain_run);
+
+        // Outside a scope the chain runs without the handler.
+        chain.call(inputs).await.unwrap();
+        assert_eq!(handler.names().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_runnable_llm_reports_events() {
+        let handler = Arc::new(RecordingHandler::default());
+        let llm: Arc<dyn LLM> = Arc::new(ScriptedLLM("hi"));
+        let pipeline = PromptTemplate::from_template("Say {word}").unwrap().pipe(llm);
+        let inputs = HashMap::from([("word".to_string(), "hi".to_string())]);
+
+        let output = Callbacks::new().with_handler(handler.clone()).scope(pipeline.invoke(inputs)).await.unwrap();
+        assert_eq!(output, "hi");
+        assert_eq!(handler.names(), ["llm_start", "llm_end"]);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_tokens_are_reported() {
+        let handler = Arc::new(RecordingHandler::default());
+        let llm: Arc<dyn LLM> = Arc::new(StreamingLLM(&[Some("Hel"), Some("lo")]));
+        let pipeline = PromptTemplate::from_template("Say {word}").unwrap().pipe(llm);
+        let inputs = HashMap::from([("word".to_string(), "hello".to_string())]);
+
+        let chunks: Vec<String> = Callbacks::new()
+            .with_handler(handler.clone())
+            .scope(pipeline.stream(inputs).map(Result::unwrap).collect())
+            .await;
+        assert_eq!(chunks, ["Hel", "lo"]);
+        assert_eq!(handler.names(), ["llm_start", "llm_token Hel", "llm_token lo", "llm_end"]);
+        let events = handler.events.lock().unwrap().clone();
+        assert!(events.iter().all(|e| e.1 == events[0].1));
+        let generation = handler.generations.lock().unwrap()[0].clone();
+        assert_eq!(generation.text, "Hello");
+        assert_eq!(generation.model.as_deref(), Some("streamer"));
+        assert_eq!(generation.usage.unwrap().total_tokens, 6);
+
+        let failing = Arc::new(RecordingHandler::default());
+        let broken = StreamingLLM(&[Some("Hel"), None, Some("lo")]);
+        let results: Vec<Result<Generation>> = Callbacks::new()
+            .with_handler(failing.clone())
+            .scope(trace_llm_stream(&broken, PromptValue::Text("hi".to_string())).collect())
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+        assert_eq!(failing.names(), ["llm_start", "llm_token Hel", "llm_error"]);
+    }
+
+    #[tokio::test]
+    async fn test_agent_tool_events_and_global_handler() {
+        let global = Arc::new(RecordingHandler::default());
+        let registered: Arc<dyn CallbackHandler> = global.clone();
+        add_global_handler(registered.clone());
+        let local = Arc::new(RecordingHandler::default());
+
+        let agent = AgentExecutor::new(Arc::new(ScriptedLLM("Action: upper\nInput: shout")))
+            .with_tool(Arc::new(UpperTool));
+        let output = Callbacks::new()
+            .with_handler(local.clone())
+            .scope(agent.execute("Make it loud"))
+            .await
+            .unwrap();
+        remove_global_handler(&registered);
+        assert!(!GLOBAL_HANDLERS.read().unwrap().iter().any(|h| Arc::ptr_eq(h, &registered)));
+        assert_eq!(output, "Tool Output: SHOUT");
+
+        assert_eq!(
+            local.names(),
+            ["chain_start AgentExecutor", "llm_start", "llm_end", "tool_start upper", "tool_end", "chain_end"]
+        );
+        let local_events = local.events.lock().unwrap();
+        assert_eq!(local_events[3].2, local_events[0].1);
+        // Other tests may run chains concurrently, so only check this run reached the global handler.
+        let global_events = global.events.lock().unwrap();
+        assert!(local_events.iter().all(|e| global_events.contains(e)));
+    }
+}
@@ -2,28 +2,89 @@ use std::collections::HashMap;
 use anyhow::Result;
 use crate::llm::LLM;
 use crate::prompt::PromptTemplate;
+use crate::chat_prompt::{ChatPromptTemplate, MessageTemplate};
+use crate::schema::{ChatMessage, PromptValue};
 use crate::cache::Cache;
 use crate::memory::Memory;
-use std::sync::Arc;
+use crate::minify::{minify, MinifyPolicy, MinifyStats};
+use crate::compression::{CompressionResult, PromptCompressor};
+use crate::batch::{run_batch, BatchOptions};
+use crate::budget::{BudgetGuard, BudgetedLLM};
+use crate::callbacks::{trace_chain, trace_llm, Callbacks};
+use crate::output_parser::{OutputParser, ParsedChain};
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+/// Prompt variable that receives format instructions when the prompt declares it.
+pub const FORMAT_INSTRUCTIONS_KEY: &str = "format_instructions";
+
+#[derive(Clone)]
+enum ChainPrompt {
+    Text(PromptTemplate),
+    Chat(ChatPromptTemplate),
+}
 
 #[derive(Clone)]
 pub struct LLMChain {
-    prompt: PromptTemplate,
+    prompt: ChainPrompt,
     llm: Arc<dyn LLM>,
     cache: Option<Arc<dyn Cache>>,
     memory: Option<Arc<dyn Memory>>,
+    minify_policy: MinifyPolicy,
+    minify_stats: Arc<Mutex<MinifyStats>>,
+    compressor: Option<PromptCompressor>,
+    last_compression: Arc<Mutex<Option<CompressionResult>>>,
+    format_instructions: Option<String>,
+    name: Option<String>,
 }
 
 impl LLMChain {
     pub fn new(prompt: PromptTemplate, llm: Arc<dyn LLM>) -> Self {
         Self {
-            prompt,
+            prompt: ChainPrompt::Text(prompt),
             llm,
             cache: None, // Default no cache
             memory: None, // Default no memory
+            minify_policy: MinifyPolicy::default(),
+            minify_stats: Arc::new(Mutex::new(MinifyStats::default())),
+            compressor: None,
+            last_compression: Arc::new(Mutex::new(None)),
+            format_instructions: None,
+            name: None,
         }
     }
 
+    /// Drives a chat model with role-tagged messages. Placeholders are filled from
+    /// `Memory::load_memory_messages`, so a `ConversationBufferMemory` keyed `history`
+    /// pairs with `MessagesPlaceholder::new("history")`.
+    pub fn from_chat_prompt(prompt: ChatPromptTemplate, llm: Arc<dyn LLM>) -> Self {
+        Self {
+            prompt: ChainPrompt::Chat(prompt),
+            llm,
+            cache: None,
+            memory: None,
+            minify_policy: MinifyPolicy::default(),
+            minify_stats: Arc::new(Mutex::new(MinifyStats::default())),
+            compressor: None,
+            last_compression: Arc::new(Mutex::new(None)),
+            format_instructions: None,
+            name: None,
+        }
+    }
+
+    /// Names the chain's runs in callbacks, traces and cost reports. Defaults to `LLMChain`.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Routes the chain's LLM calls through `guard`, refusing them with `BudgetExceededError`
+    /// once its budget is spent. Share the guard to give several chains one budget.
+    pub fn with_budget(mut self, guard: Arc<BudgetGuard>) -> Self {
+        self.llm = Arc::new(BudgetedLLM::new(self.llm, guard));
+        self
+    }
+
     pub fn with_cache(mut self, cache: Arc<dyn Cache>) -> Self {
         self.cache = Some(cache);
         self
@@ -34,49 +95,273 @@ impl LLMChain {
         self
     }
 
-    pub async fn call(&self, mut inputs: HashMap<String, String>) -> Result<String> {
+    /// Defaults to `MinifyPolicy::StructureAware`, which leaves fenced code and tables intact.
+    pub fn with_minify(mut self, policy: MinifyPolicy) -> Self {
+        self.minify_policy = policy;
+        self
+    }
+
+    /// Token counts before and after minification across every call so far.
+    pub fn minify_stats(&self) -> MinifyStats {
+        *self.minify_stats.lock().unwrap()
+    }
+
+    /// Compresses text prompts to the compressor's token budget after minification.
+    /// Chat prompts are not compressed, since their budget is spread over several messages.
+    pub fn with_compression(mut self, compressor: PromptCompressor) -> Self {
+        self.compressor = Some(compressor);
+        self
+    }
+
+    /// Result of the most recent compression, including its ratio.
+    ///
+    /// Shared by clones of the chain and written by every call, so with concurrent calls
+    /// (including `batch`) it holds whichever call compressed last. Call
+    /// `PromptCompressor::compress` directly for per-prompt results.
+    pub fn last_compression(&self) -> Option<CompressionResult> {
+        self.last_compression.lock().unwrap().clone()
+    }
+
+    /// Fills `{format_instructions}` in the prompt, or appends the instructions to the prompt
+    /// (the last message, for chat prompts) when it doesn't use that variable.
+    pub fn with_format_instructions(mut self, instructions: &str) -> Self {
+        self.format_instructions = Some(instructions.to_string());
+        self
+    }
+
+    /// Parses every completion with `parser`, injecting its format instructions into the prompt.
+    pub fn with_output_parser<P: OutputParser>(self, parser: P) -> ParsedChain<P> {
+        let chain = match parser.format_instructions() {
+            Some(instructions) => self.with_format_instructions(&instructions),
+            None => self,
+        };
+        ParsedChain::new(chain, parser)
+    }
+
+    pub fn llm(&self) -> &Arc<dyn LLM> {
+        &self.llm
+    }
+
+    /// Variables callers must supply: prompt variables not covered by partials, memory or
+    /// format instructions, in order of first appearance.
+    pub fn input_variables(&self) -> Vec<String> {
+        let mut supplied = self.memory.as_ref().map(|m| m.memory_variables()).unwrap_or_default();
+        if self.format_instructions.is_some() {
+            supplied.push(FORMAT_INSTRUCTIONS_KEY.to_string());
+        }
+        let mut names: Vec<String> = Vec::new();
+        for template in self.templates() {
+            for name in template.input_variables() {
+                if !template.partial_variables().contains_key(name)
+                    && !supplied.contains(name)
+                    && !names.contains(name)
+                {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    }
+
+    /// The string templates behind the prompt (one per chat message).
+    fn templates(&self) -> Vec<&PromptTemplate> {
+        match &self.prompt {
+            ChainPrompt::Text(prompt) => vec![prompt],
+            ChainPrompt::Chat(prompt) => prompt.messages().iter()
+                .filter_map(|m| match m {
+                    MessageTemplate::Message { prompt, .. } => Some(prompt),
+                    MessageTemplate::Placeholder(_) => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the format instructions to `inputs` when one of `templates` uses them.
+    /// Returns whether they still need to be appended to the formatted prompt.
+    fn instruction_values<'a>(
+        &self,
+        templates: &[&PromptTemplate],
+        inputs: &'a HashMap<String, String>,
+    ) -> (Cow<'a, HashMap<String, String>>, Option<&str>) {
+        let Some(instructions) = &self.format_instructions else {
+            return (Cow::Borrowed(inputs), None);
+        };
+        let declared = templates.iter().any(|t| t.input_variables().iter().any(|v| v == FORMAT_INSTRUCTIONS_KEY));
+        if !declared {
+            return (Cow::Borrowed(inputs), Some(instructions));
+        }
+        let mut values = inputs.clone();
+        values.entry(FORMAT_INSTRUCTIONS_KEY.to_string()).or_insert_with(|| instructions.clone());
+        (Cow::Owned(values), None)
+    }
+
+    fn minify(&self, text: &str) -> String {
+        if self.minify_policy == MinifyPolicy::Off {
+            return text.to_string();
+        }
+        let minified = minify(text, self.minify_policy);
+        self.minify_stats.lock().unwrap().record(text, &minified);
+        minified
+    }
+
+    pub async fn call(&self, inputs: HashMap<String, String>) -> Result<String> {
+        let traced_inputs = inputs.clone();
+        let outputs = |output: &String| HashMap::from([("output".to_string(), output.clone())]);
+        let name = self.name.as_deref().unwrap_or("LLMChain");
+        trace_chain(name, &traced_inputs, outputs, self.call_inner(inputs)).await
+    }
+
+    /// Calls the chain with `callbacks` receiving the events of this call in addition to the
+    /// global handlers.
+    pub async fn call_with_callbacks(&self, inputs: HashMap<String, String>, callbacks: Callbacks) -> Result<String> {
+        callbacks.scope(self.call(inputs)).await
+    }
+
+    async fn call_inner(&self, mut inputs: HashMap<String, String>) -> Result<String> {
+        let result = match &self.prompt {
+            ChainPrompt::Text(prompt) => self.call_text(prompt, &mut inputs).await?,
+            ChainPrompt::Chat(prompt) => self.call_chat(prompt, &mut inputs).await?,
+        };
+
+        // 6. Save Context to Memory
+        if let Some(memory) = &self.memory {
+            // Need output variables
+            let mut outputs = HashMap::new();
+            outputs.insert("output".to_string(), result.clone());
+            memory.save_context(&inputs, &outputs).await?;
+        }
+
+        Ok(result)
+    }
+
+    /// Calls the chain for every input map, `options.concurrency` at a time. Results are in
+    /// input order, with each item's error captured in place.
+    pub async fn batch(&self, inputs: Vec<HashMap<String, String>>, options: &BatchOptions) -> Result<Vec<Result<String>>> {
+        run_batch(inputs, options, |input| self.call(input)).await
+    }
+
+    async fn call_text(&self, prompt: &PromptTemplate, inputs: &mut HashMap<String, String>) -> Result<String> {
         // 0. Load Memory
         if let Some(memory) = &self.memory {
-            let mem_vars = memory.load_memory_variables(&inputs).await?;
+            let mem_vars = memory.load_memory_variables(inputs).await?;
             inputs.extend(mem_vars);
         }
 
         // 1. Format Prompt
-        let formatted = self.prompt.format(&inputs)?;
+        let (values, append) = self.instruction_values(&self.templates(), inputs);
+        let mut formatted = prompt.format(&values)?;
+        if let Some(instructions) = append {
+            formatted = format!("{}\n\n{}", formatted, instructions);
+        }
         
         // 2. Minify (Cost Saving!)
-        let minified = self.prompt.minify(&formatted);
+        let mut minified = self.minify(&formatted);
+
+        // 2b. Compress to the token budget
+        if let Some(compressor) = &self.compressor {
+            let compressed = compressor.compress(&minified);
+            minified = compressed.text.clone();
+            *self.last_compression.lock().unwrap() = Some(compressed);
+        }
 
         // 3. Check Cache
-        if let Some(cache) = &self.cache {
-            if let Some(cached_response) = cache.get(&minified).await {
-                // Determine mechanism to signal it was cached? 
-                // For now just return the string.
-                return Ok(cached_response);
-            }
+        if let Some(cached_response) = self.cache_lookup(&minified).await {
+            return Ok(cached_response);
         }
 
         // 4. Call LLM
-        let result = self.llm.generate(&minified).await?;
+        let result = trace_llm(self.llm.as_ref(), PromptValue::Text(minified.clone())).await?.text;
 
         // 5. Store in Cache
         if let Some(cache) = &self.cache {
             cache.set(&minified, &result).await;
         }
 
-        // 5. Store in Cache
-        if let Some(cache) = &self.cache {
-            cache.set(&minified, &result).await;
-        }
+        Ok(result)
+    }
 
-        // 6. Save Context to Memory
+    async fn call_chat(&self, prompt: &ChatPromptTemplate, inputs: &mut HashMap<String, String>) -> Result<String> {
+        let mut histories = HashMap::new();
         if let Some(memory) = &self.memory {
-            // Need output variables
-            let mut outputs = HashMap::new();
-            outputs.insert("output".to_string(), result.clone());
-            memory.save_context(&inputs, &outputs).await?;
+            histories = memory.load_memory_messages(inputs).await?;
+        }
+
+        let (values, append) = self.instruction_values(&self.templates(), inputs);
+        let mut messages = prompt.format_messages(&values, &histories)?;
+        if let (Some(instructions), Some(last)) = (append, messages.last_mut()) {
+            last.content = format!("{}\n\n{}", last.content, instructions);
+        }
+        let messages: Vec<ChatMessage> = messages
+            .into_iter()
+            .map(|m| ChatMessage { content: self.minify(&m.content), ..m })
+            .collect();
+        let cache_key = serde_json::to_string(&messages)?;
+
+        if let Some(cached_response) = self.cache_lookup(&cache_key).await {
+            return Ok(cached_response);
+        }
+
+        let result = trace_llm(self.llm.as_ref(), PromptValue::Messages(messages)).await?.text;
+
+        if let Some(cache) = &self.cache {
+            cache.set(&cache_key, &result).await;
         }
 
         Ok(result)
     }
+
+    async fn cache_lookup(&self, key: &str) -> Option<String> {
+        let cache = self.cache.as_ref()?;
+        let cached = cache.get(key).await;
+        let callbacks = Callbacks::current();
+        match cached {
+            Some(_) => callbacks.emit(|h| h.on_cache_hit(callbacks.parent_run_id(), key)),
+            None => callbacks.emit(|h| h.on_cache_miss(callbacks.parent_run_id(), key)),
+        }
+        cached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct EchoLLM;
+
+    #[async_trait]
+    impl LLM for EchoLLM {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            Ok(prompt.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_minify_policy_and_stats() {
+        let prompt = PromptTemplate::from_template("  Fix this:\n\n```python\ndef f():\n    {body}\n```\n").unwrap();
+        let inputs = HashMap::from([("body".to_string(), "return 1".to_string())]);
+
+        let chain = LLMChain::new(prompt.clone(), Arc::new(EchoLLM));
+        assert_eq!(chain.call(inputs.clone()).await.unwrap(), "Fix this:\n```python\ndef f():\n    return 1\n```");
+        assert_eq!(chain.minify_stats().calls, 1);
+
+        let off = LLMChain::new(prompt, Arc::new(EchoLLM)).with_minify(MinifyPolicy::Off);
+        assert_eq!(off.call(inputs).await.unwrap(), "  Fix this:\n\n```python\ndef f():\n    return 1\n```\n");
+        assert_eq!(off.minify_stats(), MinifyStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_compression_stage() {
+        let prompt = PromptTemplate::from_template("{context}\nQuestion: {question}").unwrap();
+        let chain = LLMChain::new(prompt, Arc::new(EchoLLM))
+            .with_compression(PromptCompressor::new(12));
+        let inputs = HashMap::from([
+            ("context".to_string(), "Paris is the capital.\nParis is the capital.\nParis is the capital.".to_string()),
+            ("question".to_string(), "Capital?".to_string()),
+        ]);
+
+        assert_eq!(chain.call(inputs).await.unwrap(), "Paris is the capital.\nQuestion: Capital?");
+        let compression = chain.last_compression().unwrap();
+        assert!(compression.ratio() < 0.6);
+    }
 }
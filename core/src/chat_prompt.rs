@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use anyhow::Result;
+use crate::prompt::{PartialValue, PromptError, PromptTemplate};
+use crate::schema::{ChatMessage, Role};
+use std::str::FromStr;
+
+/// Splices a list of messages (usually chat history from memory) into a chat prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessagesPlaceholder {
+    pub variable_name: String,
+    /// When true, a missing variable expands to no messages instead of an error.
+    pub optional: bool,
+}
+
+impl MessagesPlaceholder {
+    pub fn new(variable_name: &str) -> Self {
+        Self { variable_name: variable_name.to_string(), optional: false }
+    }
+
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+}
+
+/// One entry of a `ChatPromptTemplate`.
+#[derive(Debug, Clone)]
+pub enum MessageTemplate {
+    Message { role: Role, prompt: PromptTemplate },
+    Placeholder(MessagesPlaceholder),
+}
+
+impl MessageTemplate {
+    pub fn new(role: Role, template: &str) -> Result<Self> {
+        Ok(Self::Message { role, prompt: PromptTemplate::from_template(template)? })
+    }
+
+    pub fn system(template: &str) -> Result<Self> {
+        Self::new(Role::System, template)
+    }
+
+    pub fn human(template: &str) -> Result<Self> {
+        Self::new(Role::Human, template)
+    }
+
+    pub fn ai(template: &str) -> Result<Self> {
+        Self::new(Role::AI, template)
+    }
+
+    pub fn placeholder(variable_name: &str) -> Self {
+        Self::Placeholder(MessagesPlaceholder::new(variable_name))
+    }
+}
+
+impl From<MessagesPlaceholder> for MessageTemplate {
+    fn from(placeholder: MessagesPlaceholder) -> Self {
+        Self::Placeholder(placeholder)
+    }
+}
+
+/// A prompt that formats to a list of role-tagged messages rather than a single string.
+#[derive(Debug, Clone, Default)]
+pub struct ChatPromptTemplate {
+    messages: Vec<MessageTemplate>,
+}
+
+impl ChatPromptTemplate {
+    pub fn from_messages(messages: Vec<MessageTemplate>) -> Self {
+        Self { messages }
+    }
+
+    /// Builds a prompt from `(role, template)` pairs, as used by the Python and Node bindings.
+    /// The role `placeholder` takes a variable name instead of a template.
+    pub fn from_tuples(messages: &[(String, String)]) -> Result<Self> {
+        let messages = messages
+            .iter()
+            .map(|(role, template)| match role.as_str() {
+                "placeholder" => Ok(MessageTemplate::placeholder(template)),
+                role => MessageTemplate::new(Role::from_str(role)?, template),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::from_messages(messages))
+    }
+
+    pub fn with_message(mut self, message: MessageTemplate) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    /// Binds `name` to a fixed value in every message template that uses it.
+    pub fn with_partial(self, name: &str, value: &str) -> Result<Self> {
+        self.with_partial_value(name, PartialValue::Static(value.to_string()))
+    }
+
+    pub fn with_partial_value(mut self, name: &str, value: PartialValue) -> Result<Self> {
+        let mut used = false;
+        for message in &mut self.messages {
+            if let MessageTemplate::Message { prompt, .. } = message {
+                if prompt.input_variables().iter().any(|v| v == name) {
+                    *prompt = prompt.clone().with_partial_value(name, value.clone())?;
+                    used = true;
+                }
+            }
+        }
+        if !used {
+            return Err(PromptError::UnusedVariable(name.to_string()).into());
+        }
+        Ok(self)
+    }
+
+    pub fn messages(&self) -> &[MessageTemplate] {
+        &self.messages
+    }
+
+    /// Text variables used by the message templates, in order of first appearance.
+    pub fn input_variables(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for message in &self.messages {
+            if let MessageTemplate::Message { prompt, .. } = message {
+                for name in prompt.input_variables() {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /// Variables filled with message lists by `MessagesPlaceholder`s.
+    pub fn placeholder_variables(&self) -> Vec<String> {
+        self.messages
+            .iter()
+            .filter_map(|m| match m {
+                MessageTemplate::Placeholder(p) => Some(p.variable_name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Formats every template with `values` and expands placeholders from `histories`.
+    pub fn format_messages(
+        &self,
+        values: &HashMap<String, String>,
+        histories: &HashMap<String, Vec<ChatMessage>>,
+    ) -> Result<Vec<ChatMessage>> {
+        let mut result = Vec::new();
+        for message in &self.messages {
+            match message {
+                MessageTemplate::Message { role, prompt } => {
+                    result.push(ChatMessage::new(*role, prompt.format(values)?));
+                }
+                MessageTemplate::Placeholder(placeholder) => match histories.get(&placeholder.variable_name) {
+                    Some(history) => result.extend(history.iter().cloned()),
+                    None if placeholder.optional => {}
+                    None => return Err(PromptError::MissingVariable(placeholder.variable_name.clone()).into()),
+                },
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_messages_with_history() {
+        let prompt = ChatPromptTemplate::from_messages(vec![
+            MessageTemplate::system("You are a {persona}.").unwrap(),
+            MessageTemplate::placeholder("history"),
+            MessageTemplate::human("{input}").unwrap(),
+        ]);
+        assert_eq!(prompt.input_variables(), ["persona", "input"]);
+        assert_eq!(prompt.placeholder_variables(), ["history"]);
+
+        let values = HashMap::from([
+            ("persona".to_string(), "pirate".to_string()),
+            ("input".to_string(), "Where is the gold?".to_string()),
+        ]);
+        let histories = HashMap::from([(
+            "history".to_string(),
+            vec![ChatMessage::human("Ahoy"), ChatMessage::ai("Arr")],
+        )]);
+
+        let messages = prompt.format_messages(&values, &histories).unwrap();
+        assert_eq!(messages, vec![
+            ChatMessage::system("You are a pirate."),
+            ChatMessage::human("Ahoy"),
+            ChatMessage::ai("Arr"),
+            ChatMessage::human("Where is the gold?"),
+        ]);
+    }
+
+    #[test]
+    fn test_from_tuples() {
+        let prompt = ChatPromptTemplate::from_tuples(&[
+            ("system".to_string(), "Be {tone}.".to_string()),
+            ("placeholder".to_string(), "history".to_string()),
+            ("user".to_string(), "{input}".to_string()),
+        ]).unwrap();
+        assert_eq!(prompt.input_variables(), ["tone", "input"]);
+        assert_eq!(prompt.placeholder_variables(), ["history"]);
+        assert!(ChatPromptTemplate::from_tuples(&[("robot".to_string(), "hi".to_string())]).is_err());
+
+        let prompt = prompt.with_partial("tone", "kind").unwrap();
+        assert_eq!(prompt.input_variables(), ["input"]);
+        assert!(prompt.with_partial("tone", "again").is_err());
+    }
+
+    #[test]
+    fn test_missing_placeholder() {
+        let required = ChatPromptTemplate::default()
+            .with_message(MessageTemplate::placeholder("history"))
+            .with_message(MessageTemplate::human("hi").unwrap());
+        let err = required.format_messages(&HashMap::new(), &HashMap::new()).unwrap_err();
+        assert_eq!(err.downcast_ref::<PromptError>(), Some(&PromptError::MissingVariable("history".to_string())));
+
+        let optional = ChatPromptTemplate::default()
+            .with_message(MessagesPlaceholder::new("history").optional(true).into())
+            .with_message(MessageTemplate::human("hi").unwrap());
+        let messages = optional.format_messages(&HashMap::new(), &HashMap::new()).unwrap();
+        assert_eq!(messages, vec![ChatMessage::human("hi")]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use crate::token::TokenCounter;
+
+/// Common English words that carry little meaning on their own.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "the", "and", "or", "but", "so", "of", "to", "in", "on", "at", "by", "for", "with",
+    "from", "as", "is", "are", "was", "were", "be", "been", "being", "am", "it", "its", "this",
+    "that", "these", "those", "there", "here", "then", "than", "very", "really", "just", "quite",
+    "also", "too", "some", "any", "such", "into", "onto", "about", "which", "who", "whom", "do",
+    "does", "did", "has", "have", "had", "will", "would", "shall", "should", "can", "could", "may",
+    "might", "must", "basically", "actually", "simply",
+];
+
+/// A compression step, in the order they are tried (least lossy first).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionStage {
+    /// Drop lines that repeat an earlier line (ignoring case and surrounding whitespace).
+    DedupeLines,
+    /// Drop sentences whose words mostly repeat an earlier sentence.
+    RedundantSentences,
+    /// Remove stopwords outside fenced code blocks.
+    Stopwords,
+    /// Cut tokens from the middle, keeping the start and end of the text.
+    TruncateMiddle,
+}
+
+/// Outcome of `PromptCompressor::compress`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompressionResult {
+    pub text: String,
+    pub original_tokens: usize,
+    pub compressed_tokens: usize,
+    /// Stages that actually ran; compression stops as soon as the budget is met.
+    pub stages: Vec<CompressionStage>,
+}
+
+impl CompressionResult {
+    /// Compressed size relative to the original (1.0 means unchanged).
+    pub fn ratio(&self) -> f64 {
+        if self.original_tokens == 0 {
+            1.0
+        } else {
+            self.compressed_tokens as f64 / self.original_tokens as f64
+        }
+    }
+}
+
+/// Shrinks text towards `target_tokens` (as counted by `TokenCounter`) by applying
+/// increasingly lossy stages until the text fits.
+#[derive(Debug, Clone)]
+pub struct PromptCompressor {
+    target_tokens: usize,
+    stages: Vec<CompressionStage>,
+    similarity_threshold: f32,
+}
+
+impl PromptCompressor {
+    /// Uses every stage, in `CompressionStage` order.
+    pub fn new(target_tokens: usize) -> Self {
+        Self {
+            target_tokens,
+            stages: vec![
+                CompressionStage::DedupeLines,
+                CompressionStage::RedundantSentences,
+                CompressionStage::Stopwords,
+                CompressionStage::TruncateMiddle,
+            ],
+            similarity_threshold: 0.8,
+        }
+    }
+
+    /// Restricts compression to these stages (applied in the given order).
+    pub fn with_stages(mut self, stages: Vec<CompressionStage>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// Word-overlap (Jaccard) above which a sentence counts as redundant. Defaults to 0.8.
+    pub fn with_similarity_threshold(mut self, threshold: f32) -> Self {
+        self.similarity_threshold = threshold;
+        self
+    }
+
+    pub fn target_tokens(&self) -> usize {
+        self.target_tokens
+    }
+
+    pub fn compress(&self, text: &str) -> CompressionResult {
+        let original_tokens = TokenCounter::count(text);
+        let mut current = text.to_string();
+        let mut tokens = original_tokens;
+        let mut applied = Vec::new();
+
+        for stage in &self.stages {
+            if tokens <= self.target_tokens {
+                break;
+            }
+            let next = match stage {
+                CompressionStage::DedupeLines => dedupe_lines(&current),
+                CompressionStage::RedundantSentences => drop_redundant_sentences(&current, self.similarity_threshold),
+                CompressionStage::Stopwords => remove_stopwords(&current),
+                CompressionStage::TruncateMiddle => truncate_middle(&current, self.target_tokens),
+            };
+            applied.push(*stage);
+            current = next;
+            tokens = TokenCounter::count(&current);
+        }
+
+        CompressionResult {
+            text: current,
+            original_tokens,
+            compressed_tokens: tokens,
+            stages: applied,
+        }
+    }
+}
+
+/// Applies `f` to every line outside fenced code blocks; fenced lines are passed through.
+fn map_prose_lines<F: FnMut(&str) -> Option<String>>(text: &str, mut f: F) -> String {
+    let mut out = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let fence = line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~");
+        if in_fence || fence {
+            out.push(line.to_string());
+            in_fence ^= fence;
+            continue;
+        }
+        out.extend(f(line));
+    }
+    out.join("\n")
+}
+
+fn dedupe_lines(text: &str) -> String {
+    let mut seen = HashSet::new();
+    map_prose_lines(text, |line| {
+        let key = line.trim().to_lowercase();
+        if key.is_empty() || seen.insert(key) { Some(line.to_string()) } else { None }
+    })
+}
+
+fn words(sentence: &str) -> HashSet<String> {
+    sentence
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Splits after `.`, `!` or `?` followed by whitespace, keeping the punctuation.
+fn sentences(line: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let bytes = line.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        if matches!(b, b'.' | b'!' | b'?') && bytes.get(i + 1).is_some_and(u8::is_ascii_whitespace) {
+            result.push(line[start..=i].trim());
+            start = i + 1;
+        }
+    }
+    if start < line.len() && !line[start..].trim().is_empty() {
+        result.push(line[start..].trim());
+    }
+    result
+}
+
+fn drop_redundant_sentences(text: &str, threshold: f32) -> String {
+    let mut kept: Vec<HashSet<String>> = Vec::new();
+    map_prose_lines(text, |line| {
+        if line.trim().is_empty() {
+            return Some(String::new());
+        }
+        let survivors: Vec<&str> = sentences(line)
+            .into_iter()
+            .filter(|sentence| {
+                let current = words(sentence);
+                if current.is_empty() {
+                    return true;
+                }
+                let redundant = kept.iter().any(|previous| {
+                    let overlap = current.intersection(previous).count() as f32;
+                    let union = current.union(previous).count() as f32;
+                    overlap / union >= threshold
+                });
+                if !redundant {
+                    kept.push(current);
+                }
+                !redundant
+            })
+            .collect();
+        (!survivors.is_empty()).then(|| survivors.join(" "))
+    })
+}
+
+fn remove_stopwords(text: &str) -> String {
+    map_prose_lines(text, |line| {
+        let filtered: Vec<&str> = line
+            .split_whitespace()
+            .filter(|w| {
+                let bare = w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+                // Keep words carrying punctuation so sentence boundaries survive.
+                bare.is_empty() || !STOPWORDS.contains(&bare.as_str()) || bare.len() != w.len()
+            })
+            .collect();
+        Some(filtered.join(" "))
+    })
+}
+
+fn truncate_middle(text: &str, target_tokens: usize) -> String {
+    let tokens = TokenCounter::encode(text);
+    let marker = |omitted: usize| format!("\n[... {} tokens omitted ...]\n", omitted);
+    let budget = target_tokens.saturating_sub(TokenCounter::count(&marker(tokens.len())));
+    if tokens.len() <= target_tokens || budget == 0 {
+        return text.to_string();
+    }
+
+    let mut head = budget / 2;
+    let mut tail = budget - head;
+    // Token boundaries can split multi-byte characters; shrink until both halves decode.
+    loop {
+        let decoded = (
+            TokenCounter::decode(&tokens[..head]),
+            TokenCounter::decode(&tokens[tokens.len() - tail..]),
+        );
+        match decoded {
+            (Ok(start), Ok(end)) => {
+                return format!("{}{}{}", start.trim_end(), marker(tokens.len() - head - tail), end.trim_start());
+            }
+            (Err(_), _) if head > 0 => head -= 1,
+            (_, Err(_)) if tail > 0 => tail -= 1,
+            _ => return text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stops_once_within_budget() {
+        let text = "Revenue grew 10%.\nRevenue grew 10%.\nCosts fell.";
+        let generous = PromptCompressor::new(1000).compress(text);
+        assert_eq!(generous.text, text);
+        assert!(generous.stages.is_empty());
+        assert_eq!(generous.ratio(), 1.0);
+
+        let tight = PromptCompressor::new(TokenCounter::count("Revenue grew 10%.\nCosts fell.")).compress(text);
+        assert_eq!(tight.text, "Revenue grew 10%.\nCosts fell.");
+        assert_eq!(tight.stages, [CompressionStage::DedupeLines]);
+        assert!(tight.ratio() < 1.0);
+    }
+
+    #[test]
+    fn test_lossy_stages_preserve_code() {
+        let text = "The cat sat on the mat. A cat sat on the mat! The dog is in the house.\n```\nfor the in a: pass\n```";
+
+        let result = PromptCompressor::new(1)
+            .with_stages(vec![CompressionStage::RedundantSentences, CompressionStage::Stopwords])
+            .compress(text);
+        assert_eq!(result.text, "cat sat mat. dog house.\n```\nfor the in a: pass\n```");
+    }
+
+    #[test]
+    fn test_truncate_middle_fits_budget() {
+        let text = (0..200).map(|i| format!("Line number {} of the report.", i)).collect::<Vec<_>>().join("\n");
+        let result = PromptCompressor::new(120).with_stages(vec![CompressionStage::TruncateMiddle]).compress(&text);
+        assert!(result.compressed_tokens <= 120, "{}", result.compressed_tokens);
+        assert!(result.text.starts_with("Line number 0"));
+        assert!(result.text.ends_with("Line number 199 of the report."));
+        assert!(result.text.contains("tokens omitted"));
+    }
+}
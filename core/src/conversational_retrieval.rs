@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use anyhow::Result;
+use crate::callbacks::{trace_chain, trace_llm};
+use crate::llm::LLM;
+use crate::memory::Memory;
+use crate::prompt::PromptTemplate;
+use crate::retrieval_qa::{result_outputs, QAResult, RetrievalQA};
+use crate::runnable::Runnable;
+use crate::schema::PromptValue;
+
+const CONDENSE_TEMPLATE: &str = "Given the following conversation and a follow up question, \
+rephrase the follow up question to be a standalone question, in its original language.\n\n\
+Chat History:\n{chat_history}\nFollow Up Input: {question}\nStandalone question:";
+
+/// Chat over documents: follow-up questions are rewritten into standalone questions using
+/// the conversation in `Memory`, answered by a `RetrievalQA`, and the turn is saved back.
+#[derive(Clone)]
+pub struct ConversationalRetrievalChain {
+    llm: Arc<dyn LLM>,
+    qa: RetrievalQA,
+    memory: Arc<dyn Memory>,
+    condense_prompt: PromptTemplate,
+}
+
+impl ConversationalRetrievalChain {
+    /// `llm` condenses questions; `qa` retrieves and answers.
+    pub fn new(llm: Arc<dyn LLM>, qa: RetrievalQA, memory: Arc<dyn Memory>) -> Self {
+        Self {
+            llm,
+            qa,
+            memory,
+            condense_prompt: PromptTemplate::from_template(CONDENSE_TEMPLATE).unwrap(),
+        }
+    }
+
+    /// Prompt receiving `{chat_history}` and `{question}` and producing the standalone question.
+    pub fn with_condense_prompt(mut self, prompt: PromptTemplate) -> Self {
+        self.condense_prompt = prompt;
+        self
+    }
+
+    /// Rewrites `question` using the conversation so far. Without history it is returned as is.
+    pub async fn condense_question(&self, question: &str) -> Result<String> {
+        let inputs = HashMap::from([("input".to_string(), question.to_string())]);
+        let mut variables = self.memory.load_memory_variables(&inputs).await?;
+        let history = self.memory
+            .memory_variables()
+            .first()
+            .and_then(|key| variables.remove(key))
+            .unwrap_or_default();
+        if history.trim().is_empty() {
+            return Ok(question.to_string());
+        }
+
+        let values = HashMap::from([
+            ("chat_history".to_string(), history),
+            ("question".to_string(), question.to_string()),
+        ]);
+        let prompt = PromptValue::Text(self.condense_prompt.format(&values)?);
+        let standalone = trace_llm(self.llm.as_ref(), prompt).await?.text;
+        Ok(standalone.trim().to_string())
+    }
+
+    pub async fn call(&self, question: &str) -> Result<QAResult> {
+        let inputs = HashMap::from([("question".to_string(), question.to_string())]);
+        trace_chain("ConversationalRetrievalChain", &inputs, result_outputs, self.call_inner(question)).await
+    }
+
+    async fn call_inner(&self, question: &str) -> Result<QAResult> {
+        let standalone = self.condense_question(question).await?;
+        let result = self.qa.call(&standalone).await?;
+
+        self.memory
+            .save_context(
+                &HashMap::from([("input".to_string(), question.to_string())]),
+                &HashMap::from([("output".to_string(), result.answer.clone())]),
+            )
+            .await?;
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl Runnable for ConversationalRetrievalChain {
+    type Input = String;
+    type Output = QAResult;
+
+    async fn invoke(&self, question: String) -> Result<QAResult> {
+        self.call(&question).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::embedding::MockEmbeddings;
+    use crate::memory::ConversationBufferMemory;
+    use crate::schema::Document;
+    use crate::vectorstore::{InMemoryVectorStore, VectorStore};
+
+    /// Rewrites condense prompts to a fixed question and answers QA prompts with their question line.
+    #[derive(Default)]
+    struct ScriptedLLM {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LLM for ScriptedLLM {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            if prompt.starts_with("History:") {
+                return Ok(" What license does Rust use? ".to_string());
+            }
+            Ok(format!("answer to '{}'", prompt.lines().last().unwrap()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_is_condensed_and_saved() {
+        let store = Arc::new(InMemoryVectorStore::new(Arc::new(MockEmbeddings)));
+        store.add_documents(&[Document::new("Rust is MIT/Apache-2.0.".to_string())]).await.unwrap();
+
+        let llm = Arc::new(ScriptedLLM::default());
+        let memory = Arc::new(ConversationBufferMemory::new());
+        let qa = RetrievalQA::new(llm.clone(), store)
+            .with_prompt(PromptTemplate::from_template("{context}\n{question}").unwrap());
+        let chain = ConversationalRetrievalChain::new(llm.clone(), qa, memory.clone())
+            .with_condense_prompt(PromptTemplate::from_template("History:\n{chat_history}\nFollow up: {question}").unwrap());
+
+        // No history yet, so the question goes straight to retrieval.
+        let first = chain.call("Tell me about Rust").await.unwrap();
+        assert_eq!(first.answer, "answer to 'Tell me about Rust'");
+        assert_eq!(llm.prompts.lock().unwrap().len(), 1);
+
+        let second = chain.call("What license does it use?").await.unwrap();
+        assert_eq!(second.answer, "answer to 'What license does Rust use?'");
+        assert_eq!(second.source_documents.len(), 1);
+        assert_eq!(
+            llm.prompts.lock().unwrap()[1],
+            "History:\nHuman: Tell me about Rust\nAI: answer to 'Tell me about Rust'\nFollow up: What license does it use?"
+        );
+
+        let history = memory.load_memory_variables(&HashMap::new()).await.unwrap();
+        assert!(history["history"].ends_with("Human: What license does it use?\nAI: answer to 'What license does Rust use?'"));
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use crate::callbacks::{CallbackHandler, RunId, RunInfo};
+use crate::llm::{Generation, TokenUsage};
+
+/// USD prices per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    /// Rate for prompt tokens served from the provider's prompt cache. Defaults to the input rate.
+    pub cached_input_per_million: Option<f64>,
+}
+
+impl ModelPricing {
+    pub fn new(input_per_million: f64, output_per_million: f64) -> Self {
+        Self { input_per_million, output_per_million, cached_input_per_million: None }
+    }
+
+    pub fn with_cached_input(mut self, cached_input_per_million: f64) -> Self {
+        self.cached_input_per_million = Some(cached_input_per_million);
+        self
+    }
+
+    /// Cost in USD of one call's usage.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached_tokens.unwrap_or(0).min(usage.prompt_tokens);
+        let uncached = usage.prompt_tokens - cached;
+        let cached_rate = self.cached_input_per_million.unwrap_or(self.input_per_million);
+        (uncached as f64 * self.input_per_million
+            + cached as f64 * cached_rate
+            + usage.completion_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// List prices of common hosted models as `(model, input, output, cached input)` per million tokens.
+const BUILTIN_PRICES: &[(&str, f64, f64, Option<f64>)] = &[
+    ("gpt-5", 1.25, 10.0, Some(0.125)),
+    ("gpt-5-mini", 0.25, 2.0, Some(0.025)),
+    ("gpt-5-nano", 0.05, 0.40, Some(0.005)),
+    ("gpt-4.1", 2.0, 8.0, Some(0.50)),
+    ("gpt-4.1-mini", 0.40, 1.60, Some(0.10)),
+    ("gpt-4.1-nano", 0.10, 0.40, Some(0.025)),
+    ("gpt-4o", 2.50, 10.0, Some(1.25)),
+    ("gpt-4o-mini", 0.15, 0.60, Some(0.075)),
+    ("gpt-4-turbo", 10.0, 30.0, None),
+    ("gpt-4", 30.0, 60.0, None),
+    ("gpt-3.5-turbo", 0.50, 1.50, None),
+    ("o1", 15.0, 60.0, Some(7.50)),
+    ("o1-mini", 1.10, 4.40, Some(0.55)),
+    ("o3", 2.0, 8.0, Some(0.50)),
+    ("o3-mini", 1.10, 4.40, Some(0.55)),
+    ("o4-mini", 1.10, 4.40, Some(0.275)),
+    ("claude-opus-4", 15.0, 75.0, Some(1.50)),
+    ("claude-sonnet-4", 3.0, 15.0, Some(0.30)),
+    ("claude-3-7-sonnet", 3.0, 15.0, Some(0.30)),
+    ("claude-3-5-sonnet", 3.0, 15.0, Some(0.30)),
+    ("claude-3-5-haiku", 0.80, 4.0, Some(0.08)),
+    ("claude-3-opus", 15.0, 75.0, Some(1.50)),
+    ("claude-3-haiku", 0.25, 1.25, Some(0.03)),
+    ("gemini-2.5-pro", 1.25, 10.0, Some(0.31)),
+    ("gemini-2.5-flash", 0.30, 2.50, Some(0.075)),
+    ("gemini-2.0-flash", 0.10, 0.40, Some(0.025)),
+    ("gemini-1.5-pro", 1.25, 5.0, None),
+    ("gemini-1.5-flash", 0.075, 0.30, None),
+];
+
+/// Prices by model name. Lookups ignore case and a `provider/` prefix, and fall back to
+/// the longest known prefix, so dated snapshots such as `gpt-4o-2024-08-06` use the
+/// `gpt-4o` price.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PricingTable {
+    models: HashMap<String, ModelPricing>,
+}
+
+impl PricingTable {
+    /// An empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Published list prices of common OpenAI, Anthropic and Google models. Prices change;
+    /// override them with `with_model`.
+    pub fn builtin() -> Self {
+        let mut table = Self::new();
+        for &(model, input, output, cached) in BUILTIN_PRICES {
+            let pricing = ModelPricing { input_per_million: input, output_per_million: output, cached_input_per_million: cached };
+            table = table.with_model(model, pricing);
+        }
+        table
+    }
+
+    pub fn with_model(mut self, model: &str, pricing: ModelPricing) -> Self {
+        self.set(model, pricing);
+        self
+    }
+
+    pub fn set(&mut self, model: &str, pricing: ModelPricing) {
+        self.models.insert(model.to_lowercase(), pricing);
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelPricing> {
+        let model = model.to_lowercase();
+        let model = model.rsplit('/').next().unwrap_or(&model);
+        self.models.get(model).or_else(|| {
+            self.models
+                .iter()
+                .filter(|(known, _)| model.starts_with(known.as_str()))
+                .max_by_key(|(known, _)| known.len())
+                .map(|(_, pricing)| pricing)
+        })
+    }
+
+    /// Cost of `usage` on `model`, or `None` when the model has no price.
+    pub fn cost(&self, model: &str, usage: &TokenUsage) -> Option<f64> {
+        self.get(model).map(|pricing| pricing.cost(usage))
+    }
+}
+
+/// One priced LLM call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub model: Option<String>,
+    /// Name of the chain that made the call.
+    pub chain: Option<String>,
+    pub session: Option<String>,
+    pub tags: Vec<String>,
+    pub usage: TokenUsage,
+    /// `None` when the model is unknown or has no price.
+    pub cost: Option<f64>,
+}
+
+/// Accumulated usage of a group of ledger entries.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostSummary {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cached_tokens: u64,
+    /// USD across the priced calls.
+    pub cost: f64,
+    /// Calls whose model had no price; their tokens are counted but not their cost.
+    pub unpriced_calls: u64,
+}
+
+impl CostSummary {
+    fn add(&mut self, entry: &LedgerEntry) {
+        self.calls += 1;
+        self.prompt_tokens += entry.usage.prompt_tokens as u64;
+        self.completion_tokens += entry.usage.completion_tokens as u64;
+        self.cached_tokens += entry.usage.cached_tokens.unwrap_or(0) as u64;
+        match entry.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced_calls += 1,
+        }
+    }
+}
+
+/// Records the actual usage of LLM calls and reports it per model, chain, session and tag.
+///
+/// Calls are recorded through a `LedgerHandler` registered as a callback handler, or
+/// directly with `record`. Calls whose provider reports no usage are not recorded.
+pub struct CostLedger {
+    pricing: Mutex<PricingTable>,
+    entries: Mutex<Vec<LedgerEntry>>,
+}
+
+impl Default for CostLedger {
+    fn default() -> Self {
+        Self::with_pricing(PricingTable::builtin())
+    }
+}
+
+impl CostLedger {
+    /// A ledger priced with `PricingTable::builtin`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_pricing(pricing: PricingTable) -> Self {
+        Self { pricing: Mutex::new(pricing), entries: Mutex::new(Vec::new()) }
+    }
+
+    /// Sets or overrides a model's price for calls recorded from now on.
+    pub fn set_price(&self, model: &str, pricing: ModelPricing) {
+        self.pricing.lock().unwrap().set(model, pricing);
+    }
+
+    pub fn pricing(&self) -> PricingTable {
+        self.pricing.lock().unwrap().clone()
+    }
+
+    /// A callback handler recording into this ledger, labelling calls with the calling
+    /// chain and the handler's session and tags.
+    pub fn handler(self: &Arc<Self>) -> LedgerHandler {
+        LedgerHandler { ledger: self.clone(), session: None, tags: Vec::new(), chains: Mutex::new(HashMap::new()) }
+    }
+
+    /// Prices and records one call, returning the entry.
+    pub fn record(
+        &self,
+        model: Option<&str>,
+        usage: TokenUsage,
+        chain: Option<&str>,
+        session: Option<&str>,
+        tags: &[String],
+    ) -> LedgerEntry {
+        let cost = model.and_then(|m| self.pricing.lock().unwrap().cost(m, &usage));
+        let entry = LedgerEntry {
+            model: model.map(str::to_string),
+            chain: chain.map(str::to_string),
+            session: session.map(str::to_string),
+            tags: tags.to_vec(),
+            usage,
+            cost,
+        };
+        self.entries.lock().unwrap().push(entry.clone());
+        entry
+    }
+
+    pub fn entries(&self) -> Vec<LedgerEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn total(&self) -> CostSummary {
+        let mut summary = CostSummary::default();
+        for entry in self.entries.lock().unwrap().iter() {
+            summary.add(entry);
+        }
+        summary
+    }
+
+    /// Summaries keyed by model; calls without a model are under `"unknown"`.
+    pub fn by_model(&self) -> BTreeMap<String, CostSummary> {
+        self.group_by(|e| vec![e.model.clone().unwrap_or_else(|| "unknown".to_string())])
+    }
+
+    /// Summaries keyed by chain name; calls made outside a chain are left out.
+    pub fn by_chain(&self) -> BTreeMap<String, CostSummary> {
+        self.group_by(|e| e.chain.iter().cloned().collect())
+    }
+
+    /// Summaries keyed by session; calls without a session are left out.
+    pub fn by_session(&self) -> BTreeMap<String, CostSummary> {
+        self.group_by(|e| e.session.iter().cloned().collect())
+    }
+
+    /// Summaries keyed by tag. A call with several tags counts towards each of them.
+    pub fn by_tag(&self) -> BTreeMap<String, CostSummary> {
+        self.group_by(|e| e.tags.clone())
+    }
+
+    fn group_by<F: Fn(&LedgerEntry) -> Vec<String>>(&self, keys: F) -> BTreeMap<String, CostSummary> {
+        let mut groups: BTreeMap<String, CostSummary> = BTreeMap::new();
+        for entry in self.entries.lock().unwrap().iter() {
+            for key in keys(entry) {
+                groups.entry(key).or_default().add(entry);
+            }
+        }
+        groups
+    }
+}
+
+/// Callback handler feeding a `CostLedger`. Created with `CostLedger::handler`.
+pub struct LedgerHandler {
+    ledger: Arc<CostLedger>,
+    session: Option<String>,
+    tags: Vec<String>,
+    /// Names of chain runs in progress, to label the LLM calls they make.
+    chains: Mutex<HashMap<RunId, String>>,
+}
+
+impl LedgerHandler {
+    pub fn with_session(mut self, session: &str) -> Self {
+        self.session = Some(session.to_string());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+}
+
+impl CallbackHandler for LedgerHandler {
+    fn on_chain_start(&self, run: &RunInfo, name: &str, _inputs: &HashMap<String, String>) {
+        self.chains.lock().unwrap().insert(run.run_id, name.to_string());
+    }
+
+    fn on_chain_end(&self, run: &RunInfo, _outputs: &HashMap<String, String>) {
+        self.chains.lock().unwrap().remove(&run.run_id);
+    }
+
+    fn on_chain_error(&self, run: &RunInfo, _error: &anyhow::Error) {
+        self.chains.lock().unwrap().remove(&run.run_id);
+    }
+
+    fn on_llm_end(&self, run: &RunInfo, generation: &Generation) {
+        let Some(usage) = &generation.usage else {
+            return;
+        };
+        let chain = run.parent_run_id.and_then(|p| self.chains.lock().unwrap().get(&p).cloned());
+        self.ledger.record(
+            generation.model.as_deref(),
+            usage.clone(),
+            chain.as_deref(),
+            self.session.as_deref(),
+            &self.tags,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use crate::callbacks::Callbacks;
+    use crate::chain::LLMChain;
+    use crate::llm::LLM;
+    use crate::prompt::PromptTemplate;
+
+    fn usage(prompt: u32, completion: u32, cached: Option<u32>) -> TokenUsage {
+        TokenUsage { prompt_tokens: prompt, completion_tokens: completion, total_tokens: prompt + completion, cached_tokens: cached, ..Default::default() }
+    }
+
+    #[test]
+    fn test_pricing_lookup_and_cost() {
+        let table = PricingTable::builtin();
+        assert_eq!(table.get("gpt-4o-mini-2024-07-18"), table.get("gpt-4o-mini"));
+        assert_ne!(table.get("gpt-4o-mini"), table.get("gpt-4o"));
+        assert_eq!(table.get("openai/GPT-4o"), table.get("gpt-4o"));
+        assert!(table.get("llama3").is_none());
+
+        let pricing = ModelPricing::new(2.0, 8.0).with_cached_input(0.5);
+        // 600 uncached input, 400 cached input, 500 output tokens.
+        let cost = pricing.cost(&usage(1000, 500, Some(400)));
+        assert!((cost - (600.0 * 2.0 + 400.0 * 0.5 + 500.0 * 8.0) / 1e6).abs() < 1e-12);
+        assert_eq!(ModelPricing::new(1.0, 1.0).cost(&usage(10, 0, None)), 10.0 / 1e6);
+    }
+
+    struct PricedLLM(&'static str);
+
+    #[async_trait]
+    impl LLM for PricedLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            Ok("ok".to_string())
+        }
+
+        fn model_name(&self) -> Option<String> {
+            Some(self.0.to_string())
+        }
+
+        async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+            let mut generation = Generation::new(self.generate(prompt).await?);
+            generation.usage = Some(usage(1_000_000, 100_000, None));
+            Ok(generation)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ledger_groups_by_chain_session_and_tag() {
+        let ledger = Arc::new(CostLedger::with_pricing(
+            PricingTable::new().with_model("small", ModelPricing::new(1.0, 2.0)),
+        ));
+        let prompt = PromptTemplate::from_template("{q}").unwrap();
+        let cheap = LLMChain::new(prompt.clone(), Arc::new(PricedLLM("small"))).with_name("cheap");
+        let local = LLMChain::new(prompt, Arc::new(PricedLLM("llama3"))).with_name("local");
+        let inputs = HashMap::from([("q".to_string(), "hi".to_string())]);
+
+        let alice = Callbacks::new().with_handler(Arc::new(ledger.handler().with_session("alice").with_tag("beta")));
+        cheap.call_with_callbacks(inputs.clone(), alice.clone()).await.unwrap();
+        local.call_with_callbacks(inputs.clone(), alice).await.unwrap();
+        let bob = Callbacks::new().with_handler(Arc::new(ledger.handler().with_session("bob")));
+        cheap.call_with_callbacks(inputs, bob).await.unwrap();
+
+        let total = ledger.total();
+        assert_eq!((total.calls, total.unpriced_calls), (3, 1));
+        assert_eq!(total.prompt_tokens, 3_000_000);
+        assert!((total.cost - 2.0 * 1.2).abs() < 1e-9);
+
+        let by_chain = ledger.by_chain();
+        assert_eq!(by_chain["cheap"].calls, 2);
+        assert_eq!(by_chain["local"].cost, 0.0);
+        assert!((ledger.by_session()["alice"].cost - 1.2).abs() < 1e-9);
+        assert_eq!(ledger.by_tag().keys().collect::<Vec<_>>(), ["beta"]);
+        assert_eq!(ledger.by_model()["llama3"].unpriced_calls, 1);
+
+        ledger.reset();
+        assert_eq!(ledger.total(), CostSummary::default());
+    }
+}
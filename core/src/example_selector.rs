@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use anyhow::Result;
+use crate::embedding::Embeddings;
+use crate::prompt::PromptTemplate;
+use crate::schema::Document;
+use crate::token::TokenCounter;
+use crate::vectorstore::{InMemoryVectorStore, VectorStore};
+
+/// A few-shot example: variable name -> value, formatted by the example prompt.
+pub type Example = HashMap<String, String>;
+
+/// Chooses which examples a `FewShotPromptTemplate` shows for a given input.
+#[async_trait]
+pub trait ExampleSelector: Send + Sync {
+    async fn select_examples(&self, input: &HashMap<String, String>) -> Result<Vec<Example>>;
+
+    async fn add_example(&self, example: Example) -> Result<()>;
+}
+
+/// Always returns every example, in insertion order.
+#[derive(Default)]
+pub struct FixedExampleSelector {
+    examples: Mutex<Vec<Example>>,
+}
+
+impl FixedExampleSelector {
+    pub fn new(examples: Vec<Example>) -> Self {
+        Self { examples: Mutex::new(examples) }
+    }
+}
+
+#[async_trait]
+impl ExampleSelector for FixedExampleSelector {
+    async fn select_examples(&self, _input: &HashMap<String, String>) -> Result<Vec<Example>> {
+        Ok(self.examples.lock().unwrap().clone())
+    }
+
+    async fn add_example(&self, example: Example) -> Result<()> {
+        self.examples.lock().unwrap().push(example);
+        Ok(())
+    }
+}
+
+/// Takes examples in order until the formatted examples plus the input would exceed `max_tokens`.
+pub struct LengthBasedExampleSelector {
+    /// Examples with their formatted token counts, computed once when added.
+    examples: Mutex<Vec<(Example, usize)>>,
+    example_prompt: PromptTemplate,
+    max_tokens: usize,
+}
+
+impl LengthBasedExampleSelector {
+    pub fn new(examples: Vec<Example>, example_prompt: PromptTemplate, max_tokens: usize) -> Result<Self> {
+        let selector = Self {
+            examples: Mutex::new(Vec::new()),
+            example_prompt,
+            max_tokens,
+        };
+        for example in examples {
+            selector.push(example)?;
+        }
+        Ok(selector)
+    }
+
+    fn push(&self, example: Example) -> Result<()> {
+        let tokens = TokenCounter::count(&self.example_prompt.format(&example)?);
+        self.examples.lock().unwrap().push((example, tokens));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExampleSelector for LengthBasedExampleSelector {
+    async fn select_examples(&self, input: &HashMap<String, String>) -> Result<Vec<Example>> {
+        let input_text = input.values().cloned().collect::<Vec<_>>().join(" ");
+        let mut remaining = self.max_tokens.saturating_sub(TokenCounter::count(&input_text));
+
+        let mut selected = Vec::new();
+        for (example, tokens) in self.examples.lock().unwrap().iter() {
+            if *tokens > remaining {
+                break;
+            }
+            remaining -= tokens;
+            selected.push(example.clone());
+        }
+        Ok(selected)
+    }
+
+    async fn add_example(&self, example: Example) -> Result<()> {
+        self.push(example)
+    }
+}
+
+/// Embeds examples into an `InMemoryVectorStore` and looks them up by the input text.
+/// Only `input_keys` (all keys when unset) are embedded, so e.g. answers don't skew the match.
+struct ExampleIndex {
+    store: InMemoryVectorStore,
+    input_keys: Option<Vec<String>>,
+}
+
+impl ExampleIndex {
+    fn new(embeddings: Arc<dyn Embeddings>) -> Self {
+        Self { store: InMemoryVectorStore::new(embeddings), input_keys: None }
+    }
+
+    /// Values joined in key order so the text is stable across HashMap iteration orders.
+    fn text(&self, values: &HashMap<String, String>) -> String {
+        let mut keys: Vec<&String> = match &self.input_keys {
+            Some(keys) => keys.iter().filter(|k| values.contains_key(*k)).collect(),
+            None => values.keys().collect(),
+        };
+        keys.sort();
+        keys.into_iter().map(|k| values[k].as_str()).collect::<Vec<_>>().join(" ")
+    }
+
+    async fn add(&self, examples: Vec<Example>) -> Result<()> {
+        let docs: Vec<Document> = examples
+            .into_iter()
+            .map(|example| Document {
+                page_content: self.text(&example),
+                metadata: example,
+            })
+            .collect();
+        self.store.add_documents(&docs).await?;
+        Ok(())
+    }
+}
+
+/// Returns the `k` examples most similar to the input.
+pub struct SemanticSimilarityExampleSelector {
+    index: ExampleIndex,
+    k: usize,
+}
+
+impl SemanticSimilarityExampleSelector {
+    pub async fn from_examples(examples: Vec<Example>, embeddings: Arc<dyn Embeddings>, k: usize) -> Result<Self> {
+        Self::new(embeddings, k).with_examples(examples).await
+    }
+
+    pub fn new(embeddings: Arc<dyn Embeddings>, k: usize) -> Self {
+        Self { index: ExampleIndex::new(embeddings), k }
+    }
+
+    /// Restricts which example and input variables are embedded and compared.
+    pub fn with_input_keys(mut self, keys: Vec<String>) -> Self {
+        self.index.input_keys = Some(keys);
+        self
+    }
+
+    pub async fn with_examples(self, examples: Vec<Example>) -> Result<Self> {
+        self.index.add(examples).await?;
+        Ok(self)
+    }
+}
+
+#[async_trait]
+impl ExampleSelector for SemanticSimilarityExampleSelector {
+    async fn select_examples(&self, input: &HashMap<String, String>) -> Result<Vec<Example>> {
+        let docs = self.index.store.similarity_search(&self.index.text(input), self.k).await?;
+        Ok(docs.into_iter().map(|d| d.metadata).collect())
+    }
+
+    async fn add_example(&self, example: Example) -> Result<()> {
+        self.index.add(vec![example]).await
+    }
+}
+
+/// Like `SemanticSimilarityExampleSelector`, but uses maximal marginal relevance so the
+/// `k` examples are relevant without being near-duplicates of each other.
+pub struct MaxMarginalRelevanceExampleSelector {
+    index: ExampleIndex,
+    k: usize,
+    fetch_k: usize,
+    lambda_mult: f32,
+}
+
+impl MaxMarginalRelevanceExampleSelector {
+    pub async fn from_examples(examples: Vec<Example>, embeddings: Arc<dyn Embeddings>, k: usize) -> Result<Self> {
+        Self::new(embeddings, k).with_examples(examples).await
+    }
+
+    /// Defaults to considering the 20 closest examples with `lambda_mult` 0.5.
+    pub fn new(embeddings: Arc<dyn Embeddings>, k: usize) -> Self {
+        Self { index: ExampleIndex::new(embeddings), k, fetch_k: 20, lambda_mult: 0.5 }
+    }
+
+    pub fn with_input_keys(mut self, keys: Vec<String>) -> Self {
+        self.index.input_keys = Some(keys);
+        self
+    }
+
+    pub fn with_fetch_k(mut self, fetch_k: usize) -> Self {
+        self.fetch_k = fetch_k;
+        self
+    }
+
+    /// 1.0 ranks purely by relevance, 0.0 purely by diversity.
+    pub fn with_lambda_mult(mut self, lambda_mult: f32) -> Self {
+        self.lambda_mult = lambda_mult;
+        self
+    }
+
+    pub async fn with_examples(self, examples: Vec<Example>) -> Result<Self> {
+        self.index.add(examples).await?;
+        Ok(self)
+    }
+}
+
+#[async_trait]
+impl ExampleSelector for MaxMarginalRelevanceExampleSelector {
+    async fn select_examples(&self, input: &HashMap<String, String>) -> Result<Vec<Example>> {
+        let docs = self.index.store
+            .max_marginal_relevance_search(&self.index.text(input), self.k, self.fetch_k, self.lambda_mult)
+            .await?;
+        Ok(docs.into_iter().map(|d| d.metadata).collect())
+    }
+
+    async fn add_example(&self, example: Example) -> Result<()> {
+        self.index.add(vec![example]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Embeds text by counting a few keywords, so similarity follows topic.
+    struct KeywordEmbeddings;
+
+    #[async_trait]
+    impl Embeddings for KeywordEmbeddings {
+        async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+            Ok(["cat", "dog", "car", "fast"].iter().map(|w| text.matches(w).count() as f32).collect())
+        }
+
+        async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            let mut vecs = Vec::new();
+            for text in texts {
+                vecs.push(self.embed_query(text).await?);
+            }
+            Ok(vecs)
+        }
+    }
+
+    fn example(input: &str, output: &str) -> Example {
+        HashMap::from([
+            ("input".to_string(), input.to_string()),
+            ("output".to_string(), output.to_string()),
+        ])
+    }
+
+    fn inputs(examples: &[Example]) -> Vec<&str> {
+        examples.iter().map(|e| e["input"].as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_length_based_respects_budget() {
+        let prompt = PromptTemplate::from_template("Q: {input}\nA: {output}").unwrap();
+        let examples = vec![
+            example("one", "1"),
+            example("two", "2"),
+            example("a much longer question that uses many more tokens than the others", "3"),
+        ];
+        let selector = LengthBasedExampleSelector::new(examples, prompt, 20).unwrap();
+
+        let short = HashMap::from([("input".to_string(), "hi".to_string())]);
+        assert_eq!(inputs(&selector.select_examples(&short).await.unwrap()), ["one", "two"]);
+
+        let long = HashMap::from([("input".to_string(), "word ".repeat(15))]);
+        assert_eq!(selector.select_examples(&long).await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_and_mmr_selection() {
+        let examples = vec![
+            example("cat cat", "meow"),
+            example("cat cat cat", "purr"),
+            example("dog", "woof"),
+            example("fast car", "vroom"),
+        ];
+        let query = HashMap::from([("input".to_string(), "cat".to_string())]);
+
+        let similar = SemanticSimilarityExampleSelector::new(Arc::new(KeywordEmbeddings), 2)
+            .with_input_keys(vec!["input".to_string()])
+            .with_examples(examples.clone())
+            .await.unwrap();
+        let picked = similar.select_examples(&query).await.unwrap();
+        assert_eq!(picked.len(), 2);
+        assert!(inputs(&picked).iter().all(|i| i.starts_with("cat")));
+
+        let diverse = MaxMarginalRelevanceExampleSelector::from_examples(examples, Arc::new(KeywordEmbeddings), 2)
+            .await.unwrap()
+            .with_lambda_mult(0.3);
+        let picked = diverse.select_examples(&query).await.unwrap();
+        assert!(inputs(&picked)[0].starts_with("cat"));
+        assert!(!inputs(&picked)[1].starts_with("cat"));
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::Result;
+use crate::example_selector::{Example, ExampleSelector, FixedExampleSelector};
+use crate::prompt::PromptTemplate;
+
+/// Formats `prefix`, the selected examples (each through `example_prompt`) and `suffix`,
+/// joined by `example_separator`.
+///
+/// The prefix and suffix share the caller's variables; each example is formatted only
+/// with its own values.
+#[derive(Clone)]
+pub struct FewShotPromptTemplate {
+    prefix: Option<PromptTemplate>,
+    example_prompt: PromptTemplate,
+    suffix: PromptTemplate,
+    example_selector: Arc<dyn ExampleSelector>,
+    example_separator: String,
+}
+
+impl FewShotPromptTemplate {
+    /// Starts with no examples; add them with `with_examples` or `with_example_selector`.
+    pub fn new(example_prompt: PromptTemplate, suffix: &str) -> Result<Self> {
+        Ok(Self {
+            prefix: None,
+            example_prompt,
+            suffix: PromptTemplate::from_template(suffix)?,
+            example_selector: Arc::new(FixedExampleSelector::default()),
+            example_separator: "\n\n".to_string(),
+        })
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Result<Self> {
+        self.prefix = Some(PromptTemplate::from_template(prefix)?);
+        Ok(self)
+    }
+
+    /// Uses a fixed list of examples.
+    pub fn with_examples(mut self, examples: Vec<Example>) -> Self {
+        self.example_selector = Arc::new(FixedExampleSelector::new(examples));
+        self
+    }
+
+    pub fn with_example_selector(mut self, selector: Arc<dyn ExampleSelector>) -> Self {
+        self.example_selector = selector;
+        self
+    }
+
+    pub fn with_example_separator(mut self, separator: &str) -> Self {
+        self.example_separator = separator.to_string();
+        self
+    }
+
+    /// Variables of the prefix and suffix, in order of first appearance.
+    pub fn input_variables(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for template in self.prefix.iter().chain(std::iter::once(&self.suffix)) {
+            for name in template.input_variables() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    }
+
+    /// Selects examples for `values` and formats the full prompt.
+    pub async fn format(&self, values: &HashMap<String, String>) -> Result<String> {
+        let examples = self.example_selector.select_examples(values).await?;
+
+        let mut pieces = Vec::with_capacity(examples.len() + 2);
+        if let Some(prefix) = &self.prefix {
+            pieces.push(prefix.format(values)?);
+        }
+        for example in &examples {
+            pieces.push(self.example_prompt.format(example)?);
+        }
+        pieces.push(self.suffix.format(values)?);
+
+        Ok(pieces.into_iter().filter(|p| !p.is_empty()).collect::<Vec<_>>().join(&self.example_separator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(word: &str, antonym: &str) -> Example {
+        HashMap::from([
+            ("word".to_string(), word.to_string()),
+            ("antonym".to_string(), antonym.to_string()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_format_with_examples() {
+        let example_prompt = PromptTemplate::from_template("Word: {word}\nAntonym: {antonym}").unwrap();
+        let prompt = FewShotPromptTemplate::new(example_prompt, "Word: {input}\nAntonym:").unwrap()
+            .with_prefix("Give the antonym of every {kind}.").unwrap()
+            .with_examples(vec![example("happy", "sad"), example("tall", "short")]);
+        assert_eq!(prompt.input_variables(), ["kind", "input"]);
+
+        let values = HashMap::from([
+            ("kind".to_string(), "word".to_string()),
+            ("input".to_string(), "big".to_string()),
+        ]);
+        assert_eq!(
+            prompt.format(&values).await.unwrap(),
+            "Give the antonym of every word.\n\nWord: happy\nAntonym: sad\n\nWord: tall\nAntonym: short\n\nWord: big\nAntonym:"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_selector_controls_examples() {
+        let selector = Arc::new(FixedExampleSelector::default());
+        selector.add_example(example("hot", "cold")).await.unwrap();
+
+        let example_prompt = PromptTemplate::from_template("{word} -> {antonym}").unwrap();
+        let prompt = FewShotPromptTemplate::new(example_prompt, "{input} ->").unwrap()
+            .with_example_selector(selector)
+            .with_example_separator("\n");
+
+        let values = HashMap::from([("input".to_string(), "up".to_string())]);
+        assert_eq!(prompt.format(&values).await.unwrap(), "hot -> cold\nup ->");
+    }
+}
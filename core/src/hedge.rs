@@ -0,0 +1,287 @@
+use crate::llm::{Generation, LLM};
+use crate::schema::ChatMessage;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::{Id, JoinSet};
+
+struct Backend {
+    name: String,
+    llm: Arc<dyn LLM>,
+}
+
+/// Counters describing which backends have been answering hedged calls.
+#[derive(Debug, Clone, Default)]
+pub struct HedgeStats {
+    pub calls: u64,
+    /// Calls won by each backend.
+    pub wins: HashMap<String, u64>,
+    /// Failed attempts per backend (a call can still succeed through another backend).
+    pub errors: HashMap<String, u64>,
+    /// In-flight attempts aborted because another backend answered first.
+    pub cancelled: u64,
+    pub last_winner: Option<String>,
+    pub last_latency: Option<Duration>,
+}
+
+/// Sends the same prompt to several backends and returns the first successful answer.
+///
+/// Each backend is started `hedge_delay` after the previous one (all at once when the delay
+/// is zero), or immediately when every attempt in flight has failed, which also restarts the
+/// delay for the backend after it.
+/// Outstanding attempts are aborted as soon as one succeeds. Adding the same LLM twice
+/// hedges a single provider against its own tail latency.
+pub struct HedgedLLM {
+    backends: Vec<Backend>,
+    hedge_delay: Duration,
+    stats: Arc<Mutex<HedgeStats>>,
+}
+
+impl HedgedLLM {
+    pub fn new() -> Self {
+        Self {
+            backends: Vec::new(),
+            hedge_delay: Duration::ZERO,
+            stats: Arc::new(Mutex::new(HedgeStats::default())),
+        }
+    }
+
+    pub fn with_backend(mut self, name: &str, llm: Arc<dyn LLM>) -> Self {
+        self.backends.push(Backend { name: name.to_string(), llm });
+        self
+    }
+
+    pub fn with_hedge_delay(mut self, delay: Duration) -> Self {
+        self.hedge_delay = delay;
+        self
+    }
+
+    pub fn stats(&self) -> HedgeStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Starts backend `index`, remembering its task id so a panicked attempt can be attributed.
+    fn launch(
+        &self,
+        set: &mut JoinSet<(usize, Result<Generation>)>,
+        tasks: &mut HashMap<Id, usize>,
+        index: usize,
+        messages: &Arc<[ChatMessage]>,
+    ) {
+        let llm = self.backends[index].llm.clone();
+        let messages = messages.clone();
+        let handle = set.spawn(async move { (index, llm.generate_messages(&messages).await) });
+        tasks.insert(handle.id(), index);
+    }
+
+    fn record_error(&self, index: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        *stats.errors.entry(self.backends[index].name.clone()).or_insert(0) += 1;
+    }
+}
+
+impl Default for HedgedLLM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LLM for HedgedLLM {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        Ok(self.generate_with_usage(prompt).await?.text)
+    }
+
+    async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+        self.generate_messages(&[ChatMessage::human(prompt)]).await
+    }
+
+    async fn generate_messages(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        if self.backends.is_empty() {
+            return Err(anyhow!("HedgedLLM has no backends configured"));
+        }
+        self.stats.lock().unwrap().calls += 1;
+
+        let conversation: Arc<[ChatMessage]> = messages.into();
+        let started = Instant::now();
+        let mut in_flight = JoinSet::new();
+        let mut tasks = HashMap::new();
+        let mut next = 0;
+        let mut failures = Vec::new();
+
+        self.launch(&mut in_flight, &mut tasks, next, &conversation);
+        next += 1;
+        if self.hedge_delay.is_zero() {
+            while next < self.backends.len() {
+                self.launch(&mut in_flight, &mut tasks, next, &conversation);
+                next += 1;
+            }
+        }
+
+        // One timer for the whole call, restarted whenever a backend is launched.
+        let hedge_timer = tokio::time::sleep(self.hedge_delay);
+        tokio::pin!(hedge_timer);
+
+        loop {
+            let pending = next < self.backends.len();
+            tokio::select! {
+                Some(joined) = in_flight.join_next(), if !in_flight.is_empty() => {
+                    let (index, result) = match joined {
+                        Ok(outcome) => outcome,
+                        // A panicked or aborted attempt counts as that backend failing.
+                        Err(e) => (tasks[&e.id()], Err(anyhow!("task failed: {}", e))),
+                    };
+                    match result {
+                        Ok(mut generation) => {
+                            // Usage is priced by the backend that actually answered.
+                            generation.model = generation.model.or_else(|| self.backends[index].llm.model_name());
+                            let mut stats = self.stats.lock().unwrap();
+                            let name = self.backends[index].name.clone();
+                            *stats.wins.entry(name.clone()).or_insert(0) += 1;
+                            stats.cancelled += in_flight.len() as u64;
+                            stats.last_winner = Some(name);
+                            stats.last_latency = Some(started.elapsed());
+                            // Dropping the JoinSet aborts the remaining attempts.
+                            return Ok(generation);
+                        }
+                        Err(e) => {
+                            self.record_error(index);
+                            failures.push(format!("{}: {}", self.backends[index].name, e));
+                            if in_flight.is_empty() && pending {
+                                self.launch(&mut in_flight, &mut tasks, next, &conversation);
+                                next += 1;
+                                hedge_timer.as_mut().reset(tokio::time::Instant::now() + self.hedge_delay);
+                            }
+                        }
+                    }
+                }
+                _ = &mut hedge_timer, if pending => {
+                    self.launch(&mut in_flight, &mut tasks, next, &conversation);
+                    next += 1;
+                    hedge_timer.as_mut().reset(tokio::time::Instant::now() + self.hedge_delay);
+                }
+                else => break,
+            }
+
+            if in_flight.is_empty() && next >= self.backends.len() {
+                break;
+            }
+        }
+        Err(anyhow!("All hedged backends failed: {}", failures.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct SlowLLM {
+        delay: Duration,
+        reply: Option<&'static str>,
+        calls: AtomicUsize,
+    }
+
+    impl SlowLLM {
+        fn new(delay_ms: u64, reply: Option<&'static str>) -> Arc<Self> {
+            Arc::new(Self { delay: Duration::from_millis(delay_ms), reply, calls: AtomicUsize::new(0) })
+        }
+    }
+
+    #[async_trait]
+    impl LLM for SlowLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.reply.map(str::to_string).ok_or_else(|| anyhow!("backend down"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fastest_backend_wins() {
+        let hedged = HedgedLLM::new()
+            .with_backend("slow", SlowLLM::new(300, Some("slow")))
+            .with_backend("fast", SlowLLM::new(10, Some("fast")));
+
+        assert_eq!(hedged.generate("hi").await.unwrap(), "fast");
+        let stats = hedged.stats();
+        assert_eq!(stats.wins.get("fast"), Some(&1));
+        assert_eq!(stats.cancelled, 1);
+        assert_eq!(stats.last_winner.as_deref(), Some("fast"));
+    }
+
+    #[tokio::test]
+    async fn test_hedge_delay_skips_backup_when_primary_is_fast() {
+        let backup = SlowLLM::new(10, Some("backup"));
+        let hedged = HedgedLLM::new()
+            .with_backend("primary", SlowLLM::new(10, Some("primary")))
+            .with_backend("backup", backup.clone())
+            .with_hedge_delay(Duration::from_millis(200));
+
+        assert_eq!(hedged.generate("hi").await.unwrap(), "primary");
+        assert_eq!(backup.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failure_launches_next_backend_early() {
+        let hedged = HedgedLLM::new()
+            .with_backend("broken", SlowLLM::new(5, None))
+            .with_backend("backup", SlowLLM::new(5, Some("backup")))
+            .with_hedge_delay(Duration::from_secs(10));
+
+        let started = Instant::now();
+        assert_eq!(hedged.generate("hi").await.unwrap(), "backup");
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(hedged.stats().errors.get("broken"), Some(&1));
+
+        let all_broken = HedgedLLM::new()
+            .with_backend("a", SlowLLM::new(1, None))
+            .with_backend("b", SlowLLM::new(1, None));
+        let err = all_broken.generate("hi").await.unwrap_err();
+        assert!(err.to_string().contains("All hedged backends failed"));
+    }
+
+    struct PanickingLLM;
+
+    #[async_trait]
+    impl LLM for PanickingLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            panic!("backend bug")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicked_backend_counts_as_failure() {
+        let hedged = HedgedLLM::new()
+            .with_backend("buggy", Arc::new(PanickingLLM))
+            .with_backend("backup", SlowLLM::new(5, Some("backup")))
+            .with_hedge_delay(Duration::from_secs(10));
+        assert_eq!(hedged.generate("hi").await.unwrap(), "backup");
+        assert_eq!(hedged.stats().errors.get("buggy"), Some(&1));
+
+        // The last attempt panicking ends the call with the combined error.
+        let only_buggy = HedgedLLM::new()
+            .with_backend("slow", SlowLLM::new(1, None))
+            .with_backend("buggy", Arc::new(PanickingLLM))
+            .with_hedge_delay(Duration::from_millis(20));
+        let err = only_buggy.generate("hi").await.unwrap_err().to_string();
+        assert!(err.starts_with("All hedged backends failed: slow: backend down; buggy: task failed"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_hedge_delay_restarts_after_early_launch() {
+        let third = SlowLLM::new(5, Some("third"));
+        let hedged = HedgedLLM::new()
+            .with_backend("broken", SlowLLM::new(80, None))
+            .with_backend("second", SlowLLM::new(50, Some("second")))
+            .with_backend("third", third.clone())
+            .with_hedge_delay(Duration::from_millis(100));
+
+        // "second" starts early when "broken" fails at 80ms, so "third" is due a full delay
+        // after that, not at 100ms, and "second" answers first.
+        assert_eq!(hedged.generate("hi").await.unwrap(), "second");
+        assert_eq!(third.calls.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,7 +1,25 @@
 pub mod llm;
 pub mod prompt;
+pub mod minify;
+pub mod output_parser;
+pub mod runnable;
+pub mod compression;
+pub mod prompt_hub;
+pub mod chat_prompt;
+pub mod few_shot;
+pub mod example_selector;
 pub mod cache;
+pub mod callbacks;
+pub mod cost;
+pub mod tracer;
 pub mod chain;
+pub mod batch;
+pub mod budget;
+pub mod sequential;
+pub mod router;
+pub mod summarize;
+pub mod retrieval_qa;
+pub mod conversational_retrieval;
 pub mod providers;
 pub mod token;
 pub mod memory;
@@ -10,3 +28,5 @@ pub mod loader;
 pub mod embedding;
 pub mod vectorstore;
 pub mod agent;
+pub mod hedge;
+pub mod secret;
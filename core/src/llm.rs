@@ -1,8 +1,79 @@
 use async_trait::async_trait;
 use anyhow::Result;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use crate::schema::{ChatMessage, Role};
+
+/// Token accounting reported by a provider for a single call.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+    /// Hidden reasoning tokens (already included in `completion_tokens`).
+    pub reasoning_tokens: Option<u32>,
+    /// Prompt tokens served from the provider's prompt cache.
+    pub cached_tokens: Option<u32>,
+}
+
+/// A completion together with the usage the provider reported for it.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+    /// Model that produced the completion, when known.
+    pub model: Option<String>,
+}
+
+impl Generation {
+    pub fn new(text: String) -> Self {
+        Self { text, usage: None, model: None }
+    }
+}
 
 #[async_trait]
 pub trait LLM: Send + Sync {
     /// Generate a response solely based on the prompt.
     async fn generate(&self, prompt: &str) -> Result<String>;
+
+    /// Model identifier, used to price usage and label traces. `None` when unknown.
+    fn model_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Generate a response and return the token usage when the provider reports it.
+    /// Providers without usage reporting fall back to `generate`.
+    async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+        Ok(Generation::new(self.generate(prompt).await?))
+    }
+
+    /// Generate a response to a role-tagged conversation.
+    /// Providers without a native chat API receive the conversation flattened by `messages_to_prompt`.
+    async fn generate_messages(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        self.generate_with_usage(&messages_to_prompt(messages)).await
+    }
+
+    /// Streams the response to a conversation as it is generated. Each chunk's `text` is the
+    /// newly generated part; usage, when reported, arrives on the last chunk.
+    /// Providers without streaming yield the `generate_messages` result as a single chunk.
+    fn stream_messages(&self, messages: Vec<ChatMessage>) -> BoxStream<'_, Result<Generation>> {
+        stream::once(async move { self.generate_messages(&messages).await }).boxed()
+    }
+}
+
+/// Renders a conversation as `Role: content` lines ending with an open `AI:` turn.
+/// A lone human message is passed through unchanged.
+pub fn messages_to_prompt(messages: &[ChatMessage]) -> String {
+    if let [message] = messages {
+        if message.role == Role::Human {
+            return message.content.clone();
+        }
+    }
+    let mut prompt = messages
+        .iter()
+        .map(|m| format!("{}: {}", m.role.label(), m.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    prompt.push_str("\nAI:");
+    prompt
 }
@@ -2,12 +2,24 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use async_trait::async_trait;
 use anyhow::Result;
+use crate::schema::{ChatMessage, Role};
 
 /// Trait for Memory management in Chains.
 #[async_trait]
 pub trait Memory: Send + Sync {
     /// Load memory variables (e.g. history) into the input context
     async fn load_memory_variables(&self, inputs: &HashMap<String, String>) -> Result<HashMap<String, String>>;
+
+    /// Keys returned by `load_memory_variables`, so chains know which prompt variables memory fills.
+    fn memory_variables(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Load history as role-tagged messages, keyed like `load_memory_variables`, for chat prompts.
+    /// Memories that only keep text return nothing here.
+    async fn load_memory_messages(&self, _inputs: &HashMap<String, String>) -> Result<HashMap<String, Vec<ChatMessage>>> {
+        Ok(HashMap::new())
+    }
     
     /// Save context from this run to memory
     async fn save_context(&self, inputs: &HashMap<String, String>, outputs: &HashMap<String, String>) -> Result<()>;
@@ -19,7 +31,7 @@ pub trait Memory: Send + Sync {
 /// Simple buffer memory that stores chat history.
 #[derive(Clone)]
 pub struct ConversationBufferMemory {
-    history: Arc<Mutex<Vec<String>>>,
+    history: Arc<Mutex<Vec<ChatMessage>>>,
     memory_key: String, // Key to inject into prompt (default: "history")
     human_prefix: String,
     ai_prefix: String,
@@ -41,17 +53,42 @@ impl ConversationBufferMemory {
     }
 }
 
+impl Default for ConversationBufferMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl Memory for ConversationBufferMemory {
     async fn load_memory_variables(&self, _inputs: &HashMap<String, String>) -> Result<HashMap<String, String>> {
         let history = self.history.lock().unwrap();
-        let buffer = history.join("\n");
+        let buffer = history.iter()
+            .map(|m| {
+                let prefix = match m.role {
+                    Role::Human => &self.human_prefix,
+                    Role::AI => &self.ai_prefix,
+                    Role::System => "System",
+                };
+                format!("{}: {}", prefix, m.content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
         
         let mut map = HashMap::new();
         map.insert(self.memory_key.clone(), buffer);
         Ok(map)
     }
 
+    fn memory_variables(&self) -> Vec<String> {
+        vec![self.memory_key.clone()]
+    }
+
+    async fn load_memory_messages(&self, _inputs: &HashMap<String, String>) -> Result<HashMap<String, Vec<ChatMessage>>> {
+        let history = self.history.lock().unwrap();
+        Ok(HashMap::from([(self.memory_key.clone(), history.clone())]))
+    }
+
     async fn save_context(&self, inputs: &HashMap<String, String>, outputs: &HashMap<String, String>) -> Result<()> {
         // Try specific keys first, fall back to "input" or arbitrary first value
         let input_val = inputs.get("input").map(|s| s.as_str())
@@ -62,10 +99,9 @@ impl Memory for ConversationBufferMemory {
             .or_else(|| outputs.values().next().map(|s| s.as_str()))
             .unwrap_or("");
 
-        let entry = format!("{}: {}\n{}: {}", self.human_prefix, input_val, self.ai_prefix, output_val);
-        
         let mut history = self.history.lock().unwrap();
-        history.push(entry);
+        history.push(ChatMessage::human(input_val));
+        history.push(ChatMessage::ai(output_val));
         
         Ok(())
     }
@@ -0,0 +1,177 @@
+use std::str::FromStr;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::token::TokenCounter;
+
+/// How aggressively `LLMChain` shrinks prompts before sending them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MinifyPolicy {
+    /// Send the prompt exactly as formatted.
+    Off,
+    /// Strip trailing whitespace and collapse runs of blank lines; indentation is kept.
+    Whitespace,
+    /// Trim lines and drop blank lines, except inside fenced code blocks (kept verbatim)
+    /// and around markdown tables (which need a blank line to end).
+    #[default]
+    StructureAware,
+    /// Trim every line, drop blank lines and collapse runs of spaces, code included.
+    Aggressive,
+}
+
+impl FromStr for MinifyPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "off" | "none" => Ok(Self::Off),
+            "whitespace" => Ok(Self::Whitespace),
+            "structure_aware" | "structure" => Ok(Self::StructureAware),
+            "aggressive" => Ok(Self::Aggressive),
+            other => Err(anyhow::anyhow!("Unknown minify policy: {}", other)),
+        }
+    }
+}
+
+/// Token counts before and after minification, accumulated across calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinifyStats {
+    pub calls: u64,
+    pub original_tokens: u64,
+    pub minified_tokens: u64,
+}
+
+impl MinifyStats {
+    pub fn tokens_saved(&self) -> u64 {
+        self.original_tokens.saturating_sub(self.minified_tokens)
+    }
+
+    /// Adds one prompt's before/after token counts.
+    pub fn record(&mut self, original: &str, minified: &str) {
+        self.calls += 1;
+        self.original_tokens += TokenCounter::count(original) as u64;
+        self.minified_tokens += TokenCounter::count(minified) as u64;
+    }
+}
+
+pub fn minify(text: &str, policy: MinifyPolicy) -> String {
+    match policy {
+        MinifyPolicy::Off => text.to_string(),
+        MinifyPolicy::Whitespace => minify_whitespace(text),
+        MinifyPolicy::StructureAware => minify_structure_aware(text),
+        MinifyPolicy::Aggressive => text
+            .lines()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn minify_whitespace(text: &str) -> String {
+    let mut out: Vec<&str> = Vec::new();
+    let mut pending_blank = false;
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() {
+            pending_blank = !out.is_empty();
+            continue;
+        }
+        if pending_blank {
+            out.push("");
+            pending_blank = false;
+        }
+        out.push(line);
+    }
+    out.join("\n")
+}
+
+fn fence_marker(trimmed: &str) -> Option<&'static str> {
+    ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m))
+}
+
+fn is_table_row(trimmed: &str) -> bool {
+    trimmed.starts_with('|')
+}
+
+fn minify_structure_aware(text: &str) -> String {
+    let mut out: Vec<&str> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut skipped_blank = false;
+    let mut in_table = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            out.push(line);
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            skipped_blank = true;
+            continue;
+        }
+
+        // A blank line is the only thing that ends a markdown table, so keep one at its edges.
+        let table_row = is_table_row(trimmed);
+        if skipped_blank && table_row != in_table && !out.is_empty() {
+            out.push("");
+        }
+        skipped_blank = false;
+        in_table = table_row;
+
+        fence = fence_marker(trimmed);
+        out.push(if fence.is_some() { line.trim_end() } else { trimmed });
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "
+    Summarize this:
+
+
+    ```python
+    def f(x):
+        return x
+    ```
+
+    | a   | b |
+    |-----|---|
+    | 1   | 2 |
+
+    Thanks   for   reading.
+    ";
+
+    #[test]
+    fn test_policies() {
+        assert_eq!(minify(DOC, MinifyPolicy::Off), DOC);
+        assert_eq!(
+            minify(DOC, MinifyPolicy::StructureAware),
+            "Summarize this:\n    ```python\n    def f(x):\n        return x\n    ```\n\n| a   | b |\n|-----|---|\n| 1   | 2 |\n\nThanks   for   reading."
+        );
+        assert_eq!(
+            minify(DOC, MinifyPolicy::Aggressive),
+            "Summarize this:\n```python\ndef f(x):\nreturn x\n```\n| a | b |\n|-----|---|\n| 1 | 2 |\nThanks for reading."
+        );
+        let whitespace = minify(DOC, MinifyPolicy::Whitespace);
+        assert!(whitespace.starts_with("    Summarize this:\n\n    ```python"));
+        assert!(whitespace.contains("        return x\n"));
+    }
+
+    #[test]
+    fn test_stats_and_parsing() {
+        let mut stats = MinifyStats::default();
+        stats.record(DOC, &minify(DOC, MinifyPolicy::Aggressive));
+        assert_eq!(stats.calls, 1);
+        assert!(stats.tokens_saved() > 0);
+
+        assert_eq!("structure-aware".parse::<MinifyPolicy>().unwrap(), MinifyPolicy::StructureAware);
+        assert_eq!("OFF".parse::<MinifyPolicy>().unwrap(), MinifyPolicy::Off);
+        assert!("tiny".parse::<MinifyPolicy>().is_err());
+    }
+}
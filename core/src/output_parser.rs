@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use anyhow::Result;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use crate::callbacks::trace_llm;
+use crate::chain::LLMChain;
+use crate::llm::LLM;
+use crate::schema::PromptValue;
+
+/// Raised when a completion doesn't have the expected shape. Carries the offending text
+/// so it can be sent back to the LLM for correction.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Failed to parse output: {reason}")]
+pub struct OutputParserError {
+    pub reason: String,
+    pub output: String,
+}
+
+impl OutputParserError {
+    pub fn new(reason: impl Into<String>, output: &str) -> Self {
+        Self { reason: reason.into(), output: output.to_string() }
+    }
+}
+
+/// Turns raw LLM text into a structured value.
+pub trait OutputParser: Send + Sync {
+    type Output: Send + 'static;
+
+    fn parse(&self, text: &str) -> Result<Self::Output>;
+
+    /// Tells the LLM how to format its answer; injected into prompts by `LLMChain::with_output_parser`.
+    fn format_instructions(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Returns the completion with surrounding whitespace trimmed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StrOutputParser;
+
+impl OutputParser for StrOutputParser {
+    type Output = String;
+
+    fn parse(&self, text: &str) -> Result<String> {
+        Ok(text.trim().to_string())
+    }
+}
+
+/// Takes the contents of the first markdown code fence, if any. The closing fence must start
+/// a line, so backticks inside the fenced text (e.g. code in a JSON string) are kept.
+fn strip_code_fence(text: &str) -> &str {
+    let Some(start) = text.find("```") else {
+        return text;
+    };
+    let body = &text[start + 3..];
+    // Skip the language tag on the opening fence line.
+    let body = body.find('\n').map_or(body, |i| &body[i + 1..]);
+    body.find("\n```").map_or(body, |end| &body[..end])
+}
+
+/// Removes commas directly before a closing `}` or `]`, ignoring string contents.
+fn remove_trailing_commas(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let chars: Vec<char> = json.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Parses JSON leniently: markdown fences and text around the outermost object or array
+/// are dropped, and trailing commas are removed.
+pub fn parse_json_lenient(text: &str) -> Result<serde_json::Value> {
+    let body = strip_code_fence(text).trim();
+    if let Ok(value) = serde_json::from_str(body) {
+        return Ok(value);
+    }
+
+    let start = body.find(['{', '[']);
+    let end = body.rfind(['}', ']']);
+    let candidate = match (start, end) {
+        (Some(start), Some(end)) if start < end => &body[start..=end],
+        _ => body,
+    };
+    serde_json::from_str(&remove_trailing_commas(candidate))
+        .map_err(|e| OutputParserError::new(format!("Invalid JSON: {}", e), text).into())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonOutputParser;
+
+impl OutputParser for JsonOutputParser {
+    type Output = serde_json::Value;
+
+    fn parse(&self, text: &str) -> Result<serde_json::Value> {
+        parse_json_lenient(text)
+    }
+
+    fn format_instructions(&self) -> Option<String> {
+        Some("Respond with a single valid JSON value and nothing else.".to_string())
+    }
+}
+
+/// Deserializes the (leniently parsed) JSON completion into `T`.
+pub struct TypedOutputParser<T> {
+    schema: String,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedOutputParser<T> {
+    /// `schema` describes the expected JSON to the LLM, e.g. `{"name": string, "age": number}`.
+    pub fn new(schema: &str) -> Self {
+        Self { schema: schema.to_string(), _marker: PhantomData }
+    }
+}
+
+impl<T: DeserializeOwned + Send + 'static> OutputParser for TypedOutputParser<T> {
+    type Output = T;
+
+    fn parse(&self, text: &str) -> Result<T> {
+        serde_json::from_value(parse_json_lenient(text)?)
+            .map_err(|e| OutputParserError::new(format!("JSON does not match the schema: {}", e), text).into())
+    }
+
+    fn format_instructions(&self) -> Option<String> {
+        Some(format!("Respond with a single JSON value matching this schema and nothing else:\n{}", self.schema))
+    }
+}
+
+/// Splits `a, b, c` into items.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommaSeparatedListOutputParser;
+
+impl OutputParser for CommaSeparatedListOutputParser {
+    type Output = Vec<String>;
+
+    fn parse(&self, text: &str) -> Result<Vec<String>> {
+        Ok(text.trim().split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+    }
+
+    fn format_instructions(&self) -> Option<String> {
+        Some("Respond with a comma-separated list, e.g. `foo, bar, baz`.".to_string())
+    }
+}
+
+/// Collects the items of a `1. foo` / `2) bar` list; other lines are ignored.
+#[derive(Debug, Clone)]
+pub struct NumberedListOutputParser {
+    pattern: Regex,
+}
+
+impl Default for NumberedListOutputParser {
+    fn default() -> Self {
+        Self { pattern: Regex::new(r"^\s*\d+[.)]\s+(.+?)\s*$").unwrap() }
+    }
+}
+
+impl OutputParser for NumberedListOutputParser {
+    type Output = Vec<String>;
+
+    fn parse(&self, text: &str) -> Result<Vec<String>> {
+        let items: Vec<String> = text
+            .lines()
+            .filter_map(|line| self.pattern.captures(line))
+            .map(|c| c[1].to_string())
+            .collect();
+        if items.is_empty() {
+            return Err(OutputParserError::new("No numbered list items found", text).into());
+        }
+        Ok(items)
+    }
+
+    fn format_instructions(&self) -> Option<String> {
+        Some("Respond with a numbered list, one item per line, e.g.\n1. foo\n2. bar".to_string())
+    }
+}
+
+/// Extracts the named capture groups of the first match.
+#[derive(Debug, Clone)]
+pub struct RegexOutputParser {
+    pattern: Regex,
+}
+
+impl RegexOutputParser {
+    pub fn new(pattern: &str) -> Result<Self> {
+        Ok(Self { pattern: Regex::new(pattern)? })
+    }
+}
+
+impl OutputParser for RegexOutputParser {
+    type Output = HashMap<String, String>;
+
+    fn parse(&self, text: &str) -> Result<HashMap<String, String>> {
+        let captures = self.pattern
+            .captures(text)
+            .ok_or_else(|| OutputParserError::new(format!("Output does not match /{}/", self.pattern), text))?;
+        Ok(self.pattern
+            .capture_names()
+            .flatten()
+            .filter_map(|name| captures.name(name).map(|m| (name.to_string(), m.as_str().to_string())))
+            .collect())
+    }
+}
+
+/// Accepts one of a fixed set of values (case-insensitive), returned in its canonical spelling.
+#[derive(Debug, Clone)]
+pub struct EnumOutputParser {
+    values: Vec<String>,
+}
+
+impl EnumOutputParser {
+    pub fn new(values: &[&str]) -> Self {
+        Self { values: values.iter().map(|v| v.to_string()).collect() }
+    }
+}
+
+impl OutputParser for EnumOutputParser {
+    type Output = String;
+
+    fn parse(&self, text: &str) -> Result<String> {
+        let answer = text.trim().trim_matches(|c: char| c == '"' || c == '\'' || c == '.' || c == '`');
+        self.values
+            .iter()
+            .find(|v| v.eq_ignore_ascii_case(answer))
+            .cloned()
+            .ok_or_else(|| OutputParserError::new(format!("Expected one of: {}", self.values.join(", ")), text).into())
+    }
+
+    fn format_instructions(&self) -> Option<String> {
+        Some(format!("Respond with exactly one of: {}", self.values.join(", ")))
+    }
+}
+
+/// Reads yes/no and true/false answers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BooleanOutputParser;
+
+impl OutputParser for BooleanOutputParser {
+    type Output = bool;
+
+    fn parse(&self, text: &str) -> Result<bool> {
+        let answer = text.trim().trim_end_matches(['.', '!']).to_lowercase();
+        match answer.as_str() {
+            "yes" | "y" | "true" => Ok(true),
+            "no" | "n" | "false" => Ok(false),
+            _ => Err(OutputParserError::new("Expected YES or NO", text).into()),
+        }
+    }
+
+    fn format_instructions(&self) -> Option<String> {
+        Some("Respond with YES or NO.".to_string())
+    }
+}
+
+/// Wraps a parser and, when parsing fails, asks the LLM to correct its output using the
+/// parse error, up to `max_retries` times.
+pub struct OutputFixingParser<P> {
+    parser: P,
+    llm: Arc<dyn LLM>,
+    max_retries: usize,
+}
+
+impl<P: OutputParser> OutputFixingParser<P> {
+    /// Defaults to one correction attempt.
+    pub fn new(parser: P, llm: Arc<dyn LLM>) -> Self {
+        Self { parser, llm, max_retries: 1 }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn parser(&self) -> &P {
+        &self.parser
+    }
+
+    pub async fn parse(&self, text: &str) -> Result<P::Output> {
+        let mut completion = text.to_string();
+        let mut attempt = 0;
+        loop {
+            let error = match self.parser.parse(&completion) {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt >= self.max_retries => return Err(e),
+                Err(e) => e,
+            };
+            attempt += 1;
+            let prompt = PromptValue::Text(self.fix_prompt(&completion, &error));
+            completion = trace_llm(self.llm.as_ref(), prompt).await?.text;
+        }
+    }
+
+    fn fix_prompt(&self, completion: &str, error: &anyhow::Error) -> String {
+        let instructions = self.parser.format_instructions().unwrap_or_default();
+        format!(
+            "Instructions:\n{}\n\nCompletion:\n{}\n\nThe completion above did not satisfy the instructions.\nError: {}\n\n\
+             Respond again with only an answer that satisfies the instructions.",
+            instructions, completion, error
+        )
+    }
+}
+
+/// An `LLMChain` whose completions are parsed, created by `LLMChain::with_output_parser`.
+pub struct ParsedChain<P> {
+    chain: LLMChain,
+    parser: OutputFixingParser<P>,
+}
+
+impl<P: OutputParser> ParsedChain<P> {
+    pub(crate) fn new(chain: LLMChain, parser: P) -> Self {
+        let parser = OutputFixingParser::new(parser, chain.llm().clone()).with_max_retries(0);
+        Self { chain, parser }
+    }
+
+    /// Sends parse errors back to the chain's LLM for correction, up to `max_retries` times.
+    pub fn with_auto_fix(mut self, max_retries: usize) -> Self {
+        self.parser.max_retries = max_retries;
+        self
+    }
+
+    pub fn chain(&self) -> &LLMChain {
+        &self.chain
+    }
+
+    pub async fn call(&self, inputs: HashMap<String, String>) -> Result<P::Output> {
+        let text = self.chain.call(inputs).await?;
+        self.parser.parse(&text).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use serde_json::json;
+    use crate::minify::MinifyPolicy;
+    use crate::prompt::PromptTemplate;
+
+    /// Replays canned completions and records the prompts it received.
+    struct ScriptedLLM {
+        replies: Mutex<Vec<&'static str>>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl ScriptedLLM {
+        fn new(replies: &[&'static str]) -> Arc<Self> {
+            Arc::new(Self { replies: Mutex::new(replies.iter().rev().copied().collect()), prompts: Mutex::new(Vec::new()) })
+        }
+    }
+
+    #[async_trait]
+    impl LLM for ScriptedLLM {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            Ok(self.replies.lock().unwrap().pop().unwrap_or_default().to_string())
+        }
+    }
+
+    #[test]
+    fn test_json_repair() {
+        let text = "Sure! Here it is:\n```json\n{\"a\": [1, 2,], \"b\": \"x, }\",}\n```";
+        assert_eq!(JsonOutputParser.parse(text).unwrap(), json!({"a": [1, 2], "b": "x, }"}));
+        assert_eq!(JsonOutputParser.parse("The answer is {\"ok\": true}.").unwrap(), json!({"ok": true}));
+        assert_eq!(
+            JsonOutputParser.parse("```json\n{\"code\": \"```rust\\nfn main() {}\\n```\"}\n```").unwrap(),
+            json!({"code": "```rust\nfn main() {}\n```"})
+        );
+        // Escaped quotes and backslashes do not end a string early.
+        assert_eq!(
+            JsonOutputParser.parse(r#"{"q": "say \"hi,}\" now", "p": "C:\\",} done"#).unwrap(),
+            json!({"q": "say \"hi,}\" now", "p": "C:\\"})
+        );
+        assert_eq!(
+            remove_trailing_commas(r#"{"a": "x\",]", "b": [1,],}"#),
+            r#"{"a": "x\",]", "b": [1]}"#
+        );
+
+        let err = JsonOutputParser.parse("no json here").unwrap_err();
+        assert_eq!(err.downcast_ref::<OutputParserError>().unwrap().output, "no json here");
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Person {
+            name: String,
+            age: u32,
+        }
+        let parser = TypedOutputParser::<Person>::new(r#"{"name": string, "age": number}"#);
+        assert_eq!(
+            parser.parse(r#"{"name": "Ada", "age": 36,}"#).unwrap(),
+            Person { name: "Ada".to_string(), age: 36 }
+        );
+        assert!(parser.parse(r#"{"name": "Ada"}"#).is_err());
+    }
+
+    #[test]
+    fn test_simple_parsers() {
+        assert_eq!(CommaSeparatedListOutputParser.parse(" red, green ,blue ").unwrap(), ["red", "green", "blue"]);
+        assert_eq!(
+            NumberedListOutputParser::default().parse("Ideas:\n1. Tea\n2) Coffee\n\nEnjoy").unwrap(),
+            ["Tea", "Coffee"]
+        );
+
+        let regex = RegexOutputParser::new(r"Score: (?P<score>\d+)/10 \((?P<reason>.+)\)").unwrap();
+        let fields = regex.parse("Score: 8/10 (well written)").unwrap();
+        assert_eq!(fields["score"], "8");
+        assert_eq!(fields["reason"], "well written");
+        assert!(regex.parse("no score").is_err());
+
+        let sentiment = EnumOutputParser::new(&["Positive", "Negative"]);
+        assert_eq!(sentiment.parse(" negative.\n").unwrap(), "Negative");
+        assert!(sentiment.parse("neutral").is_err());
+
+        assert!(BooleanOutputParser.parse("YES").unwrap());
+        assert!(!BooleanOutputParser.parse("no.").unwrap());
+        assert!(BooleanOutputParser.parse("maybe").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_auto_fix_retries_with_error() {
+        let llm = ScriptedLLM::new(&["{name: Ada}", "{\"name\": \"Ada\"}"]);
+        let chain = LLMChain::new(PromptTemplate::from_template("Describe {who}.").unwrap(), llm.clone())
+            .with_minify(MinifyPolicy::Off)
+            .with_output_parser(JsonOutputParser)
+            .with_auto_fix(2);
+        assert_eq!(
+            chain.call(HashMap::from([("who".to_string(), "Ada".to_string())])).await.unwrap(),
+            json!({"name": "Ada"})
+        );
+
+        let prompts = llm.prompts.lock().unwrap();
+        assert_eq!(prompts[0], "Describe Ada.\n\nRespond with a single valid JSON value and nothing else.");
+        assert!(prompts[1].contains("Completion:\n{name: Ada}"));
+        assert!(prompts[1].contains("Invalid JSON"));
+    }
+
+    #[tokio::test]
+    async fn test_format_instructions_variable() {
+        let llm = ScriptedLLM::new(&["yes"]);
+        let prompt = PromptTemplate::from_template("{format_instructions}\nIs {x} even?").unwrap();
+        let chain = LLMChain::new(prompt, llm.clone())
+            .with_minify(MinifyPolicy::Off)
+            .with_output_parser(BooleanOutputParser);
+        assert_eq!(chain.chain().input_variables(), ["x"]);
+
+        assert!(chain.call(HashMap::from([("x".to_string(), "4".to_string())])).await.unwrap());
+        assert_eq!(llm.prompts.lock().unwrap()[0], "Respond with YES or NO.\nIs 4 even?");
+    }
+}
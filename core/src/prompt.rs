@@ -1,35 +1,362 @@
 use std::collections::HashMap;
-use anyhow::{Result, anyhow};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PromptError {
+    #[error("Missing variable: {0}")]
+    MissingVariable(String),
+    #[error("Template uses {{{0}}} but it is not listed in input_variables")]
+    UndeclaredVariable(String),
+    #[error("Input variable {0} does not appear in the template")]
+    UnusedVariable(String),
+    #[error("Invalid template at byte {position}: {message}")]
+    Parse { position: usize, message: String },
+    #[error("Jinja template error: {0}")]
+    Jinja(String),
+}
+
+/// Syntax of a `PromptTemplate`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TemplateFormat {
+    /// `{variable}` placeholders with `{{`/`}}` escapes.
+    #[default]
+    #[serde(rename = "f-string")]
+    FString,
+    /// Jinja-compatible syntax (`{{ var }}`, `{% if %}`, `{% for %}`, filters), rendered by
+    /// minijinja without access to files or other templates.
+    #[serde(rename = "jinja2")]
+    Jinja2,
+}
+
+impl FromStr for TemplateFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "f-string" | "fstring" | "f_string" => Ok(Self::FString),
+            "jinja2" | "jinja" => Ok(Self::Jinja2),
+            other => Err(anyhow::anyhow!("Unknown template format: {}", other)),
+        }
+    }
+}
+
+/// Upper bound on instructions per render, so a runaway loop cannot hang a chain.
+const JINJA_FUEL: u64 = 1_000_000;
+
+fn jinja_environment() -> minijinja::Environment<'static> {
+    let mut env = minijinja::Environment::new();
+    // Undefined variables may be tested in `{% if %}` but fail when printed or iterated.
+    env.set_undefined_behavior(minijinja::UndefinedBehavior::SemiStrict);
+    env.set_keep_trailing_newline(true);
+    env.set_fuel(Some(JINJA_FUEL));
+    env
+}
+
+fn jinja_error(error: minijinja::Error) -> anyhow::Error {
+    PromptError::Jinja(error.to_string()).into()
+}
+
+/// Top-level variables a Jinja template reads, excluding built-in globals like `range`.
+fn jinja_variables(template: &str) -> Result<Vec<String>> {
+    let env = jinja_environment();
+    let compiled = env.template_from_str(template).map_err(jinja_error)?;
+    let globals: Vec<&str> = env.globals().map(|(name, _)| name).collect();
+    let mut names: Vec<String> = compiled
+        .undeclared_variables(false)
+        .into_iter()
+        .filter(|name| !globals.contains(&name.as_str()))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// A piece of a parsed f-string style template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment<'a> {
+    Literal(String),
+    Variable(&'a str),
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a template into literals and `{variable}` references.
+/// `{{` and `}}` are escapes for literal braces; anything else in braces must be an identifier.
+fn parse(template: &str) -> std::result::Result<Vec<Segment<'_>>, PromptError> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.char_indices().peekable();
+
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '{' if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if matches!(chars.peek(), Some((_, '}'))) => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let start = pos + 1;
+                let end = template[start..].find('}').map(|i| start + i).ok_or_else(|| PromptError::Parse {
+                    position: pos,
+                    message: "unclosed '{' (use '{{' for a literal brace)".to_string(),
+                })?;
+                let name = &template[start..end];
+                if !is_identifier(name) {
+                    return Err(PromptError::Parse {
+                        position: pos,
+                        message: format!("'{{{}}}' is not a valid variable name (use '{{{{' and '}}}}' for literal braces)", name),
+                    });
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Variable(name));
+                while matches!(chars.peek(), Some((i, _)) if *i <= end) {
+                    chars.next();
+                }
+            }
+            '}' => {
+                return Err(PromptError::Parse {
+                    position: pos,
+                    message: "single '}' (use '}}' for a literal brace)".to_string(),
+                });
+            }
+            _ => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// Variables referenced by the template, in order of first appearance.
+fn referenced_variables(segments: &[Segment<'_>]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for segment in segments {
+        if let Segment::Variable(name) = segment {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Computes a partial value at format time, e.g. the current date.
+pub type PartialFn = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+/// A value bound to a template variable ahead of `format`.
+#[derive(Clone)]
+pub enum PartialValue {
+    Static(String),
+    Dynamic(PartialFn),
+}
+
+impl PartialValue {
+    fn resolve(&self) -> Result<String> {
+        match self {
+            Self::Static(value) => Ok(value.clone()),
+            Self::Dynamic(f) => f(),
+        }
+    }
+}
+
+impl fmt::Debug for PartialValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Static(value) => f.debug_tuple("Static").field(value).finish(),
+            Self::Dynamic(_) => f.write_str("Dynamic(..)"),
+        }
+    }
+}
+
+/// Deserialized through `RawPromptTemplate` so stored templates are validated like new ones.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawPromptTemplate")]
 pub struct PromptTemplate {
     template: String,
     input_variables: Vec<String>,
+    /// Functions cannot be serialized; `PromptFile` saves static partials instead.
+    #[serde(skip)]
+    partial_variables: HashMap<String, PartialValue>,
+    #[serde(default)]
+    template_format: TemplateFormat,
+}
+
+/// A `PromptTemplate` as stored, before its variables are checked against the template.
+#[derive(Deserialize)]
+struct RawPromptTemplate {
+    template: String,
+    input_variables: Vec<String>,
+    #[serde(default)]
+    template_format: TemplateFormat,
+}
+
+impl TryFrom<RawPromptTemplate> for PromptTemplate {
+    type Error = anyhow::Error;
+
+    /// Jinja variables are always inferred, as in `from_template_with_format`.
+    fn try_from(raw: RawPromptTemplate) -> Result<Self> {
+        match raw.template_format {
+            TemplateFormat::FString => Self::new(&raw.template, raw.input_variables),
+            format => Self::from_template_with_format(&raw.template, format),
+        }
+    }
 }
 
 impl PromptTemplate {
-    pub fn new(template: &str, input_variables: Vec<String>) -> Self {
-        Self {
+    /// Creates a template, checking that `input_variables` matches the `{placeholders}` exactly.
+    pub fn new(template: &str, input_variables: Vec<String>) -> Result<Self> {
+        let referenced = referenced_variables(&parse(template)?);
+        if let Some(undeclared) = referenced.iter().find(|v| !input_variables.contains(v)) {
+            return Err(PromptError::UndeclaredVariable(undeclared.clone()).into());
+        }
+        if let Some(unused) = input_variables.iter().find(|v| !referenced.contains(v)) {
+            return Err(PromptError::UnusedVariable(unused.clone()).into());
+        }
+        Ok(Self {
             template: template.to_string(),
             input_variables,
-        }
+            partial_variables: HashMap::new(),
+            template_format: TemplateFormat::FString,
+        })
+    }
+
+    /// Creates a template whose input variables are inferred from its `{placeholders}`.
+    pub fn from_template(template: &str) -> Result<Self> {
+        Self::from_template_with_format(template, TemplateFormat::FString)
+    }
+
+    /// Creates a template in the given syntax, inferring its input variables.
+    /// Jinja variables are listed alphabetically; conditionally used ones are included.
+    pub fn from_template_with_format(template: &str, template_format: TemplateFormat) -> Result<Self> {
+        let input_variables = match template_format {
+            TemplateFormat::FString => referenced_variables(&parse(template)?),
+            TemplateFormat::Jinja2 => jinja_variables(template)?,
+        };
+        Ok(Self {
+            template: template.to_string(),
+            input_variables,
+            partial_variables: HashMap::new(),
+            template_format,
+        })
+    }
+
+    /// Binds `name` to a fixed value, removing it from the input variables.
+    pub fn with_partial(self, name: &str, value: &str) -> Result<Self> {
+        self.with_partial_value(name, PartialValue::Static(value.to_string()))
+    }
+
+    /// Binds `name` to a function called on every `format`.
+    pub fn with_partial_fn<F>(self, name: &str, f: F) -> Result<Self>
+    where
+        F: Fn() -> Result<String> + Send + Sync + 'static,
+    {
+        self.with_partial_value(name, PartialValue::Dynamic(Arc::new(f)))
+    }
+
+    pub fn with_partial_value(mut self, name: &str, value: PartialValue) -> Result<Self> {
+        let position = self.input_variables.iter().position(|v| v == name)
+            .ok_or_else(|| PromptError::UnusedVariable(name.to_string()))?;
+        self.input_variables.remove(position);
+        self.partial_variables.insert(name.to_string(), value);
+        Ok(self)
+    }
+
+    pub fn partial_variables(&self) -> &HashMap<String, PartialValue> {
+        &self.partial_variables
+    }
+
+    /// Loads a YAML, JSON or plain-text prompt file (see `PromptFile`).
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        crate::prompt_hub::PromptFile::load(path)?.to_prompt()
+    }
+
+    /// Saves the template in the format given by the file extension, without metadata.
+    /// Static partials are saved with it; function partials cannot be.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        crate::prompt_hub::PromptFile::new(self, Default::default())?.save(path)
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn input_variables(&self) -> &[String] {
+        &self.input_variables
+    }
+
+    pub fn template_format(&self) -> TemplateFormat {
+        self.template_format
     }
 
     /// Formats the template by replacing {variable} with values.
-    /// Also performs basic cost-saving minification (trimming).
+    /// Values are inserted verbatim, so braces inside them are never substituted again.
+    /// Explicit values take precedence over partials.
     pub fn format(&self, values: &HashMap<String, String>) -> Result<String> {
-        let mut result = self.template.clone();
-        for var in &self.input_variables {
-            let value = values.get(var).ok_or_else(|| anyhow!("Missing variable: {}", var))?;
-            result = result.replace(&format!("{{{}}}", var), value);
+        if self.template_format == TemplateFormat::Jinja2 {
+            let values = values.iter()
+                .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+                .collect();
+            return self.render_jinja(values);
+        }
+
+        let mut result = String::with_capacity(self.template.len());
+        for segment in parse(&self.template)? {
+            match segment {
+                Segment::Literal(text) => result.push_str(&text),
+                Segment::Variable(name) => match (values.get(name), self.partial_variables.get(name)) {
+                    (Some(value), _) => result.push_str(value),
+                    (None, Some(partial)) => result.push_str(&partial.resolve()?),
+                    (None, None) => return Err(PromptError::MissingVariable(name.to_string()).into()),
+                },
+            }
         }
         Ok(result)
     }
 
+    /// Formats with structured values, e.g. a list of documents for a Jinja `{% for %}` loop.
+    /// F-string templates insert strings as-is and other values as JSON.
+    pub fn format_values(&self, values: &HashMap<String, serde_json::Value>) -> Result<String> {
+        match self.template_format {
+            TemplateFormat::Jinja2 => self.render_jinja(values.clone()),
+            TemplateFormat::FString => {
+                let values = values.iter()
+                    .map(|(k, v)| match v {
+                        serde_json::Value::String(s) => (k.clone(), s.clone()),
+                        other => (k.clone(), other.to_string()),
+                    })
+                    .collect();
+                self.format(&values)
+            }
+        }
+    }
+
+    fn render_jinja(&self, mut context: HashMap<String, serde_json::Value>) -> Result<String> {
+        for (name, partial) in &self.partial_variables {
+            if !context.contains_key(name) {
+                context.insert(name.clone(), serde_json::Value::String(partial.resolve()?));
+            }
+        }
+        jinja_environment().render_str(&self.template, context).map_err(jinja_error)
+    }
+
     /// Aggressive minification to reduce token cost.
     /// 1. Trims whitespace.
     /// 2. Replaces multiple spaces/newlines with single ones (context dependent).
+    ///
+    /// `LLMChain` uses `crate::minify::minify` with a configurable `MinifyPolicy` instead.
     pub fn minify(&self, populated_prompt: &str) -> String {
         // Simple implementation: 
         // 1. Split by whitespace and rejoin with single space
@@ -49,13 +376,62 @@ impl PromptTemplate {
     }
 }
 
+/// Renders named sub-templates in order and injects each result into the final template.
+///
+/// Every stage sees the caller's values plus the output of the stages before it, so
+/// fragments like a shared persona or output-format block can be composed freely.
+#[derive(Debug, Clone)]
+pub struct PipelinePromptTemplate {
+    final_prompt: PromptTemplate,
+    stages: Vec<(String, PromptTemplate)>,
+}
+
+impl PipelinePromptTemplate {
+    pub fn new(final_prompt: PromptTemplate) -> Self {
+        Self { final_prompt, stages: Vec::new() }
+    }
+
+    pub fn with_stage(mut self, name: &str, prompt: PromptTemplate) -> Self {
+        self.stages.push((name.to_string(), prompt));
+        self
+    }
+
+    /// Variables the caller must supply: everything used by the stages and the final
+    /// template that is not produced by an earlier stage.
+    pub fn input_variables(&self) -> Vec<String> {
+        let mut produced: Vec<&str> = Vec::new();
+        let mut names: Vec<String> = Vec::new();
+        let prompts = self.stages.iter()
+            .map(|(name, prompt)| (Some(name.as_str()), prompt))
+            .chain(std::iter::once((None, &self.final_prompt)));
+        for (stage_name, prompt) in prompts {
+            for name in prompt.input_variables() {
+                if !produced.contains(&name.as_str()) && !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            produced.extend(stage_name);
+        }
+        names
+    }
+
+    pub fn format(&self, values: &HashMap<String, String>) -> Result<String> {
+        let mut values = values.clone();
+        for (name, prompt) in &self.stages {
+            let rendered = prompt.format(&values)?;
+            values.insert(name.clone(), rendered);
+        }
+        self.final_prompt.format(&values)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_format() {
-        let tmpl = PromptTemplate::new("Hello {name}!", vec!["name".to_string()]);
+        let tmpl = PromptTemplate::new("Hello {name}!", vec!["name".to_string()]).unwrap();
         let mut values = HashMap::new();
         values.insert("name".to_string(), "World".to_string());
         
@@ -64,7 +440,7 @@ mod tests {
 
     #[test]
     fn test_minify() {
-        let tmpl = PromptTemplate::new("", vec![]);
+        let tmpl = PromptTemplate::new("", vec![]).unwrap();
         let input = "
         Hello   
         
@@ -73,4 +449,124 @@ mod tests {
         // Should become "Hello\nWorld"
         assert_eq!(tmpl.minify(input), "Hello\nWorld");
     }
+
+    #[test]
+    fn test_brace_escaping_and_single_substitution() {
+        let tmpl = PromptTemplate::from_template("Reply as JSON like {{\"name\": \"{name}\"}}. Note: {note}").unwrap();
+        assert_eq!(tmpl.input_variables(), ["name", "note"]);
+
+        let values = HashMap::from([
+            ("name".to_string(), "{note}".to_string()),
+            ("note".to_string(), "hi".to_string()),
+        ]);
+        assert_eq!(tmpl.format(&values).unwrap(), "Reply as JSON like {\"name\": \"{note}\"}. Note: hi");
+    }
+
+    #[test]
+    fn test_partial_variables() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let tmpl = PromptTemplate::from_template("[{date}] {persona}: {question}").unwrap()
+            .with_partial("persona", "Tutor").unwrap()
+            .with_partial_fn("date", move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok("2024-01-01".to_string())
+            }).unwrap();
+        assert_eq!(tmpl.input_variables(), ["question"]);
+
+        let mut values = HashMap::from([("question".to_string(), "Why?".to_string())]);
+        assert_eq!(tmpl.format(&values).unwrap(), "[2024-01-01] Tutor: Why?");
+        assert_eq!(tmpl.format(&values).unwrap(), "[2024-01-01] Tutor: Why?");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        values.insert("persona".to_string(), "Coach".to_string());
+        assert_eq!(tmpl.format(&values).unwrap(), "[2024-01-01] Coach: Why?");
+
+        let err = tmpl.with_partial("missing", "x").unwrap_err();
+        assert_eq!(err.downcast_ref::<PromptError>(), Some(&PromptError::UnusedVariable("missing".to_string())));
+    }
+
+    #[test]
+    fn test_pipeline_prompt() {
+        let pipeline = PipelinePromptTemplate::new(
+            PromptTemplate::from_template("{introduction}\n\n{example}\n\n{start}").unwrap(),
+        )
+        .with_stage("introduction", PromptTemplate::from_template("You are impersonating {person}.").unwrap())
+        .with_stage("example", PromptTemplate::from_template("Q: {example_q}\nA: {example_a}").unwrap())
+        .with_stage("start", PromptTemplate::from_template("Now, as {person}:\nQ: {input}\nA:").unwrap());
+        assert_eq!(pipeline.input_variables(), ["person", "example_q", "example_a", "input"]);
+
+        let values: HashMap<String, String> = [
+            ("person", "Elon Musk"),
+            ("example_q", "Favorite car?"),
+            ("example_a", "Tesla"),
+            ("input", "Favorite social media site?"),
+        ].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(
+            pipeline.format(&values).unwrap(),
+            "You are impersonating Elon Musk.\n\nQ: Favorite car?\nA: Tesla\n\nNow, as Elon Musk:\nQ: Favorite social media site?\nA:"
+        );
+    }
+
+    #[test]
+    fn test_jinja_conditionals_and_loops() {
+        let tmpl = PromptTemplate::from_template_with_format(
+            "{% if persona %}You are {{ persona }}.\n{% endif %}{% for doc in docs %}[{{ loop.index }}] {{ doc.title | upper }}\n{% endfor %}Q: {{ question }}",
+            TemplateFormat::Jinja2,
+        ).unwrap();
+        assert_eq!(tmpl.input_variables(), ["docs", "persona", "question"]);
+
+        let values: HashMap<String, serde_json::Value> = serde_json::from_str(
+            r#"{"docs": [{"title": "rust"}, {"title": "tokio"}], "question": "Why?"}"#,
+        ).unwrap();
+        assert_eq!(tmpl.format_values(&values).unwrap(), "[1] RUST\n[2] TOKIO\nQ: Why?");
+
+        let tmpl = tmpl.with_partial("persona", "a tutor").unwrap();
+        assert!(tmpl.format_values(&values).unwrap().starts_with("You are a tutor.\n[1]"));
+
+        let err = tmpl.format(&HashMap::new()).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PromptError>(), Some(PromptError::Jinja(_))));
+    }
+
+    #[test]
+    fn test_jinja_is_sandboxed() {
+        let err = PromptTemplate::from_template_with_format("{% if %}", TemplateFormat::Jinja2).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PromptError>(), Some(PromptError::Jinja(_))));
+
+        // Templates cannot pull in other templates or files.
+        assert!(PromptTemplate::from_template_with_format("{% include 'secrets.txt' %}", TemplateFormat::Jinja2).is_err());
+
+        let runaway = PromptTemplate::from_template_with_format(
+            "{% for i in range(10000) %}{% for j in range(10000) %}x{% endfor %}{% endfor %}",
+            TemplateFormat::Jinja2,
+        ).unwrap();
+        assert!(runaway.format(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_construction_validation() {
+        let err = PromptTemplate::new("Hi {name} from {place}", vec!["name".to_string()]).unwrap_err();
+        assert_eq!(err.downcast_ref::<PromptError>(), Some(&PromptError::UndeclaredVariable("place".to_string())));
+
+        let err = PromptTemplate::new("Hi {name}", vec!["name".to_string(), "extra".to_string()]).unwrap_err();
+        assert_eq!(err.downcast_ref::<PromptError>(), Some(&PromptError::UnusedVariable("extra".to_string())));
+
+        for bad in ["{\"a\": 1}", "open {name", "close }", "{}"] {
+            let err = PromptTemplate::from_template(bad).unwrap_err();
+            assert!(matches!(err.downcast_ref::<PromptError>(), Some(PromptError::Parse { .. })), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_deserialization_validates() {
+        let template = PromptTemplate::from_template("Hi {name}").unwrap();
+        let json = serde_json::to_string(&template).unwrap();
+        let loaded: PromptTemplate = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.input_variables(), ["name"]);
+
+        let err = serde_json::from_str::<PromptTemplate>(r#"{"template": "Hi {name} from {place}", "input_variables": ["name"]}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("Template uses {place} but it is not listed"), "{}", err);
+        assert!(serde_json::from_str::<PromptTemplate>(r#"{"template": "open {name", "input_variables": ["name"]}"#).is_err());
+    }
 }
@@ -0,0 +1,402 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::prompt::{PartialValue, PromptTemplate, TemplateFormat};
+
+/// Extensions tried, in order, when resolving a prompt in a `PromptHub`.
+const EXTENSIONS: &[&str] = &["yaml", "yml", "json", "txt"];
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PromptHubError {
+    #[error("Invalid prompt reference '{0}' (expected name or name@version)")]
+    InvalidReference(String),
+    #[error("Prompt '{0}' not found")]
+    NotFound(String),
+    #[error("Prompt '{name}' has no version '{version}'")]
+    VersionNotFound { name: String, version: String },
+    #[error("Prompt '{name}@{version}' already exists")]
+    VersionExists { name: String, version: String },
+    #[error("Prompt metadata must set a version to be published")]
+    MissingVersion,
+    #[error("Partial '{0}' is computed by a function and cannot be saved")]
+    DynamicPartial(String),
+}
+
+/// Descriptive fields stored alongside a prompt.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Models the prompt was written and tested for, e.g. `gpt-4o`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_hints: Vec<String>,
+}
+
+/// A prompt as stored on disk.
+///
+/// YAML and JSON files hold the metadata fields next to `template` (and optionally
+/// `input_variables`, which include any partials). Plain-text files are the template
+/// itself, optionally preceded by YAML front matter between `---` lines that holds the
+/// metadata, `template_format` and `partial_variables`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptFile {
+    #[serde(flatten)]
+    pub metadata: PromptMetadata,
+    pub template: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_variables: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "is_f_string")]
+    pub template_format: TemplateFormat,
+    /// Fixed values bound ahead of formatting.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partial_variables: BTreeMap<String, String>,
+}
+
+/// Everything in a `PromptFile` except the template, as written in plain-text front matter.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct FrontMatter {
+    #[serde(flatten)]
+    metadata: PromptMetadata,
+    #[serde(default, skip_serializing_if = "is_f_string")]
+    template_format: TemplateFormat,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    partial_variables: BTreeMap<String, String>,
+}
+
+fn is_f_string(format: &TemplateFormat) -> bool {
+    *format == TemplateFormat::FString
+}
+
+impl PromptFile {
+    /// Fails with `PromptHubError::DynamicPartial` when a partial is a function.
+    pub fn new(template: &PromptTemplate, metadata: PromptMetadata) -> Result<Self> {
+        let mut partial_variables = BTreeMap::new();
+        for (name, value) in template.partial_variables() {
+            match value {
+                PartialValue::Static(value) => partial_variables.insert(name.clone(), value.clone()),
+                PartialValue::Dynamic(_) => return Err(PromptHubError::DynamicPartial(name.clone()).into()),
+            };
+        }
+        let mut input_variables = template.input_variables().to_vec();
+        input_variables.extend(partial_variables.keys().cloned());
+        Ok(Self {
+            metadata,
+            template: template.template().to_string(),
+            input_variables: Some(input_variables),
+            template_format: template.template_format(),
+            partial_variables,
+        })
+    }
+
+    /// Validates the stored template and binds its partials; variables are inferred when
+    /// the file lists none and always for Jinja templates.
+    pub fn to_prompt(&self) -> Result<PromptTemplate> {
+        let mut prompt = match (&self.input_variables, self.template_format) {
+            (Some(vars), TemplateFormat::FString) => PromptTemplate::new(&self.template, vars.clone())?,
+            (_, format) => PromptTemplate::from_template_with_format(&self.template, format)?,
+        };
+        for (name, value) in &self.partial_variables {
+            prompt = prompt.with_partial(name, value)?;
+        }
+        Ok(prompt)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read prompt file {}", path.display()))?;
+        Self::parse(&content, extension(path))
+            .with_context(|| format!("Failed to parse prompt file {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.render(extension(path))?)
+            .with_context(|| format!("Failed to write prompt file {}", path.display()))
+    }
+
+    fn parse(content: &str, extension: &str) -> Result<Self> {
+        match extension {
+            "yaml" | "yml" => Ok(serde_yaml::from_str(content)?),
+            "json" => Ok(serde_json::from_str(content)?),
+            _ => {
+                // Files edited on Windows may use CRLF line endings.
+                let content = content.replace("\r\n", "\n");
+                let front_matter = content
+                    .strip_prefix("---\n")
+                    .and_then(|rest| rest.split_once("\n---\n"));
+                let (header, template) = match front_matter {
+                    Some((header, template)) => (serde_yaml::from_str(header)?, template),
+                    None => (FrontMatter::default(), content.as_str()),
+                };
+                Ok(Self {
+                    metadata: header.metadata,
+                    template: template.to_string(),
+                    input_variables: None,
+                    template_format: header.template_format,
+                    partial_variables: header.partial_variables,
+                })
+            }
+        }
+    }
+
+    fn render(&self, extension: &str) -> Result<String> {
+        match extension {
+            "yaml" | "yml" => Ok(serde_yaml::to_string(self)?),
+            "json" => Ok(serde_json::to_string_pretty(self)?),
+            _ => {
+                let header = FrontMatter {
+                    metadata: self.metadata.clone(),
+                    template_format: self.template_format,
+                    partial_variables: self.partial_variables.clone(),
+                };
+                if header == FrontMatter::default() {
+                    Ok(self.template.clone())
+                } else {
+                    Ok(format!("---\n{}---\n{}", serde_yaml::to_string(&header)?, self.template))
+                }
+            }
+        }
+    }
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+/// Orders versions numerically by dot-separated parts (`1.10` > `1.9`), then as text.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<Option<u64>> {
+        v.trim_start_matches('v').split('.').map(|p| p.parse().ok()).collect()
+    };
+    parts(a).cmp(&parts(b)).then_with(|| a.cmp(b))
+}
+
+/// A directory of versioned prompts laid out as `<root>/<name>/<version>.<ext>`.
+///
+/// References are `name@version`, or just `name` for the highest version. A prompt
+/// without versions can also live directly at `<root>/<name>.<ext>`.
+#[derive(Debug, Clone)]
+pub struct PromptHub {
+    root: PathBuf,
+}
+
+impl PromptHub {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn parse_reference(reference: &str) -> Result<(&str, Option<&str>)> {
+        let (name, version) = match reference.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (reference, None),
+        };
+        let valid = |s: &str| {
+            !s.is_empty() && s != "." && s != ".." && !s.contains(['/', '\\', '@'])
+        };
+        if !valid(name) || version.is_some_and(|v| !valid(v)) {
+            return Err(PromptHubError::InvalidReference(reference.to_string()).into());
+        }
+        Ok((name, version))
+    }
+
+    /// Appends each known extension to `stem` (versions like `1.10` contain dots, so
+    /// `Path::with_extension` would cut them short).
+    fn find_file(dir: &Path, stem: &str) -> Option<PathBuf> {
+        EXTENSIONS.iter().map(|ext| dir.join(format!("{}.{}", stem, ext))).find(|p| p.is_file())
+    }
+
+    /// Versions of `name`, lowest first.
+    pub fn versions(&self, name: &str) -> Result<Vec<String>> {
+        let dir = self.root.join(name);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut versions: Vec<String> = std::fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| EXTENSIONS.contains(&extension(path)))
+            .filter_map(|path| path.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+            .collect();
+        versions.sort_by(|a, b| compare_versions(a, b));
+        versions.dedup();
+        Ok(versions)
+    }
+
+    /// Names of all prompts in the hub, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut names: Vec<String> = std::fs::read_dir(&self.root)
+            .with_context(|| format!("Failed to read prompt hub {}", self.root.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_dir() || EXTENSIONS.contains(&extension(path)))
+            .filter_map(|path| path.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Path of the file a reference resolves to.
+    pub fn resolve(&self, reference: &str) -> Result<PathBuf> {
+        let (name, version) = Self::parse_reference(reference)?;
+        match version {
+            Some(version) => Self::find_file(&self.root.join(name), version).ok_or_else(|| {
+                PromptHubError::VersionNotFound { name: name.to_string(), version: version.to_string() }.into()
+            }),
+            None => match self.versions(name)?.last() {
+                Some(latest) => Self::find_file(&self.root.join(name), latest)
+                    .ok_or_else(|| PromptHubError::NotFound(name.to_string()).into()),
+                None => Self::find_file(&self.root, name)
+                    .ok_or_else(|| PromptHubError::NotFound(name.to_string()).into()),
+            },
+        }
+    }
+
+    pub fn get(&self, reference: &str) -> Result<PromptFile> {
+        PromptFile::load(self.resolve(reference)?)
+    }
+
+    pub fn load(&self, reference: &str) -> Result<PromptTemplate> {
+        self.get(reference)?.to_prompt()
+    }
+
+    /// Writes `file` as `<name>/<version>.yaml`. Published versions are never overwritten.
+    pub fn publish(&self, name: &str, file: &PromptFile) -> Result<PathBuf> {
+        let version = file.metadata.version.as_deref().ok_or(PromptHubError::MissingVersion)?;
+        Self::parse_reference(&format!("{}@{}", name, version))?;
+        file.to_prompt()?;
+
+        if Self::find_file(&self.root.join(name), version).is_some() {
+            return Err(PromptHubError::VersionExists { name: name.to_string(), version: version.to_string() }.into());
+        }
+        let path = self.root.join(name).join(format!("{}.yaml", version));
+        let mut file = file.clone();
+        file.metadata.name.get_or_insert_with(|| name.to_string());
+        file.save(&path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn temp_dir(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mini_langchain_{}_{}", label, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_round_trip_formats() {
+        let dir = temp_dir("prompt_files");
+        let template = PromptTemplate::from_template("Summarize {text} in {n} words.").unwrap();
+        let file = PromptFile::new(&template, PromptMetadata {
+            version: Some("1.0".to_string()),
+            author: Some("ops".to_string()),
+            model_hints: vec!["gpt-4o-mini".to_string()],
+            ..Default::default()
+        }).unwrap();
+
+        for name in ["p.yaml", "p.json", "p.txt"] {
+            file.save(dir.join(name)).unwrap();
+            let loaded = PromptFile::load(dir.join(name)).unwrap();
+            assert_eq!(loaded.metadata, file.metadata, "{}", name);
+            assert_eq!(loaded.to_prompt().unwrap().input_variables(), ["text", "n"], "{}", name);
+        }
+        let text = std::fs::read_to_string(dir.join("p.txt")).unwrap();
+        assert!(text.starts_with("---\nversion: '1.0'\nauthor: ops\n"));
+        assert!(text.ends_with("---\nSummarize {text} in {n} words."));
+
+        let jinja = PromptTemplate::from_template_with_format("{% for d in docs %}{{ d }}{% endfor %}", TemplateFormat::Jinja2).unwrap();
+        PromptFile::new(&jinja, PromptMetadata::default()).unwrap().save(dir.join("j.yaml")).unwrap();
+        assert!(std::fs::read_to_string(dir.join("j.yaml")).unwrap().contains("template_format: jinja2"));
+        assert_eq!(PromptTemplate::load(dir.join("j.yaml")).unwrap().template_format(), TemplateFormat::Jinja2);
+        jinja.save(dir.join("j.txt")).unwrap();
+        assert!(std::fs::read_to_string(dir.join("j.txt")).unwrap().starts_with("---\ntemplate_format: jinja2\n---\n"));
+        assert_eq!(PromptTemplate::load(dir.join("j.txt")).unwrap().template_format(), TemplateFormat::Jinja2);
+
+        std::fs::write(dir.join("crlf.txt"), "---\r\nversion: '2.0'\r\n---\r\nHello {name}\r\n").unwrap();
+        let crlf = PromptFile::load(dir.join("crlf.txt")).unwrap();
+        assert_eq!(crlf.metadata.version.as_deref(), Some("2.0"));
+        assert_eq!(crlf.template, "Hello {name}\n");
+
+        std::fs::write(dir.join("plain.txt"), "Hello {name}").unwrap();
+        assert_eq!(PromptFile::load(dir.join("plain.txt")).unwrap().template, "Hello {name}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip_partials() {
+        let dir = temp_dir("prompt_partials");
+        let template = PromptTemplate::from_template("{greeting}, {name}! Today is {day}.").unwrap()
+            .with_partial("greeting", "Hello").unwrap()
+            .with_partial("day", "Monday").unwrap();
+        let values = HashMap::from([("name".to_string(), "Ada".to_string())]);
+
+        for name in ["p.yaml", "p.json", "p.txt"] {
+            template.save(dir.join(name)).unwrap();
+            let loaded = PromptTemplate::load(dir.join(name)).unwrap();
+            assert_eq!(loaded.input_variables(), ["name"], "{}", name);
+            assert_eq!(loaded.format(&values).unwrap(), "Hello, Ada! Today is Monday.", "{}", name);
+        }
+
+        let dynamic = PromptTemplate::from_template("{day}: {task}").unwrap()
+            .with_partial_fn("day", || Ok("Monday".to_string())).unwrap();
+        let err = dynamic.save(dir.join("d.yaml")).unwrap_err();
+        assert_eq!(err.downcast_ref::<PromptHubError>(), Some(&PromptHubError::DynamicPartial("day".to_string())));
+        assert!(!dir.join("d.yaml").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_hub_resolves_versions() {
+        let dir = temp_dir("prompt_hub");
+        let hub = PromptHub::new(&dir);
+        for (version, template) in [("1.9", "v1.9 {q}"), ("1.10", "v1.10 {q}"), ("1.2", "v1.2 {q}")] {
+            let file = PromptFile {
+                metadata: PromptMetadata { version: Some(version.to_string()), ..Default::default() },
+                template: template.to_string(),
+                input_variables: None,
+                template_format: TemplateFormat::FString,
+                partial_variables: BTreeMap::new(),
+            };
+            hub.publish("qa", &file).unwrap();
+        }
+        std::fs::write(dir.join("greeting.txt"), "Hi {name}").unwrap();
+
+        assert_eq!(hub.versions("qa").unwrap(), ["1.2", "1.9", "1.10"]);
+        assert_eq!(hub.list().unwrap(), ["greeting", "qa"]);
+        assert_eq!(hub.load("qa").unwrap().template(), "v1.10 {q}");
+        assert_eq!(hub.load("qa@1.9").unwrap().template(), "v1.9 {q}");
+        assert_eq!(hub.get("qa@1.2").unwrap().metadata.name.as_deref(), Some("qa"));
+        assert_eq!(hub.load("greeting").unwrap().template(), "Hi {name}");
+
+        let err = hub.load("qa@3.0").unwrap_err();
+        assert!(matches!(err.downcast_ref::<PromptHubError>(), Some(PromptHubError::VersionNotFound { .. })));
+        let err = hub.load("../etc@1").unwrap_err();
+        assert!(matches!(err.downcast_ref::<PromptHubError>(), Some(PromptHubError::InvalidReference(_))));
+        let republish = PromptFile {
+            metadata: PromptMetadata { version: Some("1.2".to_string()), ..Default::default() },
+            template: "again".to_string(),
+            input_variables: None,
+            template_format: TemplateFormat::FString,
+            partial_variables: BTreeMap::new(),
+        };
+        let err = hub.publish("qa", &republish).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PromptHubError>(), Some(PromptHubError::VersionExists { .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
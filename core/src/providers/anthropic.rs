@@ -1,4 +1,6 @@
-use crate::llm::LLM;
+use crate::llm::{Generation, TokenUsage, LLM};
+use crate::schema::{ChatMessage, Role};
+use crate::secret::ApiKey;
 use async_trait::async_trait;
 use anyhow::{Result, Context};
 use reqwest::Client;
@@ -23,6 +25,32 @@ struct Message {
 #[derive(Deserialize)]
 struct AnthropicResponse {
     content: Vec<ContentBlock>,
+    usage: Option<AnthropicUsage>,
+}
+
+/// `input_tokens` excludes prompt tokens read from or written to the prompt cache.
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
+}
+
+impl From<AnthropicUsage> for TokenUsage {
+    fn from(u: AnthropicUsage) -> Self {
+        let cached = u.cache_read_input_tokens.unwrap_or(0);
+        let prompt_tokens = u.input_tokens + u.cache_creation_input_tokens.unwrap_or(0) + cached;
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens: u.output_tokens,
+            total_tokens: prompt_tokens + u.output_tokens,
+            reasoning_tokens: None,
+            cached_tokens: u.cache_read_input_tokens,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -32,43 +60,73 @@ struct ContentBlock {
 
 pub struct AnthropicProvider {
     client: Client,
-    api_key: String,
+    api_key: ApiKey,
     model: String,
     system_prompt: Option<String>,
     max_tokens: Option<u32>,
 }
 
 impl AnthropicProvider {
+    /// Without an explicit `api_key` the key is read from `ANTHROPIC_API_KEY` on each request.
     pub fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         system_prompt: Option<String>,
         max_tokens: Option<u32>,
     ) -> Self {
         Self {
             client: Client::new(),
-            api_key,
+            api_key: ApiKey::from_option_or_env(api_key, &["ANTHROPIC_API_KEY"]),
             model,
             system_prompt,
             max_tokens,
         }
     }
+
+    /// Replaces the key source, e.g. with `ApiKey::File` or a rotating `ApiKey::Provider`.
+    pub fn with_api_key(mut self, api_key: ApiKey) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    /// System messages are lifted into the top-level `system` field, after the configured system prompt.
+    fn build_request(&self, conversation: &[ChatMessage]) -> AnthropicRequest {
+        let system: Vec<&str> = self.system_prompt.iter().map(String::as_str)
+            .chain(conversation.iter().filter(|m| m.role == Role::System).map(|m| m.content.as_str()))
+            .collect();
+        let messages = conversation.iter()
+            .filter(|m| m.role != Role::System)
+            .map(|m| Message {
+                role: if m.role == Role::AI { "assistant" } else { "user" }.to_string(),
+                content: m.content.clone(),
+            })
+            .collect();
+
+        AnthropicRequest {
+            model: self.model.clone(),
+            messages,
+            system: if system.is_empty() { None } else { Some(system.join("\n\n")) },
+            max_tokens: self.max_tokens.or(Some(1024)), // Default max tokens required by Anthropic
+        }
+    }
 }
 
 #[async_trait]
 impl LLM for AnthropicProvider {
     async fn generate(&self, prompt: &str) -> Result<String> {
-        let messages = vec![Message { role: "user".to_string(), content: prompt.to_string() }];
+        Ok(self.generate_messages(&[ChatMessage::human(prompt)]).await?.text)
+    }
 
-        let request = AnthropicRequest {
-            model: self.model.clone(),
-            messages,
-            system: self.system_prompt.clone(),
-            max_tokens: self.max_tokens.or(Some(1024)), // Default max tokens required by Anthropic
-        };
+    fn model_name(&self) -> Option<String> {
+        Some(self.model.clone())
+    }
+
+    async fn generate_messages(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        let request = self.build_request(messages);
 
+        let api_key = self.api_key.resolve()?;
         let res = self.client.post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
+            .header("x-api-key", api_key.expose_secret())
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json")
             .json(&request)
@@ -84,9 +142,10 @@ impl LLM for AnthropicProvider {
         let response: AnthropicResponse = res.json().await
             .context("Failed to parse Anthropic response")?;
 
-        response.content.first()
+        let text = response.content.first()
             .map(|c| c.text.clone())
-            .ok_or_else(|| anyhow::anyhow!("No content returned from Anthropic"))
+            .ok_or_else(|| anyhow::anyhow!("No content returned from Anthropic"))?;
+        Ok(Generation { text, usage: response.usage.map(TokenUsage::from), model: None })
     }
 }
 
@@ -107,4 +166,31 @@ mod tests {
         assert!(json.contains("\"system\":\"sys\""));
         assert!(json.contains("\"max_tokens\":100"));
     }
+
+    #[test]
+    fn test_response_usage_includes_cached_tokens() {
+        let body = r#"{"content":[{"type":"text","text":"Hello"}],
+            "usage":{"input_tokens":20,"output_tokens":7,"cache_creation_input_tokens":0,"cache_read_input_tokens":100}}"#;
+        let response: AnthropicResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(response.content[0].text, "Hello");
+        let usage = TokenUsage::from(response.usage.unwrap());
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (120, 7, 127));
+        assert_eq!(usage.cached_tokens, Some(100));
+
+        let minimal: AnthropicResponse = serde_json::from_str(r#"{"content":[{"text":"Hi"}],"usage":{"input_tokens":3,"output_tokens":1}}"#).unwrap();
+        assert_eq!(TokenUsage::from(minimal.usage.unwrap()).cached_tokens, None);
+    }
+
+    #[test]
+    fn test_system_messages_are_lifted() {
+        let provider = AnthropicProvider::new(Some("key".to_string()), "claude-3".to_string(), Some("sys".to_string()), None);
+        let request = provider.build_request(&[
+            ChatMessage::system("Be brief."),
+            ChatMessage::human("hi"),
+            ChatMessage::ai("hello"),
+        ]);
+        assert_eq!(request.system.as_deref(), Some("sys\n\nBe brief."));
+        let roles: Vec<&str> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant"]);
+    }
 }
@@ -1,13 +1,20 @@
-use crate::llm::LLM;
+use crate::llm::{Generation, TokenUsage, LLM};
+use crate::schema::{ChatMessage, Role};
+use crate::secret::ApiKey;
 use async_trait::async_trait;
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 #[derive(Serialize)]
 struct GeminiRequest {
     contents: Vec<Content>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    safety_settings: Vec<SafetySetting>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     generation_config: Option<GenerationConfig>,
 }
 
@@ -23,86 +30,329 @@ struct Part {
     text: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 struct GenerationConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+struct SafetySetting {
+    category: HarmCategory,
+    threshold: HarmBlockThreshold,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse {
     candidates: Option<Vec<Candidate>>,
+    prompt_feedback: Option<PromptFeedback>,
+    usage_metadata: Option<UsageMetadata>,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Candidate {
-    content: ContentRes,
+    content: Option<ContentRes>,
+    finish_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
 }
 
 #[derive(Deserialize)]
 struct ContentRes {
+    #[serde(default)]
     parts: Vec<PartRes>,
 }
 
 #[derive(Deserialize)]
 struct PartRes {
-    text: String,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+    #[serde(default)]
+    total_token_count: u32,
+    thoughts_token_count: Option<u32>,
+    cached_content_token_count: Option<u32>,
+}
+
+/// Per-category safety verdict attached to a blocked prompt or candidate.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: String,
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+/// Returned (through `anyhow`) when Gemini refuses to answer for safety reasons,
+/// so callers can `downcast_ref::<GeminiBlockedError>()` instead of matching strings.
+#[derive(Debug, thiserror::Error)]
+pub enum GeminiBlockedError {
+    #[error("Google Gemini blocked the prompt: {reason}")]
+    Prompt { reason: String, safety_ratings: Vec<SafetyRating> },
+    #[error("Google Gemini blocked the response: finish reason {finish_reason}")]
+    Response { finish_reason: String, safety_ratings: Vec<SafetyRating> },
+}
+
+/// Finish reasons that mean the candidate was withheld rather than completed.
+const BLOCKING_FINISH_REASONS: &[&str] = &[
+    "SAFETY", "RECITATION", "BLOCKLIST", "PROHIBITED_CONTENT", "SPII", "IMAGE_SAFETY",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum HarmCategory {
+    #[serde(rename = "HARM_CATEGORY_HARASSMENT")]
+    Harassment,
+    #[serde(rename = "HARM_CATEGORY_HATE_SPEECH")]
+    HateSpeech,
+    #[serde(rename = "HARM_CATEGORY_SEXUALLY_EXPLICIT")]
+    SexuallyExplicit,
+    #[serde(rename = "HARM_CATEGORY_DANGEROUS_CONTENT")]
+    DangerousContent,
+    #[serde(rename = "HARM_CATEGORY_CIVIC_INTEGRITY")]
+    CivicIntegrity,
+}
+
+impl FromStr for HarmCategory {
+    type Err = anyhow::Error;
+
+    /// Accepts either the API name (`HARM_CATEGORY_HATE_SPEECH`) or the short form (`hate_speech`).
+    fn from_str(s: &str) -> Result<Self> {
+        let upper = s.to_ascii_uppercase();
+        match upper.trim_start_matches("HARM_CATEGORY_") {
+            "HARASSMENT" => Ok(Self::Harassment),
+            "HATE_SPEECH" => Ok(Self::HateSpeech),
+            "SEXUALLY_EXPLICIT" => Ok(Self::SexuallyExplicit),
+            "DANGEROUS_CONTENT" => Ok(Self::DangerousContent),
+            "CIVIC_INTEGRITY" => Ok(Self::CivicIntegrity),
+            _ => Err(anyhow!("Unknown Gemini harm category: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum HarmBlockThreshold {
+    BlockNone,
+    BlockOnlyHigh,
+    BlockMediumAndAbove,
+    BlockLowAndAbove,
+    Off,
+}
+
+impl FromStr for HarmBlockThreshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "BLOCK_NONE" => Ok(Self::BlockNone),
+            "BLOCK_ONLY_HIGH" => Ok(Self::BlockOnlyHigh),
+            "BLOCK_MEDIUM_AND_ABOVE" => Ok(Self::BlockMediumAndAbove),
+            "BLOCK_LOW_AND_ABOVE" => Ok(Self::BlockLowAndAbove),
+            "OFF" => Ok(Self::Off),
+            _ => Err(anyhow!("Unknown Gemini block threshold: {}", s)),
+        }
+    }
 }
 
 pub struct GoogleGenAIProvider {
     client: Client,
-    api_key: String,
+    api_key: ApiKey,
     model: String,
     temperature: Option<f64>,
     max_tokens: Option<u32>,
+    system_instruction: Option<String>,
+    safety_settings: Vec<SafetySetting>,
+    top_p: Option<f64>,
+    top_k: Option<u32>,
+    stop_sequences: Vec<String>,
 }
 
 impl GoogleGenAIProvider {
+    /// Without an explicit `api_key` the key is read from `GOOGLE_API_KEY` (or `GEMINI_API_KEY`) on each request.
     pub fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String, // e.g., "gemini-pro"
         temperature: Option<f64>,
         max_tokens: Option<u32>,
     ) -> Self {
         Self {
             client: Client::new(),
-            api_key,
+            api_key: ApiKey::from_option_or_env(api_key, &["GOOGLE_API_KEY", "GEMINI_API_KEY"]),
             model,
             temperature,
             max_tokens,
+            system_instruction: None,
+            safety_settings: Vec::new(),
+            top_p: None,
+            top_k: None,
+            stop_sequences: Vec::new(),
         }
     }
+
+    /// Replaces the key source, e.g. with `ApiKey::File` or a rotating `ApiKey::Provider`.
+    pub fn with_api_key(mut self, api_key: ApiKey) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    pub fn with_system_instruction(mut self, instruction: String) -> Self {
+        self.system_instruction = Some(instruction);
+        self
+    }
+
+    /// Sets the block threshold for one harm category, replacing any earlier setting for it.
+    pub fn with_safety_setting(mut self, category: HarmCategory, threshold: HarmBlockThreshold) -> Self {
+        self.safety_settings.retain(|s| s.category != category);
+        self.safety_settings.push(SafetySetting { category, threshold });
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = stop_sequences;
+        self
+    }
+
+    /// System messages join the configured system instruction; AI turns use Gemini's `model` role.
+    fn build_request(&self, conversation: &[ChatMessage]) -> GeminiRequest {
+        let contents = conversation.iter()
+            .filter(|m| m.role != Role::System)
+            .map(|m| Content {
+                parts: vec![Part { text: m.content.clone() }],
+                role: Some(if m.role == Role::AI { "model" } else { "user" }.to_string()),
+            })
+            .collect();
+        let system: Vec<&str> = self.system_instruction.iter().map(String::as_str)
+            .chain(conversation.iter().filter(|m| m.role == Role::System).map(|m| m.content.as_str()))
+            .collect();
+
+        let config = GenerationConfig {
+            temperature: self.temperature,
+            max_output_tokens: self.max_tokens,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            stop_sequences: self.stop_sequences.clone(),
+        };
+        let has_config = config.temperature.is_some()
+            || config.max_output_tokens.is_some()
+            || config.top_p.is_some()
+            || config.top_k.is_some()
+            || !config.stop_sequences.is_empty();
+
+        GeminiRequest {
+            contents,
+            system_instruction: (!system.is_empty()).then(|| Content {
+                parts: vec![Part { text: system.join("\n\n") }],
+                role: None,
+            }),
+            safety_settings: self.safety_settings.clone(),
+            generation_config: has_config.then_some(config),
+        }
+    }
+}
+
+/// Pulls the answer out of a response, turning safety blocks into `GeminiBlockedError`.
+fn extract_generation(response: GeminiResponse) -> Result<Generation> {
+    if let Some(feedback) = response.prompt_feedback {
+        if let Some(reason) = feedback.block_reason {
+            return Err(GeminiBlockedError::Prompt { reason, safety_ratings: feedback.safety_ratings }.into());
+        }
+    }
+
+    let candidate = response.candidates
+        .and_then(|c| c.into_iter().next())
+        .ok_or_else(|| anyhow!("No content returned from Google Gemini"))?;
+
+    if let Some(reason) = &candidate.finish_reason {
+        if BLOCKING_FINISH_REASONS.contains(&reason.as_str()) {
+            return Err(GeminiBlockedError::Response {
+                finish_reason: reason.clone(),
+                safety_ratings: candidate.safety_ratings,
+            }.into());
+        }
+    }
+
+    let text: String = candidate.content
+        .map(|c| c.parts.into_iter().filter_map(|p| p.text).collect())
+        .unwrap_or_default();
+    if text.is_empty() {
+        return Err(anyhow!("No content returned from Google Gemini"));
+    }
+
+    Ok(Generation {
+        text,
+        model: None,
+        usage: response.usage_metadata.map(|u| TokenUsage {
+            prompt_tokens: u.prompt_token_count,
+            completion_tokens: u.candidates_token_count + u.thoughts_token_count.unwrap_or(0),
+            total_tokens: u.total_token_count,
+            reasoning_tokens: u.thoughts_token_count,
+            cached_tokens: u.cached_content_token_count,
+        }),
+    })
 }
 
 #[async_trait]
 impl LLM for GoogleGenAIProvider {
     async fn generate(&self, prompt: &str) -> Result<String> {
-        // Construct standard URL for Gemini
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-            self.model, self.api_key
-        );
+        Ok(self.generate_with_usage(prompt).await?.text)
+    }
 
-        let parts = vec![Part { text: prompt.to_string() }];
-        let contents = vec![Content { parts, role: Some("user".to_string()) }];
+    fn model_name(&self) -> Option<String> {
+        Some(self.model.clone())
+    }
 
-        let config = if self.temperature.is_some() || self.max_tokens.is_some() {
-            Some(GenerationConfig {
-                temperature: self.temperature,
-                max_output_tokens: self.max_tokens,
-            })
-        } else {
-            None
-        };
+    async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+        self.generate_messages(&[ChatMessage::human(prompt)]).await
+    }
 
-        let request = GeminiRequest {
-            contents,
-            generation_config: config,
-        };
+    async fn generate_messages(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        // Construct standard URL for Gemini. The key goes in a header so it never
+        // ends up in URLs echoed by reqwest errors or proxy logs.
+        let url = format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
+            self.model
+        );
 
+        let request = self.build_request(messages);
+
+        let api_key = self.api_key.resolve()?;
         let res = self.client.post(&url)
+            .header("x-goog-api-key", api_key.expose_secret())
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
@@ -111,21 +361,13 @@ impl LLM for GoogleGenAIProvider {
 
         if !res.status().is_success() {
             let error_text = res.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("Google Gemini API Error: {}", error_text));
+            return Err(anyhow!("Google Gemini API Error: {}", error_text));
         }
 
         let response: GeminiResponse = res.json().await
             .context("Failed to parse Google Gemini response")?;
 
-        if let Some(candidates) = response.candidates {
-            if let Some(first) = candidates.first() {
-                if let Some(part) = first.content.parts.first() {
-                    return Ok(part.text.clone());
-                }
-            }
-        }
-        
-        Err(anyhow::anyhow!("No content returned from Google Gemini"))
+        extract_generation(response)
     }
 }
 
@@ -137,10 +379,64 @@ mod tests {
     fn test_gemini_serialization() {
         let parts = vec![Part { text: "hi".to_string() }];
         let contents = vec![Content { parts, role: Some("user".to_string()) }];
-        let request = GeminiRequest { contents, generation_config: None };
-        
+        let request = GeminiRequest {
+            contents,
+            system_instruction: None,
+            safety_settings: Vec::new(),
+            generation_config: None,
+        };
+
         let json = serde_json::to_string(&request).unwrap();
         assert!(json.contains("\"text\":\"hi\""));
         assert!(json.contains("\"role\":\"user\""));
     }
+
+    #[test]
+    fn test_gemini_options_serialization() {
+        let provider = GoogleGenAIProvider::new(Some("key".to_string()), "gemini-2.0-flash".to_string(), None, None)
+            .with_system_instruction("Be brief.".to_string())
+            .with_safety_setting(HarmCategory::HateSpeech, HarmBlockThreshold::BlockOnlyHigh)
+            .with_top_k(40)
+            .with_stop_sequences(vec!["END".to_string()]);
+
+        let json = serde_json::to_string(&provider.build_request(&[ChatMessage::human("hi")])).unwrap();
+        assert!(json.contains("\"system_instruction\":{\"parts\":[{\"text\":\"Be brief.\"}]}"));
+        assert!(json.contains("{\"category\":\"HARM_CATEGORY_HATE_SPEECH\",\"threshold\":\"BLOCK_ONLY_HIGH\"}"));
+        assert!(json.contains("\"top_k\":40"));
+        assert!(json.contains("\"stop_sequences\":[\"END\"]"));
+    }
+
+    #[test]
+    fn test_gemini_chat_roles() {
+        let provider = GoogleGenAIProvider::new(Some("key".to_string()), "gemini-2.0-flash".to_string(), None, None);
+        let request = provider.build_request(&[
+            ChatMessage::system("Be brief."),
+            ChatMessage::human("hi"),
+            ChatMessage::ai("hello"),
+        ]);
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("\"system_instruction\":{\"parts\":[{\"text\":\"Be brief.\"}]}"));
+        assert!(json.contains("\"contents\":[{\"parts\":[{\"text\":\"hi\"}],\"role\":\"user\"},{\"parts\":[{\"text\":\"hello\"}],\"role\":\"model\"}]"));
+    }
+
+    #[test]
+    fn test_gemini_blocked_responses() {
+        let prompt_blocked: GeminiResponse = serde_json::from_str(
+            r#"{"promptFeedback":{"blockReason":"SAFETY","safetyRatings":[{"category":"HARM_CATEGORY_HARASSMENT","probability":"HIGH","blocked":true}]}}"#,
+        ).unwrap();
+        let err = extract_generation(prompt_blocked).unwrap_err();
+        match err.downcast_ref::<GeminiBlockedError>() {
+            Some(GeminiBlockedError::Prompt { reason, safety_ratings }) => {
+                assert_eq!(reason, "SAFETY");
+                assert!(safety_ratings[0].blocked);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let response_blocked: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"finishReason":"RECITATION"}]}"#,
+        ).unwrap();
+        let err = extract_generation(response_blocked).unwrap_err();
+        assert!(matches!(err.downcast_ref::<GeminiBlockedError>(), Some(GeminiBlockedError::Response { .. })));
+    }
 }
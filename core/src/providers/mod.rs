@@ -3,3 +3,57 @@ pub mod openai;
 pub mod anthropic;
 pub mod google;
 pub mod ollama;
+
+use anyhow::{Context, Result};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+
+/// Lines of a streamed response body, e.g. server-sent events or newline-delimited JSON.
+pub(crate) fn response_lines(res: reqwest::Response) -> BoxStream<'static, Result<String>> {
+    let chunks = stream::try_unfold(res, |mut res| async move {
+        let chunk = res.chunk().await.context("Failed to read response stream")?;
+        Ok(chunk.map(|c| (c.to_vec(), res)))
+    });
+    split_lines(chunks)
+}
+
+/// Reassembles lines from body chunks, which may split a line anywhere. Line endings
+/// (`\n` or `\r\n`) are removed.
+fn split_lines<'a, S>(chunks: S) -> BoxStream<'a, Result<String>>
+where
+    S: Stream<Item = Result<Vec<u8>>> + Send + 'a,
+{
+    stream::try_unfold((chunks.boxed(), Vec::new(), false), |(mut chunks, mut buffer, mut done)| async move {
+        loop {
+            if let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string();
+                return Ok(Some((line, (chunks, buffer, done))));
+            }
+            if done {
+                if buffer.is_empty() {
+                    return Ok(None);
+                }
+                let line = String::from_utf8_lossy(&std::mem::take(&mut buffer)).trim_end_matches('\r').to_string();
+                return Ok(Some((line, (chunks, buffer, done))));
+            }
+            match chunks.next().await {
+                Some(chunk) => buffer.extend_from_slice(&chunk?),
+                None => done = true,
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_split_lines_across_chunks() {
+        let chunks = ["data: {\"a\"", ":1}\r\n\ndata: [DO", "NE]\n", "tail"]
+            .map(|c| Ok(c.as_bytes().to_vec()));
+        let lines: Vec<String> = split_lines(stream::iter(chunks)).map(Result::unwrap).collect().await;
+        assert_eq!(lines, ["data: {\"a\":1}", "", "data: [DONE]", "tail"]);
+    }
+}
@@ -1,8 +1,15 @@
-use crate::llm::LLM;
+use crate::llm::{Generation, TokenUsage, LLM};
+use crate::embedding::Embeddings;
+use crate::schema::{ChatMessage, Role};
+use super::response_lines;
 use async_trait::async_trait;
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
 
 #[derive(Serialize)]
 struct OllamaRequest {
@@ -11,6 +18,8 @@ struct OllamaRequest {
     stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     options: Option<OllamaOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
 }
 
 #[derive(Serialize)]
@@ -19,15 +28,47 @@ struct Message {
     content: String,
 }
 
-#[derive(Serialize)]
-struct OllamaOptions {
+/// Model parameters forwarded as the `options` object of Ollama requests.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Context window size in tokens (Ollama defaults to a small window).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// Maximum number of tokens to generate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<f64>,
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl OllamaOptions {
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.num_ctx.is_none()
+            && self.num_predict.is_none()
+            && self.top_k.is_none()
+            && self.top_p.is_none()
+            && self.repeat_penalty.is_none()
+            && self.seed.is_none()
+            && self.stop.is_empty()
+    }
 }
 
 #[derive(Deserialize)]
 struct OllamaResponse {
     message: MessageRes,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
 }
 
 #[derive(Deserialize)]
@@ -35,11 +76,147 @@ struct MessageRes {
     content: String,
 }
 
+/// One line of a streamed `/api/chat` response; the final line (`done`) carries the counts.
+#[derive(Deserialize)]
+struct OllamaChunk {
+    message: Option<MessageRes>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    error: Option<String>,
+}
+
+fn usage(prompt_eval_count: Option<u32>, eval_count: Option<u32>) -> Option<TokenUsage> {
+    match (prompt_eval_count, eval_count) {
+        (None, None) => None,
+        (prompt_tokens, completion_tokens) => {
+            let (prompt_tokens, completion_tokens) = (prompt_tokens.unwrap_or(0), completion_tokens.unwrap_or(0));
+            Some(TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+/// Turns one line of a streamed chat response into a chunk; blank lines and empty
+/// messages yield nothing.
+fn parse_chat_line(line: &str) -> Result<Option<Generation>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let chunk: OllamaChunk = serde_json::from_str(line).context("Failed to parse Ollama stream chunk")?;
+    if let Some(error) = chunk.error {
+        return Err(anyhow!("Ollama API Error: {}", error));
+    }
+    let text = chunk.message.map(|m| m.content).unwrap_or_default();
+    let usage = if chunk.done { usage(chunk.prompt_eval_count, chunk.eval_count) } else { None };
+    if text.is_empty() && usage.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Generation { text, usage, model: None }))
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    models: Vec<OllamaModel>,
+}
+
+/// A model available on the local Ollama server (`GET /api/tags`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct OllamaModel {
+    pub name: String,
+    #[serde(default)]
+    pub modified_at: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub digest: String,
+    #[serde(default)]
+    pub details: OllamaModelDetails,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OllamaModelDetails {
+    #[serde(default)]
+    pub format: String,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub parameter_size: String,
+    #[serde(default)]
+    pub quantization_level: String,
+}
+
+/// Metadata returned by `POST /api/show`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OllamaModelInfo {
+    #[serde(default)]
+    pub modelfile: String,
+    #[serde(default)]
+    pub parameters: String,
+    #[serde(default)]
+    pub template: String,
+    #[serde(default)]
+    pub details: OllamaModelDetails,
+    #[serde(default)]
+    pub model_info: HashMap<String, serde_json::Value>,
+}
+
+/// One status line streamed while pulling a model.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PullLine {
+    #[serde(flatten)]
+    progress: Option<PullProgress>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// Ollama accepts durations ("5m", "1h") or a number of seconds (`0` unloads, `-1` keeps forever).
+fn keep_alive_value(keep_alive: &str) -> serde_json::Value {
+    match keep_alive.parse::<i64>() {
+        Ok(seconds) => serde_json::Value::from(seconds),
+        Err(_) => serde_json::Value::from(keep_alive),
+    }
+}
+
+async fn check_status(res: reqwest::Response) -> Result<reqwest::Response> {
+    if !res.status().is_success() {
+        let error_text = res.text().await.unwrap_or_default();
+        return Err(anyhow!("Ollama API Error: {}", error_text));
+    }
+    Ok(res)
+}
+
 pub struct OllamaProvider {
     client: Client,
     base_url: String,
     model: String,
-    temperature: Option<f64>,
+    options: OllamaOptions,
+    keep_alive: Option<String>,
 }
 
 impl OllamaProvider {
@@ -50,42 +227,276 @@ impl OllamaProvider {
     ) -> Self {
         Self {
             client: Client::new(),
-            base_url: base_url.unwrap_or_else(|| "http://localhost:11434".to_string()),
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
             model,
-            temperature,
+            options: OllamaOptions { temperature, ..Default::default() },
+            keep_alive: None,
         }
     }
+
+    /// Replaces all model options. A temperature passed to `new` is kept unless overridden.
+    pub fn with_options(mut self, options: OllamaOptions) -> Self {
+        let temperature = options.temperature.or(self.options.temperature);
+        self.options = OllamaOptions { temperature, ..options };
+        self
+    }
+
+    pub fn with_num_ctx(mut self, num_ctx: u32) -> Self {
+        self.options.num_ctx = Some(num_ctx);
+        self
+    }
+
+    /// How long the model stays loaded after a request, e.g. "10m", "0" or "-1".
+    pub fn with_keep_alive(mut self, keep_alive: String) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    fn build_request(&self, conversation: &[ChatMessage]) -> OllamaRequest {
+        let messages = conversation.iter().map(|m| Message {
+            role: match m.role {
+                Role::System => "system",
+                Role::Human => "user",
+                Role::AI => "assistant",
+            }.to_string(),
+            content: m.content.clone(),
+        }).collect();
+        OllamaRequest {
+            model: self.model.clone(),
+            messages,
+            stream: false,
+            options: (!self.options.is_empty()).then(|| self.options.clone()),
+            keep_alive: self.keep_alive.as_deref().map(keep_alive_value),
+        }
+    }
+
+    /// Lists the models installed on the server.
+    pub async fn list_models(&self) -> Result<Vec<OllamaModel>> {
+        let res = self.client.get(self.url("/api/tags"))
+            .send()
+            .await
+            .context("Failed to send request to Ollama")?;
+        let tags: TagsResponse = check_status(res).await?.json().await
+            .context("Failed to parse Ollama model list")?;
+        Ok(tags.models)
+    }
+
+    /// Returns the modelfile, parameters and architecture details of a model.
+    pub async fn show_model(&self, name: &str) -> Result<OllamaModelInfo> {
+        let res = self.client.post(self.url("/api/show"))
+            .json(&serde_json::json!({ "model": name }))
+            .send()
+            .await
+            .context("Failed to send request to Ollama")?;
+        check_status(res).await?.json().await
+            .context("Failed to parse Ollama model info")
+    }
+
+    /// Downloads a model, calling `on_progress` for every status line the server streams back.
+    pub async fn pull_model<F>(&self, name: &str, on_progress: F) -> Result<()>
+    where
+        F: Fn(&PullProgress) + Send + Sync,
+    {
+        let res = self.client.post(self.url("/api/pull"))
+            .json(&serde_json::json!({ "model": name, "stream": true }))
+            .send()
+            .await
+            .context("Failed to send request to Ollama")?;
+        let mut res = check_status(res).await?;
+
+        // The body is newline-delimited JSON; chunks may split lines.
+        let mut buffer = Vec::new();
+        while let Some(chunk) = res.chunk().await.context("Failed to read Ollama pull stream")? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                handle_pull_line(&line, &on_progress)?;
+            }
+        }
+        handle_pull_line(&buffer, &on_progress)
+    }
+}
+
+fn handle_pull_line<F: Fn(&PullProgress)>(line: &[u8], on_progress: &F) -> Result<()> {
+    if line.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(());
+    }
+    let parsed: PullLine = serde_json::from_slice(line)
+        .context("Failed to parse Ollama pull progress")?;
+    if let Some(error) = parsed.error {
+        return Err(anyhow!("Ollama pull failed: {}", error));
+    }
+    if let Some(progress) = parsed.progress {
+        on_progress(&progress);
+    }
+    Ok(())
 }
 
 #[async_trait]
 impl LLM for OllamaProvider {
     async fn generate(&self, prompt: &str) -> Result<String> {
-        let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
-        
-        let messages = vec![Message { role: "user".to_string(), content: prompt.to_string() }];
+        Ok(self.generate_with_usage(prompt).await?.text)
+    }
 
-        let request = OllamaRequest {
-            model: self.model.clone(),
-            messages,
-            stream: false,
-            options: self.temperature.map(|t| OllamaOptions { temperature: Some(t) }),
+    fn model_name(&self) -> Option<String> {
+        Some(self.model.clone())
+    }
+
+    async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+        self.generate_messages(&[ChatMessage::human(prompt)]).await
+    }
+
+    async fn generate_messages(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        let request = self.build_request(messages);
+
+        let res = self.client.post(self.url("/api/chat"))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .context("Failed to send request to Ollama")?;
+
+        let response: OllamaResponse = check_status(res).await?.json().await
+            .context("Failed to parse Ollama response")?;
+
+        let usage = usage(response.prompt_eval_count, response.eval_count);
+        Ok(Generation { text: response.message.content, usage, model: None })
+    }
+
+    fn stream_messages(&self, messages: Vec<ChatMessage>) -> BoxStream<'_, Result<Generation>> {
+        let mut request = self.build_request(&messages);
+        request.stream = true;
+        stream::once(async move {
+            let res = self.client.post(self.url("/api/chat"))
+                .header("Content-Type", "application/json")
+                .json(&request)
+                .send()
+                .await
+                .context("Failed to send request to Ollama")?;
+            check_status(res).await
+        })
+        .map_ok(response_lines)
+        .try_flatten()
+        .try_filter_map(|line| async move { parse_chat_line(&line) })
+        .boxed()
+    }
+}
+
+/// Embeddings served by a local Ollama model through `/api/embed`.
+pub struct OllamaEmbeddings {
+    client: Client,
+    base_url: String,
+    model: String,
+    keep_alive: Option<String>,
+}
+
+impl OllamaEmbeddings {
+    pub fn new(model: String, base_url: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            model,
+            keep_alive: None,
+        }
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: String) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+}
+
+#[async_trait]
+impl Embeddings for OllamaEmbeddings {
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_documents(&[text.to_string()]).await?
+            .pop()
+            .ok_or_else(|| anyhow!("No embedding returned from Ollama"))
+    }
+
+    async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/api/embed", self.base_url.trim_end_matches('/'));
+        let request = EmbedRequest {
+            model: &self.model,
+            input: texts,
+            keep_alive: self.keep_alive.as_deref().map(keep_alive_value),
         };
 
         let res = self.client.post(&url)
-            .header("Content-Type", "application/json")
             .json(&request)
             .send()
             .await
             .context("Failed to send request to Ollama")?;
+        let response: EmbedResponse = check_status(res).await?.json().await
+            .context("Failed to parse Ollama embeddings")?;
 
-        if !res.status().is_success() {
-            let error_text = res.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("Ollama API Error: {}", error_text));
+        if response.embeddings.len() != texts.len() {
+            return Err(anyhow!(
+                "Ollama returned {} embeddings for {} inputs",
+                response.embeddings.len(),
+                texts.len()
+            ));
         }
+        Ok(response.embeddings)
+    }
+}
 
-        let response: OllamaResponse = res.json().await
-            .context("Failed to parse Ollama response")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_ollama_request_options() {
+        let provider = OllamaProvider::new("llama3".to_string(), None, Some(0.1))
+            .with_options(OllamaOptions { top_k: Some(20), ..Default::default() })
+            .with_num_ctx(8192)
+            .with_keep_alive("-1".to_string());
+
+        let json = serde_json::to_string(&provider.build_request(&[ChatMessage::human("hi")])).unwrap();
+        assert!(json.contains("\"options\":{\"temperature\":0.1,\"num_ctx\":8192,\"top_k\":20}"));
+        assert!(json.contains("\"keep_alive\":-1"));
+
+        let plain = OllamaProvider::new("llama3".to_string(), None, None);
+        let json = serde_json::to_string(&plain.build_request(&[ChatMessage::human("hi")])).unwrap();
+        assert!(!json.contains("options"));
+        assert!(!json.contains("keep_alive"));
+    }
+
+    #[test]
+    fn test_chat_stream_lines() {
+        let chunk = parse_chat_line(r#"{"model":"llama3","message":{"role":"assistant","content":"Hi"},"done":false}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.text, "Hi");
+        assert!(chunk.usage.is_none());
+
+        let last = parse_chat_line(r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":26,"eval_count":4}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!((last.text.as_str(), last.usage.unwrap().total_tokens), ("", 30));
+        assert!(parse_chat_line("").unwrap().is_none());
+        assert!(parse_chat_line(r#"{"error":"model not found"}"#).unwrap_err().to_string().contains("model not found"));
+    }
+
+    #[test]
+    fn test_pull_progress_lines() {
+        let seen = Mutex::new(Vec::new());
+        let record = |p: &PullProgress| seen.lock().unwrap().push((p.status.clone(), p.completed));
+
+        handle_pull_line(br#"{"status":"pulling manifest"}"#, &record).unwrap();
+        handle_pull_line(br#"{"status":"downloading","digest":"sha256:abc","total":100,"completed":40}"#, &record).unwrap();
+        handle_pull_line(b"  \n", &record).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![
+            ("pulling manifest".to_string(), None),
+            ("downloading".to_string(), Some(40)),
+        ]);
 
-        Ok(response.message.content)
+        let err = handle_pull_line(br#"{"error":"pull model manifest: file does not exist"}"#, &record).unwrap_err();
+        assert!(err.to_string().contains("file does not exist"));
     }
 }
@@ -1,8 +1,15 @@
-use crate::llm::LLM;
+use crate::llm::{Generation, TokenUsage, LLM};
+use crate::schema::{ChatMessage, Role};
+use crate::secret::ApiKey;
+use super::response_lines;
 use async_trait::async_trait;
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const DEFAULT_CHAT_URL: &str = "https://api.openai.com/v1/chat/completions";
 
 #[derive(Serialize)]
 struct OpenAIRequest {
@@ -12,6 +19,19 @@ struct OpenAIRequest {
     temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<ReasoningEffort>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Serialize)]
@@ -23,6 +43,7 @@ struct Message {
 #[derive(Deserialize)]
 struct OpenAIResponse {
     choices: Vec<Choice>,
+    usage: Option<ChatUsage>,
 }
 
 #[derive(Deserialize)]
@@ -32,76 +53,427 @@ struct Choice {
 
 #[derive(Deserialize)]
 struct MessageRes {
-    content: String,
+    content: Option<String>,
+    refusal: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+impl From<ChatUsage> for TokenUsage {
+    fn from(u: ChatUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            total_tokens: u.total_tokens,
+            reasoning_tokens: u.completion_tokens_details.and_then(|d| d.reasoning_tokens),
+            cached_tokens: u.prompt_tokens_details.and_then(|d| d.cached_tokens),
+        }
+    }
+}
+
+/// One server-sent event of a streamed chat completion.
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: Delta,
+}
+
+#[derive(Deserialize)]
+struct Delta {
+    content: Option<String>,
+    refusal: Option<String>,
+}
+
+/// Turns one `data:` line of a streamed chat completion into a chunk; other lines, the
+/// closing `[DONE]` and empty deltas yield nothing.
+fn parse_stream_line(line: &str) -> Result<Option<Generation>> {
+    let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+        return Ok(None);
+    };
+    if data == "[DONE]" {
+        return Ok(None);
+    }
+    let chunk: ChatChunk = serde_json::from_str(data).context("Failed to parse OpenAI stream chunk")?;
+    let mut text = String::new();
+    for choice in chunk.choices {
+        if let Some(refusal) = choice.delta.refusal.filter(|r| !r.is_empty()) {
+            return Err(anyhow!("OpenAI refused the request: {}", refusal));
+        }
+        text.push_str(choice.delta.content.as_deref().unwrap_or(""));
+    }
+    let usage = chunk.usage.map(TokenUsage::from);
+    if text.is_empty() && usage.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Generation { text, usage, model: None }))
+}
+
+#[derive(Deserialize)]
+struct PromptTokensDetails {
+    cached_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct CompletionTokensDetails {
+    reasoning_tokens: Option<u32>,
+}
+
+// --- Responses API (`/v1/responses`) ---
+
+#[derive(Serialize)]
+struct ResponsesRequest {
+    model: String,
+    input: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<ReasoningConfig>,
+}
+
+#[derive(Serialize)]
+struct ReasoningConfig {
+    effort: ReasoningEffort,
+}
+
+#[derive(Deserialize)]
+struct ResponsesResponse {
+    #[serde(default)]
+    output: Vec<OutputItem>,
+    usage: Option<ResponsesUsage>,
+}
+
+#[derive(Deserialize)]
+struct OutputItem {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    content: Vec<OutputContent>,
+}
+
+#[derive(Deserialize)]
+struct OutputContent {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+    refusal: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponsesUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+    total_tokens: u32,
+    input_tokens_details: Option<PromptTokensDetails>,
+    output_tokens_details: Option<CompletionTokensDetails>,
+}
+
+/// How much hidden reasoning an o-series / GPT-5 model should do before answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
+impl FromStr for ReasoningEffort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "minimal" => Ok(Self::Minimal),
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            other => Err(anyhow!("Unknown reasoning effort: {} (expected minimal, low, medium or high)", other)),
+        }
+    }
+}
+
+/// Which OpenAI endpoint family the provider talks to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpenAIApi {
+    #[default]
+    ChatCompletions,
+    Responses,
 }
 
 pub struct OpenAIProvider {
     client: Client,
-    api_key: String,
+    api_key: ApiKey,
     base_url: String,
     model: String,
     system_prompt: Option<String>,
     temperature: Option<f64>,
     max_tokens: Option<u32>,
+    max_completion_tokens: Option<u32>,
+    reasoning_effort: Option<ReasoningEffort>,
+    developer_role: Option<bool>,
+    api: OpenAIApi,
 }
 
 impl OpenAIProvider {
+    /// Without an explicit `api_key` the key is read from `OPENAI_API_KEY` on each request.
     pub fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         base_url: Option<String>,
         system_prompt: Option<String>,
         temperature: Option<f64>,
         max_tokens: Option<u32>,
     ) -> Self {
-        let base = base_url.unwrap_or_else(|| "https://api.openai.com/v1/chat/completions".to_string());
+        let base = base_url.unwrap_or_else(|| DEFAULT_CHAT_URL.to_string());
         Self {
             client: Client::new(),
-            api_key,
+            api_key: ApiKey::from_option_or_env(api_key, &["OPENAI_API_KEY"]),
             base_url: base,
             model,
             system_prompt,
             temperature,
             max_tokens,
+            max_completion_tokens: None,
+            reasoning_effort: None,
+            developer_role: None,
+            api: OpenAIApi::ChatCompletions,
         }
     }
-}
 
-#[async_trait]
-impl LLM for OpenAIProvider {
-    async fn generate(&self, prompt: &str) -> Result<String> {
+    /// Replaces the key source, e.g. with `ApiKey::File` or a rotating `ApiKey::Provider`.
+    pub fn with_api_key(mut self, api_key: ApiKey) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    /// Upper bound on generated tokens, including reasoning tokens.
+    /// Reasoning models only accept this instead of `max_tokens`.
+    pub fn with_max_completion_tokens(mut self, max_completion_tokens: u32) -> Self {
+        self.max_completion_tokens = Some(max_completion_tokens);
+        self
+    }
+
+    pub fn with_reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(effort);
+        self
+    }
+
+    /// Force sending the system prompt as a `developer` message (or not).
+    /// By default this is decided from the model name.
+    pub fn with_developer_role(mut self, enabled: bool) -> Self {
+        self.developer_role = Some(enabled);
+        self
+    }
+
+    /// Switch to the Responses API. A chat-completions `base_url` is rewritten to
+    /// the matching `/responses` endpoint; any other URL is used as-is.
+    pub fn with_api(mut self, api: OpenAIApi) -> Self {
+        self.api = api;
+        self
+    }
+
+    /// o-series and GPT-5 models reject `max_tokens`, non-default `temperature`
+    /// and expect instructions in the `developer` role.
+    pub fn is_reasoning_model(&self) -> bool {
+        let model = self.model.rsplit('/').next().unwrap_or(&self.model);
+        ["o1", "o3", "o4", "gpt-5"].iter().any(|p| model.starts_with(p))
+    }
+
+    fn endpoint(&self) -> String {
+        match self.api {
+            OpenAIApi::ChatCompletions => self.base_url.clone(),
+            OpenAIApi::Responses => match self.base_url.strip_suffix("/chat/completions") {
+                Some(root) => format!("{}/responses", root),
+                None => self.base_url.clone(),
+            },
+        }
+    }
+
+    fn system_role(&self) -> &'static str {
+        if self.developer_role.unwrap_or_else(|| self.is_reasoning_model()) {
+            "developer"
+        } else {
+            "system"
+        }
+    }
+
+    fn to_message(&self, message: &ChatMessage) -> Message {
+        let role = match message.role {
+            Role::System => self.system_role(),
+            Role::Human => "user",
+            Role::AI => "assistant",
+        };
+        Message { role: role.to_string(), content: message.content.clone() }
+    }
+
+    /// Sampling temperature is dropped for reasoning models, which only accept the default.
+    fn effective_temperature(&self) -> Option<f64> {
+        if self.is_reasoning_model() { None } else { self.temperature }
+    }
+
+    /// At most one limit is sent, since OpenAI rejects requests setting both:
+    /// `max_completion_tokens` wins, and reasoning models get `max_tokens` translated to it.
+    fn token_limits(&self) -> (Option<u32>, Option<u32>) {
+        if self.is_reasoning_model() || self.max_completion_tokens.is_some() {
+            (None, self.max_completion_tokens.or(self.max_tokens))
+        } else {
+            (self.max_tokens, None)
+        }
+    }
+
+    /// The configured system prompt always comes first, ahead of any system messages in the conversation.
+    fn build_chat_request(&self, conversation: &[ChatMessage]) -> OpenAIRequest {
         let mut messages = Vec::new();
         if let Some(sys) = &self.system_prompt {
-            messages.push(Message { role: "system".to_string(), content: sys.clone() });
+            messages.push(Message { role: self.system_role().to_string(), content: sys.clone() });
         }
-        messages.push(Message { role: "user".to_string(), content: prompt.to_string() });
+        messages.extend(conversation.iter().map(|m| self.to_message(m)));
 
-        let request = OpenAIRequest {
+        let (max_tokens, max_completion_tokens) = self.token_limits();
+        OpenAIRequest {
             model: self.model.clone(),
             messages,
-            temperature: self.temperature,
-            max_tokens: self.max_tokens,
-        };
+            temperature: self.effective_temperature(),
+            max_tokens,
+            max_completion_tokens,
+            reasoning_effort: self.reasoning_effort,
+            stream: false,
+            stream_options: None,
+        }
+    }
+
+    fn build_responses_request(&self, conversation: &[ChatMessage]) -> ResponsesRequest {
+        let (max_tokens, max_completion_tokens) = self.token_limits();
+        ResponsesRequest {
+            model: self.model.clone(),
+            input: conversation.iter().map(|m| self.to_message(m)).collect(),
+            instructions: self.system_prompt.clone(),
+            temperature: self.effective_temperature(),
+            max_output_tokens: max_completion_tokens.or(max_tokens),
+            reasoning: self.reasoning_effort.map(|effort| ReasoningConfig { effort }),
+        }
+    }
 
-        let res = self.client.post(&self.base_url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
+    async fn post<T: Serialize>(&self, body: &T) -> Result<reqwest::Response> {
+        let api_key = self.api_key.resolve()?;
+        let res = self.client.post(self.endpoint())
+            .header("Authorization", format!("Bearer {}", api_key.expose_secret()))
             .header("Content-Type", "application/json")
-            .json(&request)
+            .json(body)
             .send()
             .await
             .context("Failed to send request to OpenAI")?;
 
         if !res.status().is_success() {
             let error_text = res.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("OpenAI API Error: {}", error_text));
+            return Err(anyhow!("OpenAI API Error: {}", error_text));
         }
+        Ok(res)
+    }
 
+    async fn chat_completion(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        let res = self.post(&self.build_chat_request(messages)).await?;
         let response: OpenAIResponse = res.json().await
             .context("Failed to parse OpenAI response")?;
 
-        response.choices.first()
-            .map(|c| c.message.content.clone())
-            .ok_or_else(|| anyhow::anyhow!("No choices returned from OpenAI"))
+        let message = &response.choices.first()
+            .ok_or_else(|| anyhow!("No choices returned from OpenAI"))?
+            .message;
+        let text = match (&message.content, &message.refusal) {
+            (Some(content), _) => content.clone(),
+            (None, Some(refusal)) => return Err(anyhow!("OpenAI refused the request: {}", refusal)),
+            (None, None) => return Err(anyhow!("No content returned from OpenAI")),
+        };
+
+        Ok(Generation { text, model: None, usage: response.usage.map(TokenUsage::from) })
+    }
+
+    fn stream_chat_completion(&self, messages: Vec<ChatMessage>) -> BoxStream<'_, Result<Generation>> {
+        let mut request = self.build_chat_request(&messages);
+        request.stream = true;
+        request.stream_options = Some(StreamOptions { include_usage: true });
+        stream::once(async move { self.post(&request).await })
+            .map_ok(response_lines)
+            .try_flatten()
+            .try_filter_map(|line| async move { parse_stream_line(&line) })
+            .boxed()
+    }
+
+    async fn response(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        let res = self.post(&self.build_responses_request(messages)).await?;
+        let response: ResponsesResponse = res.json().await
+            .context("Failed to parse OpenAI Responses API response")?;
+
+        // Reasoning items carry no visible text; concatenate the message outputs.
+        let mut text = String::new();
+        for content in response.output.iter().filter(|o| o.kind == "message").flat_map(|o| &o.content) {
+            match (content.kind.as_str(), &content.text, &content.refusal) {
+                ("output_text", Some(t), _) => text.push_str(t),
+                ("refusal", _, Some(refusal)) => return Err(anyhow!("OpenAI refused the request: {}", refusal)),
+                _ => {}
+            }
+        }
+        if text.is_empty() {
+            return Err(anyhow!("No output text returned from OpenAI"));
+        }
+
+        Ok(Generation {
+            text,
+            model: None,
+            usage: response.usage.map(|u| TokenUsage {
+                prompt_tokens: u.input_tokens,
+                completion_tokens: u.output_tokens,
+                total_tokens: u.total_tokens,
+                reasoning_tokens: u.output_tokens_details.and_then(|d| d.reasoning_tokens),
+                cached_tokens: u.input_tokens_details.and_then(|d| d.cached_tokens),
+            }),
+        })
+    }
+}
+
+#[async_trait]
+impl LLM for OpenAIProvider {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        Ok(self.generate_with_usage(prompt).await?.text)
+    }
+
+    fn model_name(&self) -> Option<String> {
+        Some(self.model.clone())
+    }
+
+    async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+        self.generate_messages(&[ChatMessage::human(prompt)]).await
+    }
+
+    async fn generate_messages(&self, messages: &[ChatMessage]) -> Result<Generation> {
+        match self.api {
+            OpenAIApi::ChatCompletions => self.chat_completion(messages).await,
+            OpenAIApi::Responses => self.response(messages).await,
+        }
+    }
+
+    /// Chat Completions stream token by token; the Responses API answers in one chunk.
+    fn stream_messages(&self, messages: Vec<ChatMessage>) -> BoxStream<'_, Result<Generation>> {
+        match self.api {
+            OpenAIApi::ChatCompletions => self.stream_chat_completion(messages),
+            OpenAIApi::Responses => stream::once(async move { self.response(&messages).await }).boxed(),
+        }
     }
 }
 
@@ -116,11 +488,100 @@ mod tests {
             messages: vec![Message { role: "user".to_string(), content: "hello".to_string() }],
             temperature: Some(0.7),
             max_tokens: None,
+            max_completion_tokens: None,
+            reasoning_effort: None,
+            stream: false,
+            stream_options: None,
         };
         let json = serde_json::to_string(&request).unwrap();
         assert!(json.contains("\"model\":\"gpt-4\""));
         assert!(json.contains("\"role\":\"user\""));
         assert!(json.contains("\"content\":\"hello\""));
         assert!(json.contains("\"temperature\":0.7"));
+        assert!(!json.contains("stream"));
+    }
+
+    #[test]
+    fn test_stream_lines() {
+        let chunk = parse_stream_line(r#"data: {"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#).unwrap().unwrap();
+        assert_eq!(chunk.text, "Hel");
+        assert!(chunk.usage.is_none());
+        assert!(parse_stream_line(r#"data: {"choices":[{"index":0,"delta":{"role":"assistant"}}]}"#).unwrap().is_none());
+        assert!(parse_stream_line("data: [DONE]").unwrap().is_none());
+        assert!(parse_stream_line(": keep-alive").unwrap().is_none());
+
+        let last = parse_stream_line(r#"data: {"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":2,"total_tokens":11}}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!((last.text.as_str(), last.usage.unwrap().total_tokens), ("", 11));
+
+        let err = parse_stream_line(r#"data: {"choices":[{"delta":{"refusal":"No."}}]}"#).unwrap_err();
+        assert_eq!(err.to_string(), "OpenAI refused the request: No.");
+    }
+
+    #[test]
+    fn test_reasoning_model_request() {
+        let provider = OpenAIProvider::new(
+            Some("key".to_string()), "o3-mini".to_string(), None,
+            Some("Be terse.".to_string()), Some(0.2), Some(500),
+        ).with_reasoning_effort(ReasoningEffort::High);
+
+        let json = serde_json::to_string(&provider.build_chat_request(&[ChatMessage::human("hi")])).unwrap();
+        assert!(json.contains("\"role\":\"developer\""));
+        assert!(json.contains("\"max_completion_tokens\":500"));
+        assert!(json.contains("\"reasoning_effort\":\"high\""));
+        assert!(!json.contains("\"max_tokens\""));
+        assert!(!json.contains("\"temperature\""));
+    }
+
+    #[test]
+    fn test_only_one_token_limit_is_sent() {
+        let provider = OpenAIProvider::new(
+            Some("key".to_string()), "gpt-4o".to_string(), None, None, None, Some(500),
+        );
+        let json = serde_json::to_string(&provider.build_chat_request(&[ChatMessage::human("hi")])).unwrap();
+        assert!(json.contains("\"max_tokens\":500"));
+        assert!(!json.contains("\"max_completion_tokens\""));
+
+        let provider = provider.with_max_completion_tokens(800);
+        let json = serde_json::to_string(&provider.build_chat_request(&[ChatMessage::human("hi")])).unwrap();
+        assert!(json.contains("\"max_completion_tokens\":800"));
+        assert!(!json.contains("\"max_tokens\""));
+    }
+
+    #[test]
+    fn test_chat_messages_map_to_openai_roles() {
+        let provider = OpenAIProvider::new(
+            Some("key".to_string()), "gpt-4o".to_string(), None,
+            Some("Be terse.".to_string()), None, None,
+        );
+        let request = provider.build_chat_request(&[
+            ChatMessage::system("Answer in French."),
+            ChatMessage::human("hi"),
+            ChatMessage::ai("salut"),
+            ChatMessage::human("bye"),
+        ]);
+        let roles: Vec<&str> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "system", "user", "assistant", "user"]);
+        assert_eq!(request.messages[0].content, "Be terse.");
+    }
+
+    #[test]
+    fn test_responses_api_request_and_usage() {
+        let provider = OpenAIProvider::new(Some("key".to_string()), "gpt-5".to_string(), None, None, None, None)
+            .with_api(OpenAIApi::Responses)
+            .with_max_completion_tokens(64)
+            .with_reasoning_effort(ReasoningEffort::Low);
+        assert_eq!(provider.endpoint(), "https://api.openai.com/v1/responses");
+
+        let json = serde_json::to_string(&provider.build_responses_request(&[ChatMessage::human("hi")])).unwrap();
+        assert!(json.contains("\"max_output_tokens\":64"));
+        assert!(json.contains("\"reasoning\":{\"effort\":\"low\"}"));
+
+        let body = r#"{"output":[{"type":"reasoning","summary":[]},{"type":"message","content":[{"type":"output_text","text":"Hello"}]}],
+            "usage":{"input_tokens":5,"output_tokens":40,"total_tokens":45,"output_tokens_details":{"reasoning_tokens":32}}}"#;
+        let response: ResponsesResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(response.output.len(), 2);
+        assert_eq!(response.usage.unwrap().output_tokens_details.unwrap().reasoning_tokens, Some(32));
     }
 }
@@ -1,11 +1,21 @@
 use async_trait::async_trait;
 use anyhow::{Result, anyhow, Context};
-use crate::llm::LLM;
+use crate::llm::{Generation, TokenUsage, LLM};
+use crate::schema::{ChatMessage, Role};
+use crate::secret::ApiKey;
+use serde::Deserialize;
 use serde_json::json;
-use std::env;
+
+/// OpenAI-style `usage` block of a chat completion.
+#[derive(Deserialize)]
+struct SambaNovaUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+}
 
 pub struct SambaNovaProvider {
-    api_key: String,
+    api_key: ApiKey,
     model: String,
     client: reqwest::Client,
     pub system_prompt: String,
@@ -25,9 +35,10 @@ impl SambaNovaProvider {
         top_k: Option<u32>,
         top_p: Option<f64>,
     ) -> Result<Self> {
-        let key = api_key.or_else(|| env::var("SAMBANOVA_API_KEY").ok())
-            .ok_or_else(|| anyhow!("SambaNova API Key must be provided or set in SAMBANOVA_API_KEY env var"))?;
-            
+        let key = ApiKey::from_option_or_env(api_key, &["SAMBANOVA_API_KEY"]);
+        key.resolve()
+            .map_err(|_| anyhow!("SambaNova API Key must be provided or set in SAMBANOVA_API_KEY env var"))?;
+
         Ok(Self {
             api_key: key,
             model,
@@ -39,26 +50,58 @@ impl SambaNovaProvider {
             top_p,
         })
     }
+
+    /// Replaces the key source, e.g. with `ApiKey::File` or a rotating `ApiKey::Provider`.
+    pub fn with_api_key(mut self, api_key: ApiKey) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    fn parse_response(json_resp: &serde_json::Value) -> Result<Generation> {
+        // Extract content from choices[0].message.content
+        let content = json_resp["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Invalid response structure from SambaNova"))?
+            .to_string();
+
+        let mut generation = Generation::new(content);
+        generation.usage = SambaNovaUsage::deserialize(&json_resp["usage"]).ok().map(|u| TokenUsage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            total_tokens: u.total_tokens,
+            ..Default::default()
+        });
+        Ok(generation)
+    }
 }
 
 #[async_trait]
 impl LLM for SambaNovaProvider {
     async fn generate(&self, prompt: &str) -> Result<String> {
+        Ok(self.generate_messages(&[ChatMessage::human(prompt)]).await?.text)
+    }
+
+    fn model_name(&self) -> Option<String> {
+        Some(self.model.clone())
+    }
+
+    async fn generate_messages(&self, conversation: &[ChatMessage]) -> Result<Generation> {
         let url = "https://api.sambanova.ai/v1/chat/completions";
-        
+
+        let mut messages = vec![json!({ "role": "system", "content": self.system_prompt })];
+        messages.extend(conversation.iter().map(|m| json!({
+            "role": match m.role {
+                Role::System => "system",
+                Role::Human => "user",
+                Role::AI => "assistant",
+            },
+            "content": m.content,
+        })));
+
         let mut body = json!({
             "stream": false,
             "model": self.model,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": self.system_prompt
-                },
-                {
-                    "role": "user",
-                    "content": prompt
-                }
-            ]
+            "messages": messages
         });
 
         if let Some(temp) = self.temperature {
@@ -74,8 +117,9 @@ impl LLM for SambaNovaProvider {
             body.as_object_mut().unwrap().insert("top_p".to_string(), json!(p));
         }
 
+        let api_key = self.api_key.resolve()?;
         let resp = self.client.post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Authorization", format!("Bearer {}", api_key.expose_secret()))
             .header("Content-Type", "application/json")
             .json(&body)
             .send()
@@ -90,12 +134,26 @@ impl LLM for SambaNovaProvider {
         let json_resp: serde_json::Value = resp.json().await
             .context("Failed to parse SambaNova response")?;
 
-        // Extract content from choices[0].message.content
-        let content = json_resp["choices"][0]["message"]["content"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Invalid response structure from SambaNova"))?
-            .to_string();
+        Self::parse_response(&json_resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_usage() {
+        let body = json!({
+            "choices": [{"message": {"role": "assistant", "content": "Hello"}}],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15, "time_to_first_token": 0.1}
+        });
+        let generation = SambaNovaProvider::parse_response(&body).unwrap();
+        assert_eq!(generation.text, "Hello");
+        let usage = generation.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (12, 3, 15));
 
-        Ok(content)
+        let without_usage = json!({"choices": [{"message": {"content": "Hi"}}]});
+        assert!(SambaNovaProvider::parse_response(&without_usage).unwrap().usage.is_none());
     }
 }
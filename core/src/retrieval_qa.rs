@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use anyhow::Result;
+use crate::callbacks::{trace_chain, trace_llm};
+use crate::llm::LLM;
+use crate::prompt::PromptTemplate;
+use crate::runnable::Runnable;
+use crate::schema::{Document, PromptValue};
+use crate::vectorstore::{VectorStore, VectorStoreRetriever};
+
+const QA_TEMPLATE: &str = "Use the following pieces of context to answer the question at the end. \
+If you don't know the answer, just say that you don't know, don't try to make up an answer.\n\n\
+{context}\n\nQuestion: {question}\nHelpful Answer:";
+
+const DOCUMENT_TEMPLATE: &str = "[{index}] {page_content}";
+
+/// Answer of a `RetrievalQA` call with the documents it was based on.
+#[derive(Debug, Clone)]
+pub struct QAResult {
+    pub answer: String,
+    /// Retrieved documents, in the order (and numbering, from 1) they appeared in the context.
+    pub source_documents: Vec<Document>,
+    /// Distinct `source` metadata values of the documents, in retrieval order.
+    pub sources: Vec<String>,
+}
+
+/// Answers questions from documents retrieved out of a `VectorStore`.
+#[derive(Clone)]
+pub struct RetrievalQA {
+    llm: Arc<dyn LLM>,
+    store: Arc<dyn VectorStore>,
+    k: usize,
+    prompt: PromptTemplate,
+    document_prompt: PromptTemplate,
+    document_separator: String,
+}
+
+impl RetrievalQA {
+    /// Retrieves 4 documents per question by default.
+    pub fn new(llm: Arc<dyn LLM>, store: Arc<dyn VectorStore>) -> Self {
+        Self {
+            llm,
+            store,
+            k: 4,
+            prompt: PromptTemplate::from_template(QA_TEMPLATE).unwrap(),
+            document_prompt: PromptTemplate::from_template(DOCUMENT_TEMPLATE).unwrap(),
+            document_separator: "\n\n".to_string(),
+        }
+    }
+
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Prompt receiving the formatted documents as `{context}` and the question as `{question}`.
+    pub fn with_prompt(mut self, prompt: PromptTemplate) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Formats each document into the context. It can use `{page_content}`, `{index}` (from 1)
+    /// and any metadata key, e.g. `{source}`. Defaults to `[{index}] {page_content}`.
+    pub fn with_document_prompt(mut self, prompt: PromptTemplate) -> Self {
+        self.document_prompt = prompt;
+        self
+    }
+
+    pub fn with_document_separator(mut self, separator: &str) -> Self {
+        self.document_separator = separator.to_string();
+        self
+    }
+
+    pub fn retriever(&self) -> VectorStoreRetriever {
+        VectorStoreRetriever::new(self.store.clone(), self.k)
+    }
+
+    /// Formats documents into the `{context}` text.
+    pub fn format_documents(&self, docs: &[Document]) -> Result<String> {
+        let formatted = docs
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| {
+                let mut values = doc.metadata.clone();
+                values.insert("page_content".to_string(), doc.page_content.clone());
+                values.insert("index".to_string(), (i + 1).to_string());
+                self.document_prompt.format(&values)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(formatted.join(&self.document_separator))
+    }
+
+    pub async fn call(&self, question: &str) -> Result<QAResult> {
+        let inputs = HashMap::from([("question".to_string(), question.to_string())]);
+        trace_chain("RetrievalQA", &inputs, result_outputs, async {
+            let docs = self.retriever().get_relevant_documents(question).await?;
+            self.answer(question, docs).await
+        })
+        .await
+    }
+
+    /// Answers from the given documents instead of retrieving them.
+    pub async fn answer(&self, question: &str, docs: Vec<Document>) -> Result<QAResult> {
+        let values = HashMap::from([
+            ("context".to_string(), self.format_documents(&docs)?),
+            ("question".to_string(), question.to_string()),
+        ]);
+        let answer = trace_llm(self.llm.as_ref(), PromptValue::Text(self.prompt.format(&values)?)).await?.text;
+
+        let mut sources: Vec<String> = Vec::new();
+        for source in docs.iter().filter_map(|d| d.metadata.get("source")) {
+            if !sources.contains(source) {
+                sources.push(source.clone());
+            }
+        }
+        Ok(QAResult { answer: answer.trim().to_string(), source_documents: docs, sources })
+    }
+}
+
+/// Callback outputs of a QA call: the answer and its comma-separated sources.
+pub(crate) fn result_outputs(result: &QAResult) -> HashMap<String, String> {
+    HashMap::from([
+        ("answer".to_string(), result.answer.clone()),
+        ("sources".to_string(), result.sources.join(", ")),
+    ])
+}
+
+#[async_trait]
+impl Runnable for RetrievalQA {
+    type Input = String;
+    type Output = QAResult;
+
+    async fn invoke(&self, question: String) -> Result<QAResult> {
+        self.call(&question).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::embedding::Embeddings;
+    use crate::vectorstore::InMemoryVectorStore;
+
+    #[derive(Default)]
+    struct RecordingLLM {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LLM for RecordingLLM {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            Ok(" Tokio [1] \n".to_string())
+        }
+    }
+
+    struct KeywordEmbeddings;
+
+    #[async_trait]
+    impl Embeddings for KeywordEmbeddings {
+        async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+            Ok(["async", "memory", "python"].iter().map(|w| text.matches(w).count() as f32 + 0.01).collect())
+        }
+
+        async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            let mut vecs = Vec::new();
+            for text in texts {
+                vecs.push(self.embed_query(text).await?);
+            }
+            Ok(vecs)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_answer_with_citations() {
+        let store = Arc::new(InMemoryVectorStore::new(Arc::new(KeywordEmbeddings)));
+        store.add_documents(&[
+            Document::new("The async runtime is Tokio.".to_string()).with_metadata("source", "runtime.md"),
+            Document::new("Ownership gives memory safety.".to_string()).with_metadata("source", "memory.md"),
+            Document::new("More async details.".to_string()).with_metadata("source", "runtime.md"),
+            Document::new("Bindings exist for python.".to_string()),
+        ]).await.unwrap();
+
+        let llm = Arc::new(RecordingLLM::default());
+        let qa = RetrievalQA::new(llm.clone(), store)
+            .with_k(2)
+            .with_prompt(PromptTemplate::from_template("{context}\nQ: {question}").unwrap())
+            .with_document_prompt(PromptTemplate::from_template("[{index}] ({source}) {page_content}").unwrap());
+
+        let result = qa.call("Which async runtime?").await.unwrap();
+        assert_eq!(result.answer, "Tokio [1]");
+        assert_eq!(result.source_documents.len(), 2);
+        assert_eq!(result.sources, ["runtime.md"]);
+        assert_eq!(
+            llm.prompts.lock().unwrap()[0],
+            "[1] (runtime.md) The async runtime is Tokio.\n\n[2] (runtime.md) More async details.\nQ: Which async runtime?"
+        );
+
+        // The document prompt needs `source`, which the last document lacks.
+        let unsourced = vec![Document::new("Bindings exist for python.".to_string())];
+        assert!(qa.answer("python?", unsourced).await.is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use anyhow::Result;
+use tokio::sync::OnceCell;
+use crate::callbacks::{trace_chain, trace_llm};
+use crate::chain::LLMChain;
+use crate::embedding::Embeddings;
+use crate::llm::LLM;
+use crate::output_parser::{EnumOutputParser, OutputParser};
+use crate::prompt::PromptError;
+use crate::runnable::Runnable;
+use crate::schema::PromptValue;
+use crate::vectorstore::InMemoryVectorStore;
+
+/// Answer the classifier gives when no route fits.
+const DEFAULT_ROUTE: &str = "DEFAULT";
+
+/// Similarity an embedding route needs when no threshold is set. Typical for related text
+/// with current embedding models; unrelated queries usually score well below it.
+pub const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.5;
+
+#[derive(Clone)]
+struct Route {
+    name: String,
+    description: String,
+    chain: LLMChain,
+}
+
+#[derive(Clone)]
+enum Strategy {
+    Llm(Arc<dyn LLM>),
+    Embedding {
+        embeddings: Arc<dyn Embeddings>,
+        threshold: f32,
+        /// Description embeddings, computed on first use.
+        vectors: Arc<OnceCell<Vec<Vec<f32>>>>,
+    },
+}
+
+/// Sends each query to the best-matching destination chain, or to the default chain
+/// when none matches.
+///
+/// Routes are chosen either by asking an LLM to classify the query against the route
+/// descriptions, or by comparing the query's embedding with the descriptions' embeddings.
+#[derive(Clone)]
+pub struct RouterChain {
+    strategy: Strategy,
+    routes: Vec<Route>,
+    default_chain: LLMChain,
+    input_key: String,
+}
+
+impl RouterChain {
+    /// Classifies queries with `llm`; answers that aren't a route name go to `default_chain`.
+    pub fn llm_router(llm: Arc<dyn LLM>, default_chain: LLMChain) -> Self {
+        Self::new(Strategy::Llm(llm), default_chain)
+    }
+
+    /// Picks the route whose description is most similar to the query. Queries below the
+    /// similarity threshold (`DEFAULT_SIMILARITY_THRESHOLD` unless set with `with_threshold`)
+    /// go to `default_chain`.
+    pub fn embedding_router(embeddings: Arc<dyn Embeddings>, default_chain: LLMChain) -> Self {
+        let strategy = Strategy::Embedding {
+            embeddings,
+            threshold: DEFAULT_SIMILARITY_THRESHOLD,
+            vectors: Arc::new(OnceCell::new()),
+        };
+        Self::new(strategy, default_chain)
+    }
+
+    fn new(strategy: Strategy, default_chain: LLMChain) -> Self {
+        Self { strategy, routes: Vec::new(), default_chain, input_key: "input".to_string() }
+    }
+
+    /// `description` tells the router which queries belong to this route.
+    pub fn with_route(mut self, name: &str, description: &str, chain: LLMChain) -> Self {
+        self.routes.push(Route { name: name.to_string(), description: description.to_string(), chain });
+        if let Strategy::Embedding { vectors, .. } = &mut self.strategy {
+            *vectors = Arc::new(OnceCell::new());
+        }
+        self
+    }
+
+    /// Input holding the query to route. Defaults to `input`.
+    pub fn with_input_key(mut self, key: &str) -> Self {
+        self.input_key = key.to_string();
+        self
+    }
+
+    /// Minimum cosine similarity for the embedding router to pick a route. Defaults to
+    /// `DEFAULT_SIMILARITY_THRESHOLD`; tune it for the embedding model in use.
+    /// Has no effect on LLM routing.
+    pub fn with_threshold(mut self, min_similarity: f32) -> Self {
+        if let Strategy::Embedding { threshold, .. } = &mut self.strategy {
+            *threshold = min_similarity;
+        }
+        self
+    }
+
+    pub fn route_names(&self) -> Vec<String> {
+        self.routes.iter().map(|r| r.name.clone()).collect()
+    }
+
+    /// Name of the route `inputs` would be sent to, or `None` for the default chain.
+    pub async fn route(&self, inputs: &HashMap<String, String>) -> Result<Option<String>> {
+        let query = inputs
+            .get(&self.input_key)
+            .ok_or_else(|| PromptError::MissingVariable(self.input_key.clone()))?;
+        if self.routes.is_empty() {
+            return Ok(None);
+        }
+
+        let index = match &self.strategy {
+            Strategy::Llm(llm) => self.classify(llm.as_ref(), query).await?,
+            Strategy::Embedding { embeddings, threshold, vectors } => {
+                let vectors = vectors
+                    .get_or_try_init(|| {
+                        let descriptions: Vec<String> = self.routes.iter().map(|r| r.description.clone()).collect();
+                        async move { embeddings.embed_documents(&descriptions).await }
+                    })
+                    .await?;
+                let query_vector = embeddings.embed_query(query).await?;
+                vectors
+                    .iter()
+                    .map(|v| InMemoryVectorStore::cosine_similarity(&query_vector, v))
+                    .enumerate()
+                    .filter(|(_, score)| score >= threshold)
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(i, _)| i)
+            }
+        };
+        Ok(index.map(|i| self.routes[i].name.clone()))
+    }
+
+    async fn classify(&self, llm: &dyn LLM, query: &str) -> Result<Option<usize>> {
+        let destinations = self.routes
+            .iter()
+            .map(|r| format!("{}: {}", r.name, r.description))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let prompt = format!(
+            "Choose the destination best suited to handle the input.\n\nDestinations:\n{}\n\n\
+             Answer with the destination name only, or {} if none fits.\n\nInput: {}\nDestination:",
+            destinations, DEFAULT_ROUTE, query
+        );
+        let answer = trace_llm(llm, PromptValue::Text(prompt)).await?.text;
+
+        let mut names: Vec<&str> = self.routes.iter().map(|r| r.name.as_str()).collect();
+        names.push(DEFAULT_ROUTE);
+        // An unrecognised answer falls back to the default route rather than failing the call.
+        let chosen = EnumOutputParser::new(&names).parse(&answer).ok();
+        Ok(chosen.and_then(|name| self.routes.iter().position(|r| r.name == name)))
+    }
+
+    pub async fn call(&self, inputs: HashMap<String, String>) -> Result<String> {
+        let traced_inputs = inputs.clone();
+        let outputs = |output: &String| HashMap::from([("output".to_string(), output.clone())]);
+        trace_chain("RouterChain", &traced_inputs, outputs, self.call_inner(inputs)).await
+    }
+
+    async fn call_inner(&self, inputs: HashMap<String, String>) -> Result<String> {
+        let destination = self.route(&inputs).await?;
+        let chain = destination
+            .and_then(|name| self.routes.iter().find(|r| r.name == name))
+            .map_or(&self.default_chain, |r| &r.chain);
+        chain.call(inputs).await
+    }
+}
+
+#[async_trait]
+impl Runnable for RouterChain {
+    type Input = HashMap<String, String>;
+    type Output = String;
+
+    async fn invoke(&self, input: Self::Input) -> Result<String> {
+        self.call(input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prompt::PromptTemplate;
+
+    struct FixedLLM(&'static str);
+
+    #[async_trait]
+    impl LLM for FixedLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    struct KeywordEmbeddings;
+
+    #[async_trait]
+    impl Embeddings for KeywordEmbeddings {
+        async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+            Ok(["invoice", "refund", "error", "crash", "weather"].iter().map(|w| text.matches(w).count() as f32).collect())
+        }
+
+        async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            let mut vecs = Vec::new();
+            for text in texts {
+                vecs.push(self.embed_query(text).await?);
+            }
+            Ok(vecs)
+        }
+    }
+
+    fn destination(reply: &'static str) -> LLMChain {
+        LLMChain::new(PromptTemplate::from_template("{input}").unwrap(), Arc::new(FixedLLM(reply)))
+    }
+
+    fn query(text: &str) -> HashMap<String, String> {
+        HashMap::from([("input".to_string(), text.to_string())])
+    }
+
+    #[tokio::test]
+    async fn test_llm_router() {
+        let router = |answer: &'static str| {
+            RouterChain::llm_router(Arc::new(FixedLLM(answer)), destination("general"))
+                .with_route("billing", "Invoices and refunds", destination("billing"))
+                .with_route("tech", "Errors and crashes", destination("tech"))
+        };
+
+        assert_eq!(router(" Billing\n").call(query("Where is my refund?")).await.unwrap(), "billing");
+        assert_eq!(router("DEFAULT").route(&query("Hello")).await.unwrap(), None);
+        assert_eq!(router("I am not sure").call(query("Hello")).await.unwrap(), "general");
+        assert!(router("tech").call(HashMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_embedding_router() {
+        let router = RouterChain::embedding_router(Arc::new(KeywordEmbeddings), destination("general"))
+            .with_route("billing", "invoice and refund questions", destination("billing"))
+            .with_route("tech", "error reports and crash logs", destination("tech"))
+            .with_threshold(0.5);
+        assert_eq!(router.route_names(), ["billing", "tech"]);
+
+        assert_eq!(router.call(query("the app shows an error then a crash")).await.unwrap(), "tech");
+        assert_eq!(router.route(&query("refund please")).await.unwrap().as_deref(), Some("billing"));
+        assert_eq!(router.call(query("what's the weather?")).await.unwrap(), "general");
+    }
+
+    #[tokio::test]
+    async fn test_embedding_router_default_threshold() {
+        let router = RouterChain::embedding_router(Arc::new(KeywordEmbeddings), destination("general"))
+            .with_route("billing", "invoice and refund questions", destination("billing"));
+
+        // Mentions an invoice, but only loosely matches the route (similarity ~0.32).
+        assert_eq!(router.call(query("weather, weather and an invoice")).await.unwrap(), "general");
+        assert_eq!(router.call(query("refund my invoice")).await.unwrap(), "billing");
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use async_trait::async_trait;
+use anyhow::Result;
+use futures::future::{join_all, try_join_all};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use crate::batch::BatchOptions;
+use crate::chain::LLMChain;
+use crate::chat_prompt::ChatPromptTemplate;
+use crate::llm::LLM;
+use crate::output_parser::OutputParser;
+use crate::prompt::PromptTemplate;
+use crate::schema::{Document, PromptValue};
+use crate::vectorstore::VectorStoreRetriever;
+
+/// A unit of work with a typed input and output that can be composed into pipelines,
+/// e.g. `prompt.pipe(llm).pipe(parser)`.
+#[async_trait]
+pub trait Runnable: Send + Sync {
+    type Input: Send + 'static;
+    type Output: Send + 'static;
+
+    async fn invoke(&self, input: Self::Input) -> Result<Self::Output>;
+
+    /// Runs every input concurrently; fails with the first error.
+    async fn batch(&self, inputs: Vec<Self::Input>) -> Result<Vec<Self::Output>> {
+        try_join_all(inputs.into_iter().map(|input| self.invoke(input))).await
+    }
+
+    /// Yields the output in chunks. Runnables without incremental output (everything but
+    /// LLMs, whose chunks come from `LLM::stream_messages`) yield it once.
+    fn stream(&self, input: Self::Input) -> BoxStream<'_, Result<Self::Output>> {
+        stream::once(self.invoke(input)).boxed()
+    }
+}
+
+/// Type-erased runnable, e.g. for branches of a `RunnableParallel`.
+pub type BoxRunnable<I, O> = Arc<dyn Runnable<Input = I, Output = O>>;
+
+#[async_trait]
+impl<R: Runnable + ?Sized> Runnable for Arc<R> {
+    type Input = R::Input;
+    type Output = R::Output;
+
+    async fn invoke(&self, input: Self::Input) -> Result<Self::Output> {
+        (**self).invoke(input).await
+    }
+
+    async fn batch(&self, inputs: Vec<Self::Input>) -> Result<Vec<Self::Output>> {
+        (**self).batch(inputs).await
+    }
+
+    fn stream(&self, input: Self::Input) -> BoxStream<'_, Result<Self::Output>> {
+        (**self).stream(input)
+    }
+}
+
+/// Combinators available on every runnable.
+pub trait RunnableExt: Runnable + Sized {
+    /// Feeds this runnable's output into `next`.
+    fn pipe<R>(self, next: R) -> RunnableSequence<Self, R>
+    where
+        R: Runnable<Input = Self::Output>,
+    {
+        RunnableSequence { first: self, second: next }
+    }
+
+    fn boxed(self) -> BoxRunnable<Self::Input, Self::Output>
+    where
+        Self: 'static,
+    {
+        Arc::new(self)
+    }
+}
+
+impl<R: Runnable + Sized> RunnableExt for R {}
+
+/// Runs `first`, then `second` on its output. Streaming runs `first` to completion and
+/// streams `second`, so `prompt.pipe(llm)` yields the LLM's chunks as they arrive.
+#[derive(Clone)]
+pub struct RunnableSequence<A, B> {
+    first: A,
+    second: B,
+}
+
+#[async_trait]
+impl<A, B> Runnable for RunnableSequence<A, B>
+where
+    A: Runnable,
+    B: Runnable<Input = A::Output>,
+{
+    type Input = A::Input;
+    type Output = B::Output;
+
+    async fn invoke(&self, input: A::Input) -> Result<B::Output> {
+        let intermediate = self.first.invoke(input).await?;
+        self.second.invoke(intermediate).await
+    }
+
+    async fn batch(&self, inputs: Vec<A::Input>) -> Result<Vec<B::Output>> {
+        let intermediate = self.first.batch(inputs).await?;
+        self.second.batch(intermediate).await
+    }
+
+    fn stream(&self, input: A::Input) -> BoxStream<'_, Result<B::Output>> {
+        stream::once(self.first.invoke(input))
+            .flat_map(move |intermediate| match intermediate {
+                Ok(value) => self.second.stream(value),
+                Err(e) => stream::once(async { Err(e) }).boxed(),
+            })
+            .boxed()
+    }
+}
+
+/// Runs named branches concurrently on copies of the same input and collects their
+/// outputs by name, e.g. to build the variables of a prompt.
+pub struct RunnableParallel<I, O> {
+    branches: Vec<(String, BoxRunnable<I, O>)>,
+}
+
+impl<I, O> Default for RunnableParallel<I, O> {
+    fn default() -> Self {
+        Self { branches: Vec::new() }
+    }
+}
+
+impl<I, O> RunnableParallel<I, O>
+where
+    I: Clone + Send + 'static,
+    O: Send + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_branch<R>(mut self, name: &str, runnable: R) -> Self
+    where
+        R: Runnable<Input = I, Output = O> + 'static,
+    {
+        self.branches.push((name.to_string(), Arc::new(runnable)));
+        self
+    }
+}
+
+#[async_trait]
+impl<I, O> Runnable for RunnableParallel<I, O>
+where
+    I: Clone + Send + Sync + 'static,
+    O: Send + 'static,
+{
+    type Input = I;
+    type Output = HashMap<String, O>;
+
+    async fn invoke(&self, input: I) -> Result<HashMap<String, O>> {
+        let results = join_all(self.branches.iter().map(|(_, branch)| branch.invoke(input.clone()))).await;
+        self.branches
+            .iter()
+            .zip(results)
+            .map(|((name, _), result)| Ok((name.clone(), result?)))
+            .collect()
+    }
+}
+
+/// Returns its input unchanged, e.g. to carry the question alongside retrieved context.
+pub struct RunnablePassthrough<T> {
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RunnablePassthrough<T> {
+    pub fn new() -> Self {
+        Self { _marker: PhantomData }
+    }
+}
+
+impl<T> Default for RunnablePassthrough<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<T: Send + 'static> Runnable for RunnablePassthrough<T> {
+    type Input = T;
+    type Output = T;
+
+    async fn invoke(&self, input: T) -> Result<T> {
+        Ok(input)
+    }
+}
+
+/// Wraps a plain function, e.g. to join retrieved documents into a context string.
+pub struct RunnableLambda<F, I, O> {
+    f: F,
+    _marker: PhantomData<fn(I) -> O>,
+}
+
+impl<F, I, O> RunnableLambda<F, I, O>
+where
+    F: Fn(I) -> Result<O> + Send + Sync,
+{
+    pub fn new(f: F) -> Self {
+        Self { f, _marker: PhantomData }
+    }
+}
+
+#[async_trait]
+impl<F, I, O> Runnable for RunnableLambda<F, I, O>
+where
+    F: Fn(I) -> Result<O> + Send + Sync,
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    type Input = I;
+    type Output = O;
+
+    async fn invoke(&self, input: I) -> Result<O> {
+        (self.f)(input)
+    }
+}
+
+#[async_trait]
+impl Runnable for PromptTemplate {
+    type Input = HashMap<String, String>;
+    type Output = PromptValue;
+
+    async fn invoke(&self, input: Self::Input) -> Result<PromptValue> {
+        Ok(PromptValue::Text(self.format(&input)?))
+    }
+}
+
+/// Placeholders can't be filled from string values, so they must be optional.
+#[async_trait]
+impl Runnable for ChatPromptTemplate {
+    type Input = HashMap<String, String>;
+    type Output = PromptValue;
+
+    async fn invoke(&self, input: Self::Input) -> Result<PromptValue> {
+        Ok(PromptValue::Messages(self.format_messages(&input, &HashMap::new())?))
+    }
+}
+
+#[async_trait]
+impl Runnable for dyn LLM {
+    type Input = PromptValue;
+    type Output = String;
+
+    async fn invoke(&self, input: PromptValue) -> Result<String> {
+        Ok(crate::callbacks::trace_llm(self, input).await?.text)
+    }
+
+    /// Yields text as the provider streams it; usage-only chunks are skipped.
+    fn stream(&self, input: PromptValue) -> BoxStream<'_, Result<String>> {
+        crate::callbacks::trace_llm_stream(self, input)
+            .try_filter_map(|chunk| async move { Ok((!chunk.text.is_empty()).then_some(chunk.text)) })
+            .boxed()
+    }
+}
+
+#[async_trait]
+impl Runnable for LLMChain {
+    type Input = HashMap<String, String>;
+    type Output = String;
+
+    async fn invoke(&self, input: Self::Input) -> Result<String> {
+        self.call(input).await
+    }
+
+    async fn batch(&self, inputs: Vec<Self::Input>) -> Result<Vec<String>> {
+        LLMChain::batch(self, inputs, &BatchOptions::default()).await?.into_iter().collect()
+    }
+}
+
+#[async_trait]
+impl Runnable for VectorStoreRetriever {
+    type Input = String;
+    type Output = Vec<Document>;
+
+    async fn invoke(&self, query: String) -> Result<Vec<Document>> {
+        self.get_relevant_documents(&query).await
+    }
+}
+
+#[async_trait]
+impl<P: OutputParser> Runnable for P {
+    type Input = String;
+    type Output = P::Output;
+
+    async fn invoke(&self, text: String) -> Result<P::Output> {
+        self.parse(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::Embeddings;
+    use crate::output_parser::StrOutputParser;
+    use crate::vectorstore::{InMemoryVectorStore, VectorStore};
+
+    struct EchoLLM;
+
+    #[async_trait]
+    impl LLM for EchoLLM {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            Ok(format!("  {}  ", prompt))
+        }
+    }
+
+    struct LengthEmbeddings;
+
+    #[async_trait]
+    impl Embeddings for LengthEmbeddings {
+        async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+            Ok(vec![1.0, text.len() as f32])
+        }
+
+        async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|t| vec![1.0, t.len() as f32]).collect())
+        }
+    }
+
+    fn values(topic: &str) -> HashMap<String, String> {
+        HashMap::from([("topic".to_string(), topic.to_string())])
+    }
+
+    #[tokio::test]
+    async fn test_prompt_llm_parser_pipeline() {
+        let llm: Arc<dyn LLM> = Arc::new(EchoLLM);
+        let chain = PromptTemplate::from_template("Tell me about {topic}").unwrap()
+            .pipe(llm)
+            .pipe(StrOutputParser);
+
+        assert_eq!(chain.invoke(values("Rust")).await.unwrap(), "Tell me about Rust");
+        assert_eq!(
+            chain.batch(vec![values("a"), values("b")]).await.unwrap(),
+            ["Tell me about a", "Tell me about b"]
+        );
+        let chunks: Vec<String> = chain.stream(values("c")).map(Result::unwrap).collect().await;
+        assert_eq!(chunks, ["Tell me about c"]);
+        assert!(chain.invoke(HashMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parallel_retrieval_pipeline() {
+        let store = Arc::new(InMemoryVectorStore::new(Arc::new(LengthEmbeddings)));
+        store.add_documents(&[Document::new("short".to_string())]).await.unwrap();
+
+        let context = VectorStoreRetriever::new(store, 1).pipe(RunnableLambda::new(|docs: Vec<Document>| {
+            Ok(docs.into_iter().map(|d| d.page_content).collect::<Vec<_>>().join("\n"))
+        }));
+        let inputs = RunnableParallel::new()
+            .with_branch("context", context)
+            .with_branch("question", RunnablePassthrough::new());
+        let llm: Arc<dyn LLM> = Arc::new(EchoLLM);
+        let chain = inputs
+            .pipe(PromptTemplate::from_template("{context}\nQ: {question}").unwrap())
+            .pipe(llm)
+            .boxed();
+
+        assert_eq!(chain.invoke("why?".to_string()).await.unwrap().trim(), "short\nQ: why?");
+    }
+}
@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct Document {
@@ -19,3 +21,105 @@ impl Document {
         self
     }
 }
+
+/// Who authored a chat message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    Human,
+    AI,
+}
+
+impl Role {
+    /// Prefix used when a conversation is rendered as plain text.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Role::System => "System",
+            Role::Human => "Human",
+            Role::AI => "AI",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "system" => Ok(Role::System),
+            "human" | "user" => Ok(Role::Human),
+            "ai" | "assistant" => Ok(Role::AI),
+            other => Err(anyhow::anyhow!("Unknown message role: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: String) -> Self {
+        Self { role, content }
+    }
+
+    pub fn system(content: &str) -> Self {
+        Self::new(Role::System, content.to_string())
+    }
+
+    pub fn human(content: &str) -> Self {
+        Self::new(Role::Human, content.to_string())
+    }
+
+    pub fn ai(content: &str) -> Self {
+        Self::new(Role::AI, content.to_string())
+    }
+}
+
+/// A formatted prompt, either plain text or a conversation, as passed from a prompt to an LLM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PromptValue {
+    Text(String),
+    Messages(Vec<ChatMessage>),
+}
+
+impl PromptValue {
+    /// Text is treated as a single human message.
+    pub fn to_messages(&self) -> Vec<ChatMessage> {
+        match self {
+            PromptValue::Text(text) => vec![ChatMessage::human(text)],
+            PromptValue::Messages(messages) => messages.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for PromptValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromptValue::Text(text) => f.write_str(text),
+            PromptValue::Messages(messages) => f.write_str(&crate::llm::messages_to_prompt(messages)),
+        }
+    }
+}
+
+impl From<String> for PromptValue {
+    fn from(text: String) -> Self {
+        PromptValue::Text(text)
+    }
+}
+
+impl From<&str> for PromptValue {
+    fn from(text: &str) -> Self {
+        PromptValue::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ChatMessage>> for PromptValue {
+    fn from(messages: Vec<ChatMessage>) -> Self {
+        PromptValue::Messages(messages)
+    }
+}
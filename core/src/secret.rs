@@ -0,0 +1,144 @@
+use anyhow::{Result, Context, anyhow};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A string that never prints its contents through `Debug` or `Display`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(secret: String) -> Self {
+        Self(secret)
+    }
+
+    /// Returns the raw secret. Only call this where the value is sent, never when logging.
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_string())
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString([REDACTED])")
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+/// Callback returning the current key, e.g. from a vault client that rotates credentials.
+pub type KeyProvider = Arc<dyn Fn() -> Result<SecretString> + Send + Sync>;
+
+/// Where a provider gets its API key from. Everything except `Static` is re-read
+/// on every request, so rotated keys are picked up without rebuilding the provider.
+#[derive(Clone)]
+pub enum ApiKey {
+    Static(SecretString),
+    Env(String),
+    File(PathBuf),
+    Provider(KeyProvider),
+}
+
+impl ApiKey {
+    /// Uses the explicit key when given, otherwise the first of `vars` that is set
+    /// (falling back to the first name so the error mentions it).
+    pub fn from_option_or_env(api_key: Option<String>, vars: &[&str]) -> Self {
+        match api_key {
+            Some(key) => Self::Static(SecretString::new(key)),
+            None => {
+                let var = vars.iter()
+                    .find(|v| std::env::var(v).is_ok())
+                    .or_else(|| vars.first())
+                    .copied()
+                    .unwrap_or_default();
+                Self::Env(var.to_string())
+            }
+        }
+    }
+
+    pub fn resolve(&self) -> Result<SecretString> {
+        let key = match self {
+            Self::Static(secret) => secret.clone(),
+            Self::Env(var) => std::env::var(var)
+                .map(SecretString::new)
+                .map_err(|_| anyhow!("API key must be provided or set in the {} env var", var))?,
+            Self::File(path) => std::fs::read_to_string(path)
+                .map(|s| SecretString::new(s.trim().to_string()))
+                .with_context(|| format!("Failed to read API key file {}", path.display()))?,
+            Self::Provider(provider) => provider().context("API key provider failed")?,
+        };
+        if key.expose_secret().is_empty() {
+            return Err(anyhow!("API key from {:?} is empty", self));
+        }
+        Ok(key)
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Static(_) => f.write_str("ApiKey::Static([REDACTED])"),
+            Self::Env(var) => write!(f, "ApiKey::Env({})", var),
+            Self::File(path) => write!(f, "ApiKey::File({})", path.display()),
+            Self::Provider(_) => f.write_str("ApiKey::Provider(..)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_secret_is_redacted() {
+        let secret = SecretString::from("sk-live-123");
+        assert_eq!(format!("{}", secret), "[REDACTED]");
+        assert!(!format!("{:?}", secret).contains("sk-live"));
+        assert!(!format!("{:?}", ApiKey::Static(secret.clone())).contains("sk-live"));
+        assert_eq!(secret.expose_secret(), "sk-live-123");
+    }
+
+    #[test]
+    fn test_file_and_provider_sources() {
+        let path = std::env::temp_dir().join(format!("mini_langchain_key_{}", std::process::id()));
+        std::fs::write(&path, "file-key\n").unwrap();
+        let key = ApiKey::File(path.clone());
+        assert_eq!(key.resolve().unwrap().expose_secret(), "file-key");
+        std::fs::write(&path, "rotated-key").unwrap();
+        assert_eq!(key.resolve().unwrap().expose_secret(), "rotated-key");
+        std::fs::remove_file(&path).unwrap();
+        assert!(key.resolve().is_err());
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let c = counter.clone();
+        let key = ApiKey::Provider(Arc::new(move || {
+            Ok(SecretString::new(format!("key-{}", c.fetch_add(1, Ordering::SeqCst))))
+        }));
+        assert_eq!(key.resolve().unwrap().expose_secret(), "key-0");
+        assert_eq!(key.resolve().unwrap().expose_secret(), "key-1");
+    }
+
+    #[test]
+    fn test_missing_env_error_names_variable() {
+        let key = ApiKey::from_option_or_env(None, &["MINI_LANGCHAIN_TEST_UNSET_KEY"]);
+        let err = key.resolve().unwrap_err().to_string();
+        assert!(err.contains("MINI_LANGCHAIN_TEST_UNSET_KEY"));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use anyhow::Result;
+use crate::callbacks::trace_chain;
+use crate::chain::LLMChain;
+use crate::runnable::Runnable;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SequentialChainError {
+    #[error("Step '{step}' needs variables that no input or earlier step provides: {}", missing.join(", "))]
+    MissingVariables { step: String, missing: Vec<String> },
+    #[error("Key '{0}' is already an input or an earlier step's output")]
+    DuplicateKey(String),
+}
+
+#[derive(Clone)]
+struct Step {
+    chain: LLMChain,
+    output_key: String,
+}
+
+/// Runs `LLMChain`s in order, storing each output under its key so later prompts can use it.
+///
+/// Every step is checked when it is added, so a chain that builds successfully never
+/// fails on a missing variable.
+#[derive(Clone)]
+pub struct SequentialChain {
+    input_variables: Vec<String>,
+    steps: Vec<Step>,
+}
+
+impl SequentialChain {
+    pub fn new(input_variables: Vec<String>) -> Self {
+        Self { input_variables, steps: Vec::new() }
+    }
+
+    /// Appends a step whose output is stored under `output_key`.
+    /// Fails if the step's prompt uses a variable that isn't available yet.
+    pub fn with_step(mut self, output_key: &str, chain: LLMChain) -> Result<Self> {
+        let available = self.available_variables();
+        if available.iter().any(|k| k == output_key) {
+            return Err(SequentialChainError::DuplicateKey(output_key.to_string()).into());
+        }
+        let missing: Vec<String> = chain.input_variables()
+            .into_iter()
+            .filter(|name| !available.contains(name))
+            .collect();
+        if !missing.is_empty() {
+            return Err(SequentialChainError::MissingVariables { step: output_key.to_string(), missing }.into());
+        }
+
+        self.steps.push(Step { chain, output_key: output_key.to_string() });
+        Ok(self)
+    }
+
+    pub fn input_variables(&self) -> &[String] {
+        &self.input_variables
+    }
+
+    /// Output keys of the steps, in execution order.
+    pub fn output_variables(&self) -> Vec<String> {
+        self.steps.iter().map(|s| s.output_key.clone()).collect()
+    }
+
+    fn available_variables(&self) -> Vec<String> {
+        self.input_variables.iter().cloned().chain(self.output_variables()).collect()
+    }
+
+    /// Returns the inputs together with every step's output.
+    pub async fn call(&self, inputs: HashMap<String, String>) -> Result<HashMap<String, String>> {
+        let traced_inputs = inputs.clone();
+        trace_chain("SequentialChain", &traced_inputs, Clone::clone, self.call_inner(inputs)).await
+    }
+
+    async fn call_inner(&self, inputs: HashMap<String, String>) -> Result<HashMap<String, String>> {
+        if let Some(missing) = self.input_variables.iter().find(|k| !inputs.contains_key(*k)) {
+            return Err(crate::prompt::PromptError::MissingVariable(missing.clone()).into());
+        }
+
+        let mut values = inputs;
+        for step in &self.steps {
+            let step_inputs = step.chain.input_variables()
+                .into_iter()
+                .map(|name| {
+                    let value = values[&name].clone();
+                    (name, value)
+                })
+                .collect();
+            let output = step.chain.call(step_inputs).await?;
+            values.insert(step.output_key.clone(), output);
+        }
+        Ok(values)
+    }
+}
+
+#[async_trait]
+impl Runnable for SequentialChain {
+    type Input = HashMap<String, String>;
+    type Output = HashMap<String, String>;
+
+    async fn invoke(&self, input: Self::Input) -> Result<Self::Output> {
+        self.call(input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::llm::LLM;
+    use crate::minify::MinifyPolicy;
+    use crate::prompt::PromptTemplate;
+
+    struct UpperLLM;
+
+    #[async_trait]
+    impl LLM for UpperLLM {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            Ok(prompt.to_uppercase())
+        }
+    }
+
+    fn chain(template: &str) -> LLMChain {
+        LLMChain::new(PromptTemplate::from_template(template).unwrap(), Arc::new(UpperLLM))
+            .with_minify(MinifyPolicy::Off)
+    }
+
+    #[tokio::test]
+    async fn test_outputs_feed_later_steps() {
+        let sequence = SequentialChain::new(vec!["title".to_string(), "era".to_string()])
+            .with_step("synopsis", chain("synopsis of {title}")).unwrap()
+            .with_step("review", chain("review of {synopsis} set in {era}")).unwrap();
+        assert_eq!(sequence.output_variables(), ["synopsis", "review"]);
+
+        let outputs = sequence
+            .call(HashMap::from([
+                ("title".to_string(), "Dune".to_string()),
+                ("era".to_string(), "the future".to_string()),
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(outputs["synopsis"], "SYNOPSIS OF DUNE");
+        assert_eq!(outputs["review"], "REVIEW OF SYNOPSIS OF DUNE SET IN THE FUTURE");
+        assert_eq!(outputs["title"], "Dune");
+
+        assert!(sequence.call(HashMap::from([("title".to_string(), "Dune".to_string())])).await.is_err());
+    }
+
+    #[test]
+    fn test_build_time_validation() {
+        let err = SequentialChain::new(vec!["title".to_string()])
+            .with_step("review", chain("review of {synopsis} by {critic}"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<SequentialChainError>(),
+            Some(&SequentialChainError::MissingVariables {
+                step: "review".to_string(),
+                missing: vec!["synopsis".to_string(), "critic".to_string()],
+            })
+        );
+
+        let err = SequentialChain::new(vec!["title".to_string()])
+            .with_step("title", chain("{title}"))
+            .err()
+            .unwrap();
+        assert_eq!(err.downcast_ref(), Some(&SequentialChainError::DuplicateKey("title".to_string())));
+
+        let partial = LLMChain::new(
+            PromptTemplate::from_template("{tone} review of {title}").unwrap().with_partial("tone", "kind").unwrap(),
+            Arc::new(UpperLLM),
+        );
+        assert!(SequentialChain::new(vec!["title".to_string()]).with_step("review", partial).is_ok());
+    }
+}
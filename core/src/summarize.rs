@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use anyhow::{bail, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use crate::callbacks::{trace_chain, trace_llm};
+use crate::llm::LLM;
+use crate::prompt::PromptTemplate;
+use crate::runnable::Runnable;
+use crate::schema::{Document, PromptValue};
+use crate::token::TokenCounter;
+
+const SUMMARY_TEMPLATE: &str = "Write a concise summary of the following:\n\n{text}\n\nCONCISE SUMMARY:";
+
+const REFINE_TEMPLATE: &str = "Your job is to produce a final summary.\n\
+We have an existing summary up to a certain point:\n{existing_summary}\n\n\
+Refine the existing summary (only if needed) with the additional context below.\n\
+------------\n{text}\n------------\n\
+Given the new context, refine the original summary. If the context isn't useful, return the original summary.\n\
+REFINED SUMMARY:";
+
+/// How documents are fed to the LLM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummarizeStrategy {
+    /// All documents in a single prompt. Cheapest, but only for text that fits the context window.
+    Stuff,
+    /// Summarize each document concurrently, then combine the summaries, collapsing them in
+    /// groups first while they exceed the token budget.
+    MapReduce,
+    /// Summarize the first document, then refine the summary with each following document in turn.
+    Refine,
+}
+
+/// Summarizes a list of documents. Prompts receive the text as `{text}`; the refine prompt
+/// also receives the summary so far as `{existing_summary}`.
+#[derive(Clone)]
+pub struct SummarizeChain {
+    llm: Arc<dyn LLM>,
+    strategy: SummarizeStrategy,
+    prompt: PromptTemplate,
+    combine_prompt: PromptTemplate,
+    refine_prompt: PromptTemplate,
+    separator: String,
+    token_max: usize,
+    concurrency: usize,
+}
+
+impl SummarizeChain {
+    pub fn new(llm: Arc<dyn LLM>, strategy: SummarizeStrategy) -> Self {
+        let summary = PromptTemplate::from_template(SUMMARY_TEMPLATE).unwrap();
+        Self {
+            llm,
+            strategy,
+            prompt: summary.clone(),
+            combine_prompt: summary,
+            refine_prompt: PromptTemplate::from_template(REFINE_TEMPLATE).unwrap(),
+            separator: "\n\n".to_string(),
+            token_max: 3000,
+            concurrency: 8,
+        }
+    }
+
+    pub fn stuff(llm: Arc<dyn LLM>) -> Self {
+        Self::new(llm, SummarizeStrategy::Stuff)
+    }
+
+    pub fn map_reduce(llm: Arc<dyn LLM>) -> Self {
+        Self::new(llm, SummarizeStrategy::MapReduce)
+    }
+
+    pub fn refine(llm: Arc<dyn LLM>) -> Self {
+        Self::new(llm, SummarizeStrategy::Refine)
+    }
+
+    /// Prompt for the stuff call, each map call and the first refine call.
+    pub fn with_prompt(mut self, prompt: PromptTemplate) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Prompt that merges summaries, used when collapsing and for the final map-reduce step.
+    pub fn with_combine_prompt(mut self, prompt: PromptTemplate) -> Self {
+        self.combine_prompt = prompt;
+        self
+    }
+
+    pub fn with_refine_prompt(mut self, prompt: PromptTemplate) -> Self {
+        self.refine_prompt = prompt;
+        self
+    }
+
+    /// Joins documents (or summaries) within one prompt. Defaults to a blank line.
+    pub fn with_separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// Map-reduce keeps collapsing summaries until together they fit this many tokens. Defaults to 3000.
+    pub fn with_token_max(mut self, token_max: usize) -> Self {
+        self.token_max = token_max;
+        self
+    }
+
+    /// Maximum concurrent LLM calls during map-reduce. Defaults to 8.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn summarize(&self, docs: &[Document]) -> Result<String> {
+        let inputs = HashMap::from([("documents".to_string(), docs.len().to_string())]);
+        let outputs = |summary: &String| HashMap::from([("output".to_string(), summary.clone())]);
+        trace_chain("SummarizeChain", &inputs, outputs, self.summarize_inner(docs)).await
+    }
+
+    async fn summarize_inner(&self, docs: &[Document]) -> Result<String> {
+        if docs.is_empty() {
+            bail!("No documents to summarize");
+        }
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        match self.strategy {
+            SummarizeStrategy::Stuff => self.run(&self.prompt, &texts.join(&self.separator), None).await,
+            SummarizeStrategy::MapReduce => self.map_reduce_texts(texts).await,
+            SummarizeStrategy::Refine => self.refine_texts(&texts).await,
+        }
+    }
+
+    async fn run(&self, prompt: &PromptTemplate, text: &str, existing_summary: Option<&str>) -> Result<String> {
+        let mut values = HashMap::from([("text".to_string(), text.to_string())]);
+        if let Some(summary) = existing_summary {
+            values.insert("existing_summary".to_string(), summary.to_string());
+        }
+        let prompt = PromptValue::Text(prompt.format(&values)?);
+        Ok(trace_llm(self.llm.as_ref(), prompt).await?.text.trim().to_string())
+    }
+
+    /// Runs `prompt` over every text with at most `concurrency` calls in flight, keeping order.
+    async fn run_all(&self, prompt: &PromptTemplate, texts: Vec<String>) -> Result<Vec<String>> {
+        stream::iter(texts)
+            .map(|text| async move { self.run(prompt, &text, None).await })
+            .buffered(self.concurrency)
+            .try_collect()
+            .await
+    }
+
+    async fn map_reduce_texts(&self, texts: Vec<String>) -> Result<String> {
+        let mut summaries = self.run_all(&self.prompt, texts).await?;
+
+        while self.tokens(&summaries) > self.token_max {
+            let groups = self.group_by_budget(&summaries);
+            if groups.len() == summaries.len() {
+                bail!(
+                    "Cannot collapse summaries below token_max ({}): each summary is too large to combine with another",
+                    self.token_max
+                );
+            }
+            let joined = groups.into_iter().map(|g| g.join(&self.separator)).collect();
+            summaries = self.run_all(&self.combine_prompt, joined).await?;
+        }
+
+        self.run(&self.combine_prompt, &summaries.join(&self.separator), None).await
+    }
+
+    fn tokens(&self, texts: &[String]) -> usize {
+        TokenCounter::count(&texts.join(&self.separator))
+    }
+
+    /// Greedily packs consecutive summaries into groups of at most `token_max` tokens.
+    fn group_by_budget<'a>(&self, summaries: &'a [String]) -> Vec<Vec<&'a str>> {
+        let separator_tokens = TokenCounter::count(&self.separator);
+        let mut groups: Vec<Vec<&str>> = Vec::new();
+        let mut current: Vec<&str> = Vec::new();
+        let mut current_tokens = 0;
+        for summary in summaries {
+            let tokens = TokenCounter::count(summary);
+            if !current.is_empty() && current_tokens + separator_tokens + tokens > self.token_max {
+                groups.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+            current_tokens += if current.is_empty() { tokens } else { separator_tokens + tokens };
+            current.push(summary);
+        }
+        groups.push(current);
+        groups
+    }
+
+    async fn refine_texts(&self, texts: &[String]) -> Result<String> {
+        let mut summary = self.run(&self.prompt, &texts[0], None).await?;
+        for text in &texts[1..] {
+            summary = self.run(&self.refine_prompt, text, Some(&summary)).await?;
+        }
+        Ok(summary)
+    }
+}
+
+#[async_trait]
+impl Runnable for SummarizeChain {
+    type Input = Vec<Document>;
+    type Output = String;
+
+    async fn invoke(&self, docs: Vec<Document>) -> Result<String> {
+        self.summarize(&docs).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Answers every prompt with a short, numbered summary and records the prompts.
+    #[derive(Default)]
+    struct RecordingLLM {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LLM for RecordingLLM {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            let mut prompts = self.prompts.lock().unwrap();
+            prompts.push(prompt.to_string());
+            Ok(format!("summary {}", prompts.len()))
+        }
+    }
+
+    fn docs(n: usize) -> Vec<Document> {
+        (0..n).map(|i| Document::new(format!("document {}", i))).collect()
+    }
+
+    fn prompt(template: &str) -> PromptTemplate {
+        PromptTemplate::from_template(template).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stuff_and_refine() {
+        let llm = Arc::new(RecordingLLM::default());
+        let stuff = SummarizeChain::stuff(llm.clone()).with_prompt(prompt("sum: {text}"));
+        assert_eq!(stuff.summarize(&docs(2)).await.unwrap(), "summary 1");
+        assert_eq!(llm.prompts.lock().unwrap()[0], "sum: document 0\n\ndocument 1");
+
+        let llm = Arc::new(RecordingLLM::default());
+        let refine = SummarizeChain::refine(llm.clone())
+            .with_prompt(prompt("sum: {text}"))
+            .with_refine_prompt(prompt("{existing_summary} + {text}"));
+        assert_eq!(refine.summarize(&docs(3)).await.unwrap(), "summary 3");
+        assert_eq!(*llm.prompts.lock().unwrap(), ["sum: document 0", "summary 1 + document 1", "summary 2 + document 2"]);
+
+        assert!(refine.summarize(&[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_map_reduce_collapses_to_budget() {
+        let map_reduce = |llm: Arc<RecordingLLM>| {
+            SummarizeChain::map_reduce(llm)
+                .with_prompt(prompt("map: {text}"))
+                .with_combine_prompt(prompt("combine: {text}"))
+                .with_concurrency(3)
+        };
+
+        let llm = Arc::new(RecordingLLM::default());
+        map_reduce(llm.clone()).summarize(&docs(10)).await.unwrap();
+        let prompts = llm.prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 11);
+        assert_eq!(prompts.iter().filter(|p| p.starts_with("combine")).count(), 1);
+
+        let llm = Arc::new(RecordingLLM::default());
+        map_reduce(llm.clone()).with_token_max(10).summarize(&docs(10)).await.unwrap();
+        let prompts = llm.prompts.lock().unwrap().clone();
+        let combines: Vec<&String> = prompts.iter().filter(|p| p.starts_with("combine")).collect();
+        assert_eq!(prompts.len() - combines.len(), 10);
+        // Groups were collapsed before the final combine, each within the budget.
+        assert!(combines.len() > 2);
+        assert!(combines.iter().all(|p| TokenCounter::count(&p["combine: ".len()..]) <= 10));
+
+        let impossible = SummarizeChain::map_reduce(Arc::new(RecordingLLM::default())).with_token_max(1);
+        assert!(impossible.summarize(&docs(3)).await.is_err());
+    }
+}
@@ -1,4 +1,11 @@
-use tiktoken_rs::cl100k_base;
+use std::sync::OnceLock;
+use tiktoken_rs::{cl100k_base, CoreBPE};
+
+/// Building the encoder parses a large vocabulary, so it is done once per process.
+fn encoder() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| cl100k_base().unwrap())
+}
 
 pub struct TokenCounter;
 
@@ -7,11 +14,20 @@ impl TokenCounter {
     pub fn count(text: &str) -> usize {
         // For accurate counting we should ideally use the specific model encoding.
         // But cl100k_base is a safe, high-performance default for modern LLMs.
-        let bpe = cl100k_base().unwrap();
-        let tokens = bpe.encode_with_special_tokens(text);
+        let tokens = encoder().encode_with_special_tokens(text);
         tokens.len()
     }
 
+    /// Token ids of `text` under the same encoding as `count`.
+    pub fn encode(text: &str) -> Vec<usize> {
+        encoder().encode_with_special_tokens(text)
+    }
+
+    /// Turns token ids back into text. Fails if the ids split a multi-byte character.
+    pub fn decode(tokens: &[usize]) -> anyhow::Result<String> {
+        encoder().decode(tokens.to_vec())
+    }
+
     /// Estimates cost based on input/output tokens and rate per 1k tokens.
     /// Returns estimated cost in USD.
     pub fn estimate_cost(text: &str, rate_per_1k: f64) -> f64 {
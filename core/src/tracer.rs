@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::callbacks::{CallbackHandler, RunId, RunInfo};
+use crate::llm::{Generation, TokenUsage};
+use crate::schema::{Document, PromptValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunKind {
+    Chain,
+    Llm,
+    Tool,
+    Retriever,
+}
+
+/// One finished run. Spans of the same top-level run share its id as `trace_id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSpan {
+    pub run_id: RunId,
+    pub parent_run_id: Option<RunId>,
+    pub trace_id: RunId,
+    pub kind: RunKind,
+    pub name: String,
+    pub inputs: HashMap<String, String>,
+    pub outputs: HashMap<String, String>,
+    pub error: Option<String>,
+    pub usage: Option<TokenUsage>,
+    pub start_time_unix_nano: u64,
+    pub end_time_unix_nano: u64,
+}
+
+impl RunSpan {
+    pub fn latency_ms(&self) -> f64 {
+        self.end_time_unix_nano.saturating_sub(self.start_time_unix_nano) as f64 / 1e6
+    }
+}
+
+/// A span with its child runs, in start order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunTree {
+    pub span: RunSpan,
+    pub children: Vec<RunTree>,
+}
+
+fn now_unix_nano() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// Destination for finished spans.
+#[async_trait]
+pub trait SpanExporter: Send + Sync {
+    async fn export(&self, spans: &[RunSpan]) -> Result<()>;
+}
+
+#[derive(Default)]
+struct TracerState {
+    active: HashMap<RunId, RunSpan>,
+    finished: Vec<RunSpan>,
+}
+
+/// Callback handler recording a `RunSpan` for every chain, LLM, tool and retriever run.
+///
+/// Finished spans are kept until taken with `take_finished` or `export`. With
+/// `with_auto_export`, each trace is instead sent to the exporter as soon as its top-level
+/// run ends.
+#[derive(Default)]
+pub struct Tracer {
+    state: Mutex<TracerState>,
+    auto_export: Option<Arc<dyn SpanExporter>>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exports every trace on completion from a spawned task; failures are logged with `tracing`.
+    /// Requires a Tokio runtime; without one the spans stay in `finished_spans`.
+    pub fn with_auto_export(mut self, exporter: Arc<dyn SpanExporter>) -> Self {
+        self.auto_export = Some(exporter);
+        self
+    }
+
+    pub fn finished_spans(&self) -> Vec<RunSpan> {
+        self.state.lock().unwrap().finished.clone()
+    }
+
+    pub fn take_finished(&self) -> Vec<RunSpan> {
+        std::mem::take(&mut self.state.lock().unwrap().finished)
+    }
+
+    /// Finished spans arranged by parent; a span whose parent was not recorded is a root.
+    pub fn trees(&self) -> Vec<RunTree> {
+        build_trees(self.finished_spans())
+    }
+
+    /// Sends the finished spans to `exporter`, keeping them if the export fails.
+    pub async fn export(&self, exporter: &dyn SpanExporter) -> Result<()> {
+        let spans = self.take_finished();
+        if spans.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = exporter.export(&spans).await {
+            self.state.lock().unwrap().finished.splice(0..0, spans);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// A run whose parent this tracer has not seen (e.g. a tracer attached inside an outer
+    /// run) starts its own trace.
+    fn start(&self, run: &RunInfo, kind: RunKind, name: &str, inputs: HashMap<String, String>) {
+        let mut state = self.state.lock().unwrap();
+        let trace_id = run
+            .parent_run_id
+            .and_then(|p| state.active.get(&p))
+            .map_or(run.run_id, |parent| parent.trace_id);
+        let span = RunSpan {
+            run_id: run.run_id,
+            parent_run_id: run.parent_run_id,
+            trace_id,
+            kind,
+            name: name.to_string(),
+            inputs,
+            outputs: HashMap::new(),
+            error: None,
+            usage: None,
+            start_time_unix_nano: now_unix_nano(),
+            end_time_unix_nano: 0,
+        };
+        state.active.insert(run.run_id, span);
+    }
+
+    fn end(&self, run: &RunInfo, outputs: HashMap<String, String>, error: Option<String>, usage: Option<TokenUsage>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(mut span) = state.active.remove(&run.run_id) else {
+            return;
+        };
+        span.outputs = outputs;
+        span.error = error;
+        span.usage = usage;
+        span.end_time_unix_nano = now_unix_nano();
+        let trace_id = span.trace_id;
+        let is_root = span.run_id == trace_id;
+        state.finished.push(span);
+
+        if let (true, Some(exporter)) = (is_root, &self.auto_export) {
+            let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+                return;
+            };
+            let (trace, rest) = std::mem::take(&mut state.finished)
+                .into_iter()
+                .partition(|s| s.trace_id == trace_id);
+            state.finished = rest;
+            let exporter = exporter.clone();
+            runtime.spawn(async move {
+                let trace: Vec<RunSpan> = trace;
+                if let Err(e) = exporter.export(&trace).await {
+                    tracing::warn!(target: "mini_langchain", trace_id = %trace_id, error = %e, "trace export failed");
+                }
+            });
+        }
+    }
+}
+
+fn single(key: &str, value: impl Into<String>) -> HashMap<String, String> {
+    HashMap::from([(key.to_string(), value.into())])
+}
+
+impl CallbackHandler for Tracer {
+    fn on_chain_start(&self, run: &RunInfo, name: &str, inputs: &HashMap<String, String>) {
+        self.start(run, RunKind::Chain, name, inputs.clone());
+    }
+
+    fn on_chain_end(&self, run: &RunInfo, outputs: &HashMap<String, String>) {
+        self.end(run, outputs.clone(), None, None);
+    }
+
+    fn on_chain_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        self.end(run, HashMap::new(), Some(error.to_string()), None);
+    }
+
+    fn on_llm_start(&self, run: &RunInfo, prompt: &PromptValue) {
+        self.start(run, RunKind::Llm, "LLM", single("prompt", prompt.to_string()));
+    }
+
+    /// The span is renamed after the model that answered, when it is known.
+    fn on_llm_end(&self, run: &RunInfo, generation: &Generation) {
+        if let Some(model) = &generation.model {
+            if let Some(span) = self.state.lock().unwrap().active.get_mut(&run.run_id) {
+                span.name = model.clone();
+            }
+        }
+        self.end(run, single("text", generation.text.clone()), None, generation.usage.clone());
+    }
+
+    fn on_llm_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        self.end(run, HashMap::new(), Some(error.to_string()), None);
+    }
+
+    fn on_tool_start(&self, run: &RunInfo, tool: &str, input: &str) {
+        self.start(run, RunKind::Tool, tool, single("input", input));
+    }
+
+    fn on_tool_end(&self, run: &RunInfo, output: &str) {
+        self.end(run, single("output", output), None, None);
+    }
+
+    fn on_tool_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        self.end(run, HashMap::new(), Some(error.to_string()), None);
+    }
+
+    fn on_retriever_start(&self, run: &RunInfo, query: &str) {
+        self.start(run, RunKind::Retriever, "Retriever", single("query", query));
+    }
+
+    fn on_retriever_end(&self, run: &RunInfo, documents: &[Document]) {
+        let contents: Vec<&str> = documents.iter().map(|d| d.page_content.as_str()).collect();
+        let outputs = single("documents", serde_json::to_string(&contents).unwrap_or_default());
+        self.end(run, outputs, None, None);
+    }
+
+    fn on_retriever_error(&self, run: &RunInfo, error: &anyhow::Error) {
+        self.end(run, HashMap::new(), Some(error.to_string()), None);
+    }
+}
+
+fn build_trees(spans: Vec<RunSpan>) -> Vec<RunTree> {
+    let ids: Vec<RunId> = spans.iter().map(|s| s.run_id).collect();
+    let mut children: HashMap<RunId, Vec<RunSpan>> = HashMap::new();
+    let mut roots = Vec::new();
+    for span in spans {
+        match span.parent_run_id.filter(|p| ids.contains(p)) {
+            Some(parent) => children.entry(parent).or_default().push(span),
+            None => roots.push(span),
+        }
+    }
+
+    fn attach(span: RunSpan, children: &mut HashMap<RunId, Vec<RunSpan>>) -> RunTree {
+        let mut kids = children.remove(&span.run_id).unwrap_or_default();
+        kids.sort_by_key(|s| s.start_time_unix_nano);
+        let kids = kids.into_iter().map(|k| attach(k, children)).collect();
+        RunTree { span, children: kids }
+    }
+
+    roots.sort_by_key(|s| s.start_time_unix_nano);
+    roots.into_iter().map(|r| attach(r, &mut children)).collect()
+}
+
+/// Appends spans to a JSONL file, one `RunSpan` per line.
+pub struct JsonlExporter {
+    path: PathBuf,
+}
+
+impl JsonlExporter {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Reads back spans written by this exporter.
+    pub fn read(&self) -> Result<Vec<RunSpan>> {
+        let contents = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read traces {}", self.path.display()))?;
+        contents
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| Ok(serde_json::from_str(l)?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SpanExporter for JsonlExporter {
+    async fn export(&self, spans: &[RunSpan]) -> Result<()> {
+        let mut lines = String::new();
+        for span in spans {
+            lines.push_str(&serde_json::to_string(span)?);
+            lines.push('\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open traces {}", self.path.display()))?;
+        file.write_all(lines.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Sends spans to an OpenTelemetry collector over OTLP/HTTP with JSON encoding.
+pub struct OtlpExporter {
+    endpoint: String,
+    service_name: String,
+    headers: HashMap<String, String>,
+    client: reqwest::Client,
+}
+
+impl OtlpExporter {
+    /// `endpoint` is the collector's base URL, e.g. `http://localhost:4318`; spans are posted
+    /// to `{endpoint}/v1/traces`.
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            service_name: "mini_langchain".to_string(),
+            headers: HashMap::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// `service.name` resource attribute. Defaults to `mini_langchain`.
+    pub fn with_service_name(mut self, name: &str) -> Self {
+        self.service_name = name.to_string();
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// The OTLP `ExportTraceServiceRequest` body for `spans`.
+    pub fn payload(&self, spans: &[RunSpan]) -> Value {
+        let spans: Vec<Value> = spans.iter().map(otlp_span).collect();
+        json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [string_attribute("service.name", &self.service_name)]},
+                "scopeSpans": [{
+                    "scope": {"name": "mini_langchain", "version": env!("CARGO_PKG_VERSION")},
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+#[async_trait]
+impl SpanExporter for OtlpExporter {
+    async fn export(&self, spans: &[RunSpan]) -> Result<()> {
+        let mut request = self.client.post(format!("{}/v1/traces", self.endpoint)).json(&self.payload(spans));
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await.context("Failed to reach the OTLP collector")?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("OTLP collector returned {}: {}", status, body);
+        }
+        Ok(())
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({"key": key, "value": {"stringValue": value}})
+}
+
+fn int_attribute(key: &str, value: u32) -> Value {
+    // OTLP JSON encodes 64-bit integers as strings.
+    json!({"key": key, "value": {"intValue": value.to_string()}})
+}
+
+/// OTLP span ids are 8 bytes; the run's UUID is truncated to fit.
+fn span_id(run_id: &RunId) -> String {
+    run_id.simple().to_string()[..16].to_string()
+}
+
+fn otlp_span(span: &RunSpan) -> Value {
+    let kind = serde_json::to_value(span.kind).unwrap_or_default();
+    let mut attributes = vec![json!({"key": "mini_langchain.run_kind", "value": {"stringValue": kind}})];
+    let mut sorted = |prefix: &str, values: &HashMap<String, String>| {
+        let mut keys: Vec<&String> = values.keys().collect();
+        keys.sort();
+        for key in keys {
+            attributes.push(string_attribute(&format!("{}.{}", prefix, key), &values[key]));
+        }
+    };
+    sorted("input", &span.inputs);
+    sorted("output", &span.outputs);
+    if let Some(usage) = &span.usage {
+        attributes.push(int_attribute("llm.usage.prompt_tokens", usage.prompt_tokens));
+        attributes.push(int_attribute("llm.usage.completion_tokens", usage.completion_tokens));
+        attributes.push(int_attribute("llm.usage.total_tokens", usage.total_tokens));
+    }
+
+    // Status codes: 1 = OK, 2 = ERROR.
+    let status = match &span.error {
+        Some(message) => json!({"code": 2, "message": message}),
+        None => json!({"code": 1}),
+    };
+    let mut otlp = json!({
+        "traceId": span.trace_id.simple().to_string(),
+        "spanId": span_id(&span.run_id),
+        "name": span.name,
+        "kind": 1,
+        "startTimeUnixNano": span.start_time_unix_nano.to_string(),
+        "endTimeUnixNano": span.end_time_unix_nano.to_string(),
+        "attributes": attributes,
+        "status": status,
+    });
+    if let Some(parent) = &span.parent_run_id {
+        otlp["parentSpanId"] = json!(span_id(parent));
+    }
+    otlp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{AgentExecutor, Tool};
+    use crate::callbacks::Callbacks;
+    use crate::llm::LLM;
+
+    struct UsageLLM;
+
+    #[async_trait]
+    impl LLM for UsageLLM {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            Ok("Action: fail\nInput: now".to_string())
+        }
+
+        async fn generate_with_usage(&self, prompt: &str) -> Result<Generation> {
+            let usage = TokenUsage { prompt_tokens: 12, completion_tokens: 5, total_tokens: 17, ..Default::default() };
+            Ok(Generation { text: self.generate(prompt).await?, usage: Some(usage), model: None })
+        }
+
+        fn model_name(&self) -> Option<String> {
+            Some("usage-model".to_string())
+        }
+    }
+
+    struct FailingTool;
+
+    #[async_trait]
+    impl Tool for FailingTool {
+        fn name(&self) -> String {
+            "fail".to_string()
+        }
+        fn description(&self) -> String {
+            "Always fails".to_string()
+        }
+        async fn call(&self, _input: &str) -> Result<String> {
+            anyhow::bail!("tool broke")
+        }
+    }
+
+    async fn traced_run(tracer: Arc<Tracer>) {
+        let agent = AgentExecutor::new(Arc::new(UsageLLM)).with_tool(Arc::new(FailingTool));
+        let result = Callbacks::new().with_handler(tracer).scope(agent.execute("go")).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_tree_with_usage_and_errors() {
+        let tracer = Arc::new(Tracer::new());
+        traced_run(tracer.clone()).await;
+
+        let trees = tracer.trees();
+        assert_eq!(trees.len(), 1);
+        let root = &trees[0];
+        assert_eq!((root.span.kind, root.span.name.as_str()), (RunKind::Chain, "AgentExecutor"));
+        assert_eq!(root.span.inputs["input"], "go");
+        assert_eq!(root.span.error.as_deref(), Some("tool broke"));
+        assert!(root.span.end_time_unix_nano >= root.span.start_time_unix_nano);
+
+        let kinds: Vec<RunKind> = root.children.iter().map(|c| c.span.kind).collect();
+        assert_eq!(kinds, [RunKind::Llm, RunKind::Tool]);
+        let llm = &root.children[0].span;
+        assert_eq!(llm.name, "usage-model");
+        assert_eq!(llm.usage.as_ref().unwrap().total_tokens, 17);
+        assert_eq!(llm.outputs["text"], "Action: fail\nInput: now");
+        assert!(root.children.iter().all(|c| c.span.trace_id == root.span.run_id));
+
+        let exporter = OtlpExporter::new("http://localhost:4318/").with_service_name("tests");
+        let payload = exporter.payload(&tracer.finished_spans());
+        let spans = payload["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap();
+        assert_eq!(spans.len(), 3);
+        let tool = spans.iter().find(|s| s["name"] == "fail").unwrap();
+        assert_eq!(tool["status"], json!({"code": 2, "message": "tool broke"}));
+        assert_eq!(tool["parentSpanId"], json!(span_id(&root.span.run_id)));
+        assert_eq!(tool["traceId"].as_str().unwrap().len(), 32);
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_export_and_auto_export() {
+        let path = std::env::temp_dir().join(format!("mini_langchain_traces_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let exporter = Arc::new(JsonlExporter::new(&path));
+
+        let tracer = Arc::new(Tracer::new());
+        traced_run(tracer.clone()).await;
+        let spans = tracer.finished_spans();
+        tracer.export(exporter.as_ref()).await.unwrap();
+        assert!(tracer.finished_spans().is_empty());
+        assert_eq!(exporter.read().unwrap(), spans);
+
+        let auto = Arc::new(Tracer::new().with_auto_export(exporter.clone()));
+        traced_run(auto.clone()).await;
+        assert!(auto.finished_spans().is_empty());
+        for _ in 0..50 {
+            if exporter.read().unwrap().len() == 6 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(exporter.read().unwrap().len(), 6);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[derive(Default)]
+    struct CollectingExporter {
+        spans: Mutex<Vec<RunSpan>>,
+    }
+
+    #[async_trait]
+    impl SpanExporter for CollectingExporter {
+        async fn export(&self, spans: &[RunSpan]) -> Result<()> {
+            self.spans.lock().unwrap().extend_from_slice(spans);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_export_inside_an_untraced_run() {
+        let exporter = Arc::new(CollectingExporter::default());
+        let tracer = Arc::new(Tracer::new().with_auto_export(exporter.clone()));
+
+        // The tracer only joins once the outer chain is running, so it never sees its parent.
+        let inner = async {
+            let agent = AgentExecutor::new(Arc::new(UsageLLM)).with_tool(Arc::new(FailingTool));
+            Callbacks::current().with_handler(tracer.clone()).scope(agent.execute("go")).await
+        };
+        let outer = crate::callbacks::trace_chain("outer", &HashMap::new(), |_| HashMap::new(), inner).await;
+        assert!(outer.is_err());
+
+        assert!(tracer.finished_spans().is_empty());
+        for _ in 0..50 {
+            if exporter.spans.lock().unwrap().len() == 3 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let spans = exporter.spans.lock().unwrap().clone();
+        assert_eq!(spans.len(), 3);
+        let root = spans.iter().find(|s| s.name == "AgentExecutor").unwrap();
+        assert_eq!(root.trace_id, root.run_id);
+        assert!(root.parent_run_id.is_some());
+        assert!(spans.iter().all(|s| s.trace_id == root.run_id));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::callbacks::trace_retriever;
 use crate::schema::Document;
 use crate::embedding::Embeddings;
 use async_trait::async_trait;
@@ -26,7 +27,7 @@ impl InMemoryVectorStore {
         }
     }
 
-    fn cosine_similarity(v1: &[f32], v2: &[f32]) -> f32 {
+    pub(crate) fn cosine_similarity(v1: &[f32], v2: &[f32]) -> f32 {
         let dot_product: f32 = v1.iter().zip(v2.iter()).map(|(a, b)| a * b).sum();
         let norm_a: f32 = v1.iter().map(|a| a * a).sum::<f32>().sqrt();
         let norm_b: f32 = v2.iter().map(|b| b * b).sum::<f32>().sqrt();
@@ -37,6 +38,60 @@ impl InMemoryVectorStore {
         
         dot_product / (norm_a * norm_b)
     }
+
+    /// Like `similarity_search`, but also returns the cosine similarity of each hit.
+    pub async fn similarity_search_with_score(&self, query: &str, k: usize) -> Result<Vec<(Document, f32)>> {
+        let query_vector = self.embeddings.embed_query(query).await?;
+        let stored_vectors = self.vectors.lock().unwrap();
+        let stored_docs = self.documents.lock().unwrap();
+
+        let mut scores: Vec<(usize, f32)> = stored_vectors.iter().enumerate()
+            .map(|(i, vec)| (i, Self::cosine_similarity(&query_vector, vec)))
+            .collect();
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+        Ok(scores.into_iter().take(k)
+            .map(|(i, score)| (stored_docs[i].clone(), score))
+            .collect())
+    }
+
+    /// Maximal marginal relevance: takes the `fetch_k` closest documents, then greedily picks `k`
+    /// of them, trading relevance to the query against similarity to what was already picked.
+    /// `lambda_mult` of 1.0 is pure relevance, 0.0 is maximum diversity.
+    pub async fn max_marginal_relevance_search(
+        &self,
+        query: &str,
+        k: usize,
+        fetch_k: usize,
+        lambda_mult: f32,
+    ) -> Result<Vec<Document>> {
+        let query_vector = self.embeddings.embed_query(query).await?;
+        let stored_vectors = self.vectors.lock().unwrap();
+        let stored_docs = self.documents.lock().unwrap();
+
+        let mut candidates: Vec<(usize, f32)> = stored_vectors.iter().enumerate()
+            .map(|(i, vec)| (i, Self::cosine_similarity(&query_vector, vec)))
+            .collect();
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        candidates.truncate(fetch_k.max(k));
+
+        let mut selected: Vec<usize> = Vec::new();
+        while selected.len() < k && !candidates.is_empty() {
+            let (best, _) = candidates.iter().enumerate()
+                .map(|(pos, &(i, relevance))| {
+                    let redundancy = selected.iter()
+                        .map(|&j| Self::cosine_similarity(&stored_vectors[i], &stored_vectors[j]))
+                        .fold(f32::NEG_INFINITY, f32::max);
+                    let redundancy = if selected.is_empty() { 0.0 } else { redundancy };
+                    (pos, lambda_mult * relevance - (1.0 - lambda_mult) * redundancy)
+                })
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                .unwrap();
+            selected.push(candidates.remove(best).0);
+        }
+
+        Ok(selected.into_iter().map(|i| stored_docs[i].clone()).collect())
+    }
 }
 
 #[async_trait]
@@ -78,3 +133,20 @@ impl VectorStore for InMemoryVectorStore {
         Ok(top_k)
     }
 }
+
+/// Fetches the `k` documents most similar to a query from any `VectorStore`.
+#[derive(Clone)]
+pub struct VectorStoreRetriever {
+    store: Arc<dyn VectorStore>,
+    k: usize,
+}
+
+impl VectorStoreRetriever {
+    pub fn new(store: Arc<dyn VectorStore>, k: usize) -> Self {
+        Self { store, k }
+    }
+
+    pub async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>> {
+        trace_retriever(query, self.store.similarity_search(query, self.k)).await
+    }
+}
@@ -25,7 +25,7 @@ async fn main() -> Result<()> {
     let prompt = PromptTemplate::new(
         "Write a haiku about {topic}.",
         vec!["topic".to_string()]
-    );
+    )?;
 
     // 3. Create Chain
     let chain = LLMChain::new(prompt, llm);
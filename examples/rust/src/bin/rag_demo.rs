@@ -1,8 +1,6 @@
 use anyhow::Result;
-use std::collections::HashMap;
 use std::sync::Arc;
-use mini_langchain_core::chain::LLMChain;
-use mini_langchain_core::prompt::PromptTemplate;
+use mini_langchain_core::retrieval_qa::RetrievalQA;
 use mini_langchain_core::providers::sambanova::SambaNovaProvider;
 use mini_langchain_core::loader::{TextLoader, Loader};
 use mini_langchain_core::vectorstore::{InMemoryVectorStore, VectorStore};
@@ -27,37 +25,27 @@ async fn main() -> Result<()> {
 
     // 3. Index
     let embeddings = Arc::new(MockEmbeddings);
-    let store = InMemoryVectorStore::new(embeddings);
+    let store = Arc::new(InMemoryVectorStore::new(embeddings));
     store.add_documents(&docs).await?;
 
-    // 4. Retrieve
-    let query = "Why use Rust?";
-    let results = store.similarity_search(query, 1).await?;
-    let context = results[0].page_content.clone();
-    println!("\nRetrieved Context: {}", context.trim());
-
-    // 5. Generate
-    let prompt = PromptTemplate::new(
-        "Context: {context}\n\nQuestion: {query}\n\nAnswer:",
-        vec!["context".to_string(), "query".to_string()]
-    );
-    
+    // 4. Retrieve and answer
     let llm = Arc::new(SambaNovaProvider::new(
         None,
         "Meta-Llama-3.1-8B-Instruct".to_string(),
         None, None, None, None, None
     )?);
+    let qa = RetrievalQA::new(llm, store).with_k(1);
 
-    let chain = LLMChain::new(prompt, llm);
-
-    let mut inputs = HashMap::new();
-    inputs.insert("context".to_string(), context);
-    inputs.insert("query".to_string(), query.to_string());
-
-    match chain.call(inputs).await {
-        Ok(response) => {
+    let query = "Why use Rust?";
+    match qa.call(query).await {
+        Ok(result) => {
+            match result.source_documents.first() {
+                Some(doc) => println!("\nRetrieved Context: {}", doc.page_content.trim()),
+                None => println!("\nRetrieved Context: (no documents found)"),
+            }
             println!("\nAnswer:");
-            println!("{}", response);
+            println!("{}", result.answer);
+            println!("\nSources: {}", result.sources.join(", "));
         }
         Err(e) => eprintln!("Error: {}", e),
     }
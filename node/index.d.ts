@@ -6,18 +6,53 @@ export declare class AgentExecutor {
 }
 
 export declare class AnthropicLLM {
-  constructor(apiKey: string, model: string, systemPrompt?: string | undefined | null, maxTokens?: number | undefined | null)
+  constructor(apiKey: string | undefined | null, model: string, systemPrompt?: string | undefined | null, maxTokens?: number | undefined | null)
 }
 
 export declare class Chain {
-  constructor(prompt: PromptTemplate, llmInput: SambaNovaLLM | OpenAILLM | AnthropicLLM | GoogleGenAILLM | OllamaLLM, memory?: ConversationBufferMemory | undefined | null)
+  constructor(prompt: PromptTemplate | ChatPromptTemplate, llmInput: SambaNovaLLM | OpenAILLM | AnthropicLLM | GoogleGenAILLM | OllamaLLM, memory?: ConversationBufferMemory | undefined | null)
+  /** Names the chain in cost reports. Defaults to "LLMChain". */
+  setName(name: string): void
+  /** Records the usage of every later call in `ledger`, labelled with `session` and `tags`. */
+  setCostLedger(ledger: CostLedger, session?: string | undefined | null, tags?: Array<string> | undefined | null): void
+  setMinify(policy: string): void
+  tokensSaved(): number
   invoke(inputs: Record<string, string>): Promise<string>
+  /**
+   * Runs the chain over many inputs. Results are in input order, with each item's error
+   * captured rather than rejecting the whole batch.
+   */
+  batch(inputs: Array<Record<string, string>>, options?: BatchOptions | undefined | null, onProgress?: ((arg: BatchProgress) => unknown) | undefined | null): Promise<Array<BatchItem>>
+}
+
+export declare class ChatPromptTemplate {
+  constructor(messages: Array<Array<string>>)
+  get inputVariables(): Array<string>
 }
 
 export declare class ConversationBufferMemory {
   constructor()
 }
 
+/**
+ * Accumulates the actual token usage and cost of chain calls. Attach it with
+ * `Chain.setCostLedger`.
+ */
+export declare class CostLedger {
+  /** Starts with built-in prices for common OpenAI, Anthropic and Google models. */
+  constructor()
+  /** Sets a model's USD price per million tokens. */
+  setPrice(model: string, inputPerMillion: number, outputPerMillion: number, cachedInputPerMillion?: number | undefined | null): void
+  /** USD cost of the given usage on `model`, or null when the model has no price. */
+  cost(model: string, promptTokens: number, completionTokens: number, cachedTokens?: number | undefined | null): number | null
+  total(): CostSummary
+  byModel(): Record<string, CostSummary>
+  byChain(): Record<string, CostSummary>
+  bySession(): Record<string, CostSummary>
+  byTag(): Record<string, CostSummary>
+  reset(): void
+}
+
 export declare class Document {
   constructor(pageContent: string, metadata?: Record<string, string> | undefined | null)
   get pageContent(): string
@@ -25,11 +60,11 @@ export declare class Document {
 }
 
 export declare class GoogleGenAILLM {
-  constructor(apiKey: string, model: string, temperature?: number | undefined | null, maxTokens?: number | undefined | null)
+  constructor(apiKey: string | undefined | null, model: string, temperature?: number | undefined | null, maxTokens?: number | undefined | null, systemInstruction?: string | undefined | null, topP?: number | undefined | null, topK?: number | undefined | null, stopSequences?: Array<string> | undefined | null, safetySettings?: Record<string, string> | undefined | null)
 }
 
 export declare class InMemoryVectorStore {
-  constructor(embeddings: MockEmbeddings)
+  constructor(embeddings: MockEmbeddings | OllamaEmbeddings)
   addDocuments(docs: Array<Document>): Promise<Array<string>>
   similaritySearch(query: string, k: number): Promise<Array<Document>>
 }
@@ -39,17 +74,28 @@ export declare class MockEmbeddings {
   embedQuery(text: string): Promise<Array<number>>
 }
 
+export declare class OllamaEmbeddings {
+  constructor(model: string, baseUrl?: string | undefined | null, keepAlive?: string | undefined | null)
+  embedQuery(text: string): Promise<Array<number>>
+}
+
 export declare class OllamaLLM {
-  constructor(model: string, baseUrl?: string | undefined | null, temperature?: number | undefined | null)
+  constructor(model: string, baseUrl?: string | undefined | null, temperature?: number | undefined | null, numCtx?: number | undefined | null, keepAlive?: string | undefined | null)
+  listModels(): Promise<Array<string>>
+  pullModel(name: string): Promise<void>
 }
 
 export declare class OpenAILLM {
-  constructor(apiKey: string, model: string, baseUrl?: string | undefined | null, systemPrompt?: string | undefined | null, temperature?: number | undefined | null, maxTokens?: number | undefined | null)
+  constructor(apiKey: string | undefined | null, model: string, baseUrl?: string | undefined | null, systemPrompt?: string | undefined | null, temperature?: number | undefined | null, maxTokens?: number | undefined | null, maxCompletionTokens?: number | undefined | null, reasoningEffort?: string | undefined | null, useResponsesApi?: boolean | undefined | null)
 }
 
 export declare class PromptTemplate {
-  constructor(template: string, variables: Array<string>)
-  format(values: Record<string, string>): string
+  constructor(template: string, variables?: Array<string> | undefined | null, templateFormat?: string | undefined | null)
+  static load(path: string): PromptTemplate
+  save(path: string): void
+  get inputVariables(): Array<string>
+  format(values: Record<string, any>): string
+  partial(values: Record<string, string>): PromptTemplate
 }
 
 export declare class SambaNovaLLM {
@@ -60,3 +106,33 @@ export declare class TextLoader {
   constructor(filePath: string)
   load(): Array<Document>
 }
+
+/** Outcome of one batch input: either `output` or `error` is set. */
+export interface BatchItem {
+  output?: string
+  error?: string
+}
+
+export interface BatchOptions {
+  /** Maximum items in flight at once. Defaults to 4. */
+  concurrency?: number
+  /** JSONL file recording finished items; rerunning the batch skips them. */
+  checkpoint?: string
+}
+
+export interface BatchProgress {
+  completed: number
+  failed: number
+  total: number
+}
+
+export interface CostSummary {
+  calls: number
+  promptTokens: number
+  completionTokens: number
+  cachedTokens: number
+  /** USD across the priced calls. */
+  cost: number
+  /** Calls whose model had no price; their tokens are counted but not their cost. */
+  unpricedCalls: number
+}
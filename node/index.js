@@ -579,11 +579,14 @@ module.exports = nativeBinding
 module.exports.AgentExecutor = nativeBinding.AgentExecutor
 module.exports.AnthropicLLM = nativeBinding.AnthropicLLM
 module.exports.Chain = nativeBinding.Chain
+module.exports.ChatPromptTemplate = nativeBinding.ChatPromptTemplate
 module.exports.ConversationBufferMemory = nativeBinding.ConversationBufferMemory
+module.exports.CostLedger = nativeBinding.CostLedger
 module.exports.Document = nativeBinding.Document
 module.exports.GoogleGenAILLM = nativeBinding.GoogleGenAILLM
 module.exports.InMemoryVectorStore = nativeBinding.InMemoryVectorStore
 module.exports.MockEmbeddings = nativeBinding.MockEmbeddings
+module.exports.OllamaEmbeddings = nativeBinding.OllamaEmbeddings
 module.exports.OllamaLLM = nativeBinding.OllamaLLM
 module.exports.OpenAILLM = nativeBinding.OpenAILLM
 module.exports.PromptTemplate = nativeBinding.PromptTemplate
@@ -1,10 +1,16 @@
 use napi_derive::napi;
 use std::sync::{Arc, Mutex};
-use mini_langchain_core::prompt::PromptTemplate as CorePromptTemplate;
+use mini_langchain_core::prompt::{PromptTemplate as CorePromptTemplate, TemplateFormat};
+use mini_langchain_core::chat_prompt::ChatPromptTemplate as CoreChatPromptTemplate;
 use mini_langchain_core::chain::LLMChain as CoreLLMChain;
+use mini_langchain_core::batch::BatchOptions as CoreBatchOptions;
+use mini_langchain_core::callbacks::Callbacks;
 use mini_langchain_core::llm::LLM;
+use mini_langchain_core::minify::MinifyPolicy;
 use std::collections::HashMap;
-use napi::{Result, Error};
+use napi::{Result, Error, Status};
+use napi::bindgen_prelude::Unknown;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
 
 use crate::providers::SambaNovaLLM;
 use crate::providers::OpenAILLM;
@@ -12,6 +18,7 @@ use crate::providers::AnthropicLLM;
 use crate::providers::GoogleGenAILLM;
 use crate::providers::OllamaLLM;
 use crate::memory::ConversationBufferMemory;
+use crate::cost::CostLedger;
 
 #[napi]
 pub struct PromptTemplate {
@@ -20,22 +27,109 @@ pub struct PromptTemplate {
 
 #[napi]
 impl PromptTemplate {
+    /// `templateFormat: "jinja2"` enables `{% if %}`/`{% for %}` blocks and filters.
     #[napi(constructor)]
-    pub fn new(template: String, variables: Vec<String>) -> Self {
-        Self {
-            inner: CorePromptTemplate::new(&template, variables),
-        }
+    pub fn new(template: String, variables: Option<Vec<String>>, template_format: Option<String>) -> Result<Self> {
+        let format: TemplateFormat = template_format.as_deref().unwrap_or("f-string").parse()
+            .map_err(|e: anyhow::Error| Error::from_reason(e.to_string()))?;
+        let inner = match (variables, format) {
+            (Some(vars), TemplateFormat::FString) => CorePromptTemplate::new(&template, vars),
+            (_, format) => CorePromptTemplate::from_template_with_format(&template, format),
+        };
+        Ok(Self {
+            inner: inner.map_err(|e| Error::from_reason(e.to_string()))?,
+        })
+    }
+
+    /// Loads a `.yaml`, `.json` or `.txt` prompt file.
+    #[napi(factory)]
+    pub fn load(path: String) -> Result<Self> {
+        let inner = CorePromptTemplate::load(&path).map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    #[napi]
+    pub fn save(&self, path: String) -> Result<()> {
+        self.inner.save(&path).map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi(getter)]
+    pub fn input_variables(&self) -> Vec<String> {
+        self.inner.input_variables().to_vec()
+    }
+
+    /// Values may be any JSON-compatible objects, e.g. an array of documents for a Jinja loop.
+    #[napi]
+    pub fn format(&self, values: HashMap<String, serde_json::Value>) -> Result<String> {
+        self.inner.format_values(&values).map_err(|e| Error::from_reason(e.to_string()))
     }
 
+    /// Returns a copy with the given variables bound to fixed values.
     #[napi]
-    pub fn format(&self, values: HashMap<String, String>) -> Result<String> {
-        self.inner.format(&values).map_err(|e| Error::from_reason(e.to_string()))
+    pub fn partial(&self, values: HashMap<String, String>) -> Result<PromptTemplate> {
+        let mut inner = self.inner.clone();
+        for (name, value) in values {
+            inner = inner.with_partial(&name, &value).map_err(|e| Error::from_reason(e.to_string()))?;
+        }
+        Ok(PromptTemplate { inner })
     }
 }
 
+/// Messages are `[role, template]` pairs with role `system`, `human`, `ai` or
+/// `placeholder` (whose second item names the history variable, e.g. `"history"`).
+#[napi]
+pub struct ChatPromptTemplate {
+    pub(crate) inner: CoreChatPromptTemplate,
+}
+
+#[napi]
+impl ChatPromptTemplate {
+    #[napi(constructor)]
+    pub fn new(messages: Vec<Vec<String>>) -> Result<Self> {
+        let pairs = messages
+            .into_iter()
+            .map(|pair| match <[String; 2]>::try_from(pair) {
+                Ok([role, template]) => Ok((role, template)),
+                Err(_) => Err(Error::from_reason("Each message must be a [role, template] pair".to_string())),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let inner = CoreChatPromptTemplate::from_tuples(&pairs)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    #[napi(getter)]
+    pub fn input_variables(&self) -> Vec<String> {
+        self.inner.input_variables()
+    }
+}
+
+#[napi(object)]
+pub struct BatchOptions {
+    /// Maximum items in flight at once. Defaults to 4.
+    pub concurrency: Option<u32>,
+    /// JSONL file recording finished items; rerunning the batch skips them.
+    pub checkpoint: Option<String>,
+}
+
+#[napi(object)]
+pub struct BatchProgress {
+    pub completed: u32,
+    pub failed: u32,
+    pub total: u32,
+}
+
+/// Outcome of one batch input: either `output` or `error` is set.
+#[napi(object)]
+pub struct BatchItem {
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
 #[napi]
 pub struct Chain {
     inner: Arc<Mutex<Option<CoreLLMChain>>>,
+    callbacks: Arc<Mutex<Callbacks>>,
 }
 
 use napi::bindgen_prelude::Either;
@@ -44,7 +138,7 @@ use napi::bindgen_prelude::Either;
 impl Chain {
     #[napi(constructor)]
     pub fn new(
-        prompt: &PromptTemplate, 
+        prompt: Either<&PromptTemplate, &ChatPromptTemplate>, 
         llm_input: Either<&SambaNovaLLM, Either<&OpenAILLM, Either<&AnthropicLLM, Either<&GoogleGenAILLM, &OllamaLLM>>>>, 
         memory: Option<&ConversationBufferMemory>
     ) -> Result<Self> {
@@ -62,7 +156,10 @@ impl Chain {
             },
         };
 
-        let mut chain = CoreLLMChain::new(prompt.inner.clone(), llm);
+        let mut chain = match prompt {
+            Either::A(text) => CoreLLMChain::new(text.inner.clone(), llm),
+            Either::B(chat) => CoreLLMChain::from_chat_prompt(chat.inner.clone(), llm),
+        };
         
         if let Some(mem) = memory {
             let core_mem = mem.inner.lock().unwrap().clone();
@@ -71,9 +168,59 @@ impl Chain {
 
         Ok(Self {
             inner: Arc::new(Mutex::new(Some(chain))),
+            callbacks: Arc::new(Mutex::new(Callbacks::new())),
         })
     }
 
+    /// Names the chain in cost reports. Defaults to "LLMChain".
+    #[napi]
+    pub fn set_name(&self, name: String) -> Result<()> {
+        let mut guard = self.inner.lock().unwrap();
+        match guard.take() {
+            Some(chain) => {
+                *guard = Some(chain.with_name(&name));
+                Ok(())
+            }
+            None => Err(Error::from_reason("Chain not initialized".to_string())),
+        }
+    }
+
+    /// Records the usage of every later call in `ledger`, labelled with `session` and `tags`.
+    #[napi]
+    pub fn set_cost_ledger(&self, ledger: &CostLedger, session: Option<String>, tags: Option<Vec<String>>) {
+        let mut handler = ledger.inner.handler();
+        if let Some(session) = session {
+            handler = handler.with_session(&session);
+        }
+        for tag in tags.unwrap_or_default() {
+            handler = handler.with_tag(&tag);
+        }
+        *self.callbacks.lock().unwrap() = Callbacks::new().with_handler(Arc::new(handler));
+    }
+
+    /// Selects the minification policy: "off", "whitespace", "structure_aware" (default) or "aggressive".
+    #[napi]
+    pub fn set_minify(&self, policy: String) -> Result<()> {
+        let policy: MinifyPolicy = policy.parse().map_err(|e: anyhow::Error| Error::from_reason(e.to_string()))?;
+        let mut guard = self.inner.lock().unwrap();
+        match guard.take() {
+            Some(chain) => {
+                *guard = Some(chain.with_minify(policy));
+                Ok(())
+            }
+            None => Err(Error::from_reason("Chain not initialized".to_string())),
+        }
+    }
+
+    /// Prompt tokens removed by minification across all calls.
+    #[napi]
+    pub fn tokens_saved(&self) -> Result<i64> {
+        let guard = self.inner.lock().unwrap();
+        guard.as_ref()
+            .map(|chain| chain.minify_stats().tokens_saved() as i64)
+            .ok_or_else(|| Error::from_reason("Chain not initialized".to_string()))
+    }
+
     #[napi]
     pub async fn invoke(&self, inputs: HashMap<String, String>) -> Result<String> {
         let inner_clone = self.inner.clone();
@@ -87,6 +234,51 @@ impl Chain {
             }
         };
 
-        chain.call(inputs).await.map_err(|e| Error::from_reason(e.to_string()))
+        let callbacks = self.callbacks.lock().unwrap().clone();
+        callbacks.scope(chain.call(inputs)).await.map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Runs the chain over many inputs. Results are in input order, with each item's error
+    /// captured rather than rejecting the whole batch.
+    #[napi]
+    pub async fn batch(
+        &self,
+        inputs: Vec<HashMap<String, String>>,
+        options: Option<BatchOptions>,
+        on_progress: Option<ThreadsafeFunction<BatchProgress, Unknown<'static>, BatchProgress, Status, false>>,
+    ) -> Result<Vec<BatchItem>> {
+        let chain = {
+            let guard = self.inner.lock().unwrap();
+            guard.as_ref()
+                .cloned()
+                .ok_or_else(|| Error::from_reason("Chain not initialized".to_string()))?
+        };
+
+        let mut batch_options = CoreBatchOptions::new();
+        if let Some(options) = options {
+            if let Some(concurrency) = options.concurrency {
+                batch_options = batch_options.with_concurrency(concurrency as usize);
+            }
+            if let Some(checkpoint) = options.checkpoint {
+                batch_options = batch_options.with_checkpoint(checkpoint);
+            }
+        }
+        if let Some(callback) = on_progress {
+            batch_options = batch_options.with_progress(move |p| {
+                let progress = BatchProgress { completed: p.completed as u32, failed: p.failed as u32, total: p.total as u32 };
+                callback.call(progress, ThreadsafeFunctionCallMode::NonBlocking);
+            });
+        }
+
+        let callbacks = self.callbacks.lock().unwrap().clone();
+        let results = callbacks.scope(chain.batch(inputs, &batch_options)).await
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                Ok(output) => BatchItem { output: Some(output), error: None },
+                Err(e) => BatchItem { output: None, error: Some(e.to_string()) },
+            })
+            .collect())
     }
 }
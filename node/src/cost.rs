@@ -0,0 +1,109 @@
+use napi_derive::napi;
+use std::collections::HashMap;
+use std::sync::Arc;
+use mini_langchain_core::cost::{CostLedger as CoreCostLedger, CostSummary as CoreCostSummary, ModelPricing};
+use mini_langchain_core::llm::TokenUsage;
+
+#[napi(object)]
+pub struct CostSummary {
+    pub calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cached_tokens: i64,
+    /// USD across the priced calls.
+    pub cost: f64,
+    /// Calls whose model had no price; their tokens are counted but not their cost.
+    pub unpriced_calls: i64,
+}
+
+impl From<&CoreCostSummary> for CostSummary {
+    fn from(summary: &CoreCostSummary) -> Self {
+        Self {
+            calls: summary.calls as i64,
+            prompt_tokens: summary.prompt_tokens as i64,
+            completion_tokens: summary.completion_tokens as i64,
+            cached_tokens: summary.cached_tokens as i64,
+            cost: summary.cost,
+            unpriced_calls: summary.unpriced_calls as i64,
+        }
+    }
+}
+
+fn groups<'a>(groups: impl IntoIterator<Item = (&'a String, &'a CoreCostSummary)>) -> HashMap<String, CostSummary> {
+    groups.into_iter().map(|(key, summary)| (key.clone(), summary.into())).collect()
+}
+
+/// Accumulates the actual token usage and cost of chain calls. Attach it with
+/// `Chain.setCostLedger`.
+#[napi]
+pub struct CostLedger {
+    pub(crate) inner: Arc<CoreCostLedger>,
+}
+
+#[napi]
+impl CostLedger {
+    /// Starts with built-in prices for common OpenAI, Anthropic and Google models.
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self { inner: Arc::new(CoreCostLedger::new()) }
+    }
+
+    /// Sets a model's USD price per million tokens.
+    #[napi]
+    pub fn set_price(&self, model: String, input_per_million: f64, output_per_million: f64, cached_input_per_million: Option<f64>) {
+        let mut pricing = ModelPricing::new(input_per_million, output_per_million);
+        if let Some(cached) = cached_input_per_million {
+            pricing = pricing.with_cached_input(cached);
+        }
+        self.inner.set_price(&model, pricing);
+    }
+
+    /// USD cost of the given usage on `model`, or null when the model has no price.
+    #[napi]
+    pub fn cost(&self, model: String, prompt_tokens: u32, completion_tokens: u32, cached_tokens: Option<u32>) -> Option<f64> {
+        let usage = TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            cached_tokens,
+            ..Default::default()
+        };
+        self.inner.pricing().cost(&model, &usage)
+    }
+
+    #[napi]
+    pub fn total(&self) -> CostSummary {
+        (&self.inner.total()).into()
+    }
+
+    #[napi]
+    pub fn by_model(&self) -> HashMap<String, CostSummary> {
+        groups(&self.inner.by_model())
+    }
+
+    #[napi]
+    pub fn by_chain(&self) -> HashMap<String, CostSummary> {
+        groups(&self.inner.by_chain())
+    }
+
+    #[napi]
+    pub fn by_session(&self) -> HashMap<String, CostSummary> {
+        groups(&self.inner.by_session())
+    }
+
+    #[napi]
+    pub fn by_tag(&self) -> HashMap<String, CostSummary> {
+        groups(&self.inner.by_tag())
+    }
+
+    #[napi]
+    pub fn reset(&self) {
+        self.inner.reset();
+    }
+}
+
+impl Default for CostLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
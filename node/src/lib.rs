@@ -4,6 +4,7 @@ pub mod chain;
 pub mod memory;
 pub mod rag;
 pub mod agent;
+pub mod cost;
 
 // Re-export common structs if needed, or let Napi verify logic handle it.
 // Actually, Napi automatic discovery might need these pubs to be 'use'd or declared inside modules.
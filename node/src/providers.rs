@@ -1,9 +1,10 @@
 use napi_derive::napi;
 use std::sync::Arc;
+use std::collections::HashMap;
 use mini_langchain_core::providers::sambanova::SambaNovaProvider;
-use mini_langchain_core::providers::openai::OpenAIProvider;
+use mini_langchain_core::providers::openai::{OpenAIProvider, OpenAIApi, ReasoningEffort};
 use mini_langchain_core::providers::anthropic::AnthropicProvider;
-use mini_langchain_core::providers::google::GoogleGenAIProvider;
+use mini_langchain_core::providers::google::{GoogleGenAIProvider, HarmCategory, HarmBlockThreshold};
 use mini_langchain_core::providers::ollama::OllamaProvider;
 
 // --- SambaNova ---
@@ -42,16 +43,31 @@ pub struct OpenAILLM {
 #[napi]
 impl OpenAILLM {
     #[napi(constructor)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         base_url: Option<String>,
         system_prompt: Option<String>,
         temperature: Option<f64>,
         max_tokens: Option<u32>,
-    ) -> Self {
-        let provider = OpenAIProvider::new(api_key, model, base_url, system_prompt, temperature, max_tokens);
-        Self { inner: Arc::new(provider) }
+        max_completion_tokens: Option<u32>,
+        reasoning_effort: Option<String>,
+        use_responses_api: Option<bool>,
+    ) -> napi::Result<Self> {
+        let mut provider = OpenAIProvider::new(api_key, model, base_url, system_prompt, temperature, max_tokens);
+        if let Some(limit) = max_completion_tokens {
+            provider = provider.with_max_completion_tokens(limit);
+        }
+        if let Some(effort) = reasoning_effort {
+            let effort = effort.parse::<ReasoningEffort>()
+                .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+            provider = provider.with_reasoning_effort(effort);
+        }
+        if use_responses_api.unwrap_or(false) {
+            provider = provider.with_api(OpenAIApi::Responses);
+        }
+        Ok(Self { inner: Arc::new(provider) })
     }
 }
 
@@ -66,7 +82,7 @@ pub struct AnthropicLLM {
 impl AnthropicLLM {
     #[napi(constructor)]
     pub fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         system_prompt: Option<String>,
         max_tokens: Option<u32>,
@@ -86,14 +102,39 @@ pub struct GoogleGenAILLM {
 #[napi]
 impl GoogleGenAILLM {
     #[napi(constructor)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         temperature: Option<f64>,
         max_tokens: Option<u32>,
-    ) -> Self {
-        let provider = GoogleGenAIProvider::new(api_key, model, temperature, max_tokens);
-        Self { inner: Arc::new(provider) }
+        system_instruction: Option<String>,
+        top_p: Option<f64>,
+        top_k: Option<u32>,
+        stop_sequences: Option<Vec<String>>,
+        safety_settings: Option<HashMap<String, String>>,
+    ) -> napi::Result<Self> {
+        let mut provider = GoogleGenAIProvider::new(api_key, model, temperature, max_tokens);
+        if let Some(instruction) = system_instruction {
+            provider = provider.with_system_instruction(instruction);
+        }
+        if let Some(p) = top_p {
+            provider = provider.with_top_p(p);
+        }
+        if let Some(k) = top_k {
+            provider = provider.with_top_k(k);
+        }
+        if let Some(stops) = stop_sequences {
+            provider = provider.with_stop_sequences(stops);
+        }
+        for (category, threshold) in safety_settings.unwrap_or_default() {
+            let category = category.parse::<HarmCategory>()
+                .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+            let threshold = threshold.parse::<HarmBlockThreshold>()
+                .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+            provider = provider.with_safety_setting(category, threshold);
+        }
+        Ok(Self { inner: Arc::new(provider) })
     }
 }
 
@@ -111,8 +152,29 @@ impl OllamaLLM {
         model: String,
         base_url: Option<String>,
         temperature: Option<f64>,
+        num_ctx: Option<u32>,
+        keep_alive: Option<String>,
     ) -> Self {
-        let provider = OllamaProvider::new(model, base_url, temperature);
+        let mut provider = OllamaProvider::new(model, base_url, temperature);
+        if let Some(n) = num_ctx {
+            provider = provider.with_num_ctx(n);
+        }
+        if let Some(k) = keep_alive {
+            provider = provider.with_keep_alive(k);
+        }
         Self { inner: Arc::new(provider) }
     }
+
+    #[napi]
+    pub async fn list_models(&self) -> napi::Result<Vec<String>> {
+        let models = self.inner.list_models().await
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+        Ok(models.into_iter().map(|m| m.name).collect())
+    }
+
+    #[napi]
+    pub async fn pull_model(&self, name: String) -> napi::Result<()> {
+        self.inner.pull_model(&name, |_| {}).await
+            .map_err(|e| napi::Error::from_reason(e.to_string()))
+    }
 }
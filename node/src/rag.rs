@@ -7,6 +7,8 @@ use mini_langchain_core::loader::{Loader, TextLoader as CoreTextLoader};
 use mini_langchain_core::schema::Document as CoreDocument;
 use mini_langchain_core::embedding::{Embeddings, MockEmbeddings as CoreMockEmbeddings};
 use mini_langchain_core::vectorstore::{VectorStore, InMemoryVectorStore as CoreInMemoryVectorStore};
+use mini_langchain_core::providers::ollama::OllamaEmbeddings as CoreOllamaEmbeddings;
+use napi::bindgen_prelude::Either;
 
 #[napi]
 pub struct Document {
@@ -81,6 +83,29 @@ impl MockEmbeddings {
     }
 }
 
+#[napi]
+pub struct OllamaEmbeddings {
+    pub(crate) inner: Arc<CoreOllamaEmbeddings>,
+}
+
+#[napi]
+impl OllamaEmbeddings {
+    #[napi(constructor)]
+    pub fn new(model: String, base_url: Option<String>, keep_alive: Option<String>) -> Self {
+        let mut embeddings = CoreOllamaEmbeddings::new(model, base_url);
+        if let Some(k) = keep_alive {
+            embeddings = embeddings.with_keep_alive(k);
+        }
+        Self { inner: Arc::new(embeddings) }
+    }
+
+    #[napi]
+    pub async fn embed_query(&self, text: String) -> Result<Vec<f64>> {
+        let res = self.inner.embed_query(&text).await.map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(res.into_iter().map(|v| v as f64).collect())
+    }
+}
+
 #[napi]
 pub struct InMemoryVectorStore {
     inner: Arc<CoreInMemoryVectorStore>,
@@ -89,9 +114,13 @@ pub struct InMemoryVectorStore {
 #[napi]
 impl InMemoryVectorStore {
     #[napi(constructor)]
-    pub fn new(embeddings: &MockEmbeddings) -> Self {
+    pub fn new(embeddings: Either<&MockEmbeddings, &OllamaEmbeddings>) -> Self {
+        let embeddings: Arc<dyn Embeddings> = match embeddings {
+            Either::A(mock) => mock.inner.clone(),
+            Either::B(ollama) => ollama.inner.clone(),
+        };
         Self {
-            inner: Arc::new(CoreInMemoryVectorStore::new(embeddings.inner.clone())),
+            inner: Arc::new(CoreInMemoryVectorStore::new(embeddings)),
         }
     }
 
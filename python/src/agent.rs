@@ -41,6 +41,6 @@ impl AgentExecutor {
                .map_err(|e| e.to_string())
         });
         
-        result.map_err(|e| pyo3::exceptions::PyValueError::new_err(e))
+        result.map_err(pyo3::exceptions::PyValueError::new_err)
     }
 }
@@ -1,12 +1,19 @@
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use mini_langchain_core::prompt::PromptTemplate as CorePromptTemplate;
+use mini_langchain_core::prompt::{PartialValue, PromptTemplate as CorePromptTemplate, TemplateFormat};
+use mini_langchain_core::chat_prompt::ChatPromptTemplate as CoreChatPromptTemplate;
 use mini_langchain_core::chain::LLMChain as CoreLLMChain;
+use mini_langchain_core::batch::BatchOptions;
+use mini_langchain_core::callbacks::Callbacks;
 use mini_langchain_core::llm::LLM;
+use mini_langchain_core::minify::MinifyPolicy;
+use mini_langchain_core::schema::ChatMessage;
 
 use crate::llm::{SambaNovaLLM, OpenAILLM, AnthropicLLM, GoogleGenAILLM, OllamaLLM, PyLLMBridge};
 use crate::memory::{ConversationBufferMemory, InMemoryCache};
+use crate::utils::CostLedger;
 
 #[pyclass]
 pub struct PromptTemplate {
@@ -15,28 +22,131 @@ pub struct PromptTemplate {
 
 #[pymethods]
 impl PromptTemplate {
+    /// Variables are inferred from the `{placeholders}` when `variables` is omitted.
+    /// `template_format="jinja2"` enables `{% if %}`/`{% for %}` blocks and filters.
     #[new]
-    fn new(template: String, variables: Vec<String>) -> Self {
-        Self {
-            inner: CorePromptTemplate::new(&template, variables),
+    #[pyo3(signature = (template, variables=None, template_format=None))]
+    fn new(template: String, variables: Option<Vec<String>>, template_format: Option<String>) -> PyResult<Self> {
+        let format: TemplateFormat = template_format.as_deref().unwrap_or("f-string").parse()
+            .map_err(|e: anyhow::Error| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let inner = match (variables, format) {
+            (Some(vars), TemplateFormat::FString) => CorePromptTemplate::new(&template, vars),
+            (_, format) => CorePromptTemplate::from_template_with_format(&template, format),
+        };
+        Ok(Self {
+            inner: inner.map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?,
+        })
+    }
+
+    /// Loads a `.yaml`, `.json` or `.txt` prompt file.
+    #[staticmethod]
+    fn load(path: String) -> PyResult<Self> {
+        let inner = CorePromptTemplate::load(&path)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    fn save(&self, path: String) -> PyResult<()> {
+        self.inner.save(&path).map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn input_variables(&self) -> Vec<String> {
+        self.inner.input_variables().to_vec()
+    }
+
+    /// Values may be any JSON-compatible objects, e.g. a list of dicts for a Jinja loop.
+    fn format(&self, py: Python<'_>, values: &Bound<'_, PyDict>) -> PyResult<String> {
+        let json: String = py.import("json")?.call_method1("dumps", (values,))?.extract()?;
+        let values: HashMap<String, serde_json::Value> = serde_json::from_str(&json)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        self.inner.format_values(&values).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+
+    /// Returns a copy with some variables bound. Values are strings, or zero-argument
+    /// callables (e.g. `lambda: date.today().isoformat()`) evaluated on every format.
+    #[pyo3(signature = (**kwargs))]
+    fn partial(&self, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut inner = self.inner.clone();
+        for (key, value) in kwargs.into_iter().flatten() {
+            let name: String = key.extract()?;
+            let partial = if value.is_callable() {
+                let callback = value.unbind();
+                let label = name.clone();
+                PartialValue::Dynamic(Arc::new(move || {
+                    Python::attach(|py| callback.call0(py)?.extract::<String>(py))
+                        .map_err(|e| anyhow::anyhow!("Partial '{}' failed: {}", label, e))
+                }))
+            } else {
+                PartialValue::Static(value.extract()?)
+            };
+            inner = inner.with_partial_value(&name, partial)
+                .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
         }
+        Ok(Self { inner })
     }
+}
+
+/// Messages are `(role, template)` tuples with role `system`, `human`, `ai` or
+/// `placeholder` (whose second item names the history variable, e.g. `"history"`).
+#[pyclass]
+pub struct ChatPromptTemplate {
+    pub(crate) inner: CoreChatPromptTemplate,
+}
 
-    fn format(&self, values: HashMap<String, String>) -> PyResult<String> {
-        self.inner.format(&values).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+#[pymethods]
+impl ChatPromptTemplate {
+    #[new]
+    fn new(messages: Vec<(String, String)>) -> PyResult<Self> {
+        let inner = CoreChatPromptTemplate::from_tuples(&messages)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    #[getter]
+    fn input_variables(&self) -> Vec<String> {
+        self.inner.input_variables()
+    }
+
+    /// Returns the formatted conversation as `(role, content)` tuples.
+    #[pyo3(signature = (values, histories=None))]
+    fn format_messages(
+        &self,
+        values: HashMap<String, String>,
+        histories: Option<HashMap<String, Vec<(String, String)>>>,
+    ) -> PyResult<Vec<(String, String)>> {
+        let histories = histories
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, messages)| {
+                let messages = messages
+                    .into_iter()
+                    .map(|(role, content)| Ok(ChatMessage::new(role.parse()?, content)))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Ok((key, messages))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>();
+        let messages = histories
+            .and_then(|histories| self.inner.format_messages(&values, &histories))
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        Ok(messages
+            .into_iter()
+            .map(|m| (m.role.label().to_lowercase(), m.content))
+            .collect())
     }
 }
 
 #[pyclass]
 pub struct Chain {
     inner: Arc<Mutex<Option<CoreLLMChain>>>,
+    callbacks: Arc<Mutex<Callbacks>>,
 }
 
 #[pymethods]
 impl Chain {
     #[new]
     #[pyo3(signature = (prompt, llm_model, memory=None))]
-    fn new(py: Python<'_>, prompt: &PromptTemplate, llm_model: Py<PyAny>, memory: Option<&ConversationBufferMemory>) -> PyResult<Self> {
+    fn new(py: Python<'_>, prompt: &Bound<'_, PyAny>, llm_model: Py<PyAny>, memory: Option<&ConversationBufferMemory>) -> PyResult<Self> {
         // Try to extract known Rust LLMs
         let llm: Arc<dyn LLM> = if let Ok(samba) = llm_model.extract::<SambaNovaLLM>(py) {
              samba.inner.clone()
@@ -53,7 +163,13 @@ impl Chain {
              Arc::new(PyLLMBridge { py_obj: llm_model })
         };
         
-        let mut chain = CoreLLMChain::new(prompt.inner.clone(), llm);
+        let mut chain = if let Ok(chat) = prompt.extract::<PyRef<ChatPromptTemplate>>() {
+            CoreLLMChain::from_chat_prompt(chat.inner.clone(), llm)
+        } else if let Ok(text) = prompt.extract::<PyRef<PromptTemplate>>() {
+            CoreLLMChain::new(text.inner.clone(), llm)
+        } else {
+            return Err(pyo3::exceptions::PyTypeError::new_err("prompt must be a PromptTemplate or ChatPromptTemplate"));
+        };
         
         if let Some(mem) = memory {
              let core_mem = mem.inner.lock().unwrap().clone();
@@ -62,9 +178,34 @@ impl Chain {
 
         Ok(Self {
             inner: Arc::new(Mutex::new(Some(chain))),
+            callbacks: Arc::new(Mutex::new(Callbacks::new())),
         })
     }
 
+    /// Names the chain in cost reports. Defaults to "LLMChain".
+    fn set_name(&self, name: String) -> PyResult<()> {
+        let mut guard = self.inner.lock().unwrap();
+        if let Some(chain) = guard.take() {
+            *guard = Some(chain.with_name(&name));
+            Ok(())
+        } else {
+            Err(pyo3::exceptions::PyRuntimeError::new_err("Chain not initialized"))
+        }
+    }
+
+    /// Records the usage of every later call in `ledger`, labelled with `session` and `tags`.
+    #[pyo3(signature = (ledger, session=None, tags=None))]
+    fn set_cost_ledger(&self, ledger: &CostLedger, session: Option<String>, tags: Option<Vec<String>>) {
+        let mut handler = ledger.inner.handler();
+        if let Some(session) = session {
+            handler = handler.with_session(&session);
+        }
+        for tag in tags.unwrap_or_default() {
+            handler = handler.with_tag(&tag);
+        }
+        *self.callbacks.lock().unwrap() = Callbacks::new().with_handler(Arc::new(handler));
+    }
+
     fn set_cache(&self, cache: &InMemoryCache) -> PyResult<()> {
         let mut guard = self.inner.lock().unwrap();
         if let Some(chain) = guard.take() {
@@ -76,26 +217,94 @@ impl Chain {
         }
     }
 
+    /// Selects the minification policy: "off", "whitespace", "structure_aware" (default) or "aggressive".
+    fn set_minify(&self, policy: String) -> PyResult<()> {
+        let policy: MinifyPolicy = policy.parse()
+            .map_err(|e: anyhow::Error| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let mut guard = self.inner.lock().unwrap();
+        if let Some(chain) = guard.take() {
+            *guard = Some(chain.with_minify(policy));
+            Ok(())
+        } else {
+            Err(pyo3::exceptions::PyRuntimeError::new_err("Chain not initialized"))
+        }
+    }
+
+    /// Prompt tokens removed by minification across all calls.
+    fn tokens_saved(&self) -> PyResult<u64> {
+        let guard = self.inner.lock().unwrap();
+        guard.as_ref()
+            .map(|chain| chain.minify_stats().tokens_saved())
+            .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("Chain not initialized"))
+    }
+
     #[pyo3(signature = (inputs))]
     fn invoke(&self, py: Python<'_>, inputs: HashMap<String, String>) -> PyResult<String> {
         let inner_clone = self.inner.clone();
-        
+        let callbacks = self.callbacks.lock().unwrap().clone();
+
         let result: Result<String, String> = py.detach(move || {
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
                 .build()
                 .unwrap();
 
-            rt.block_on(async {
-                let mut chain_opt = inner_clone.lock().unwrap();
-                if let Some(chain) = chain_opt.as_mut() {
-                    chain.call(inputs).await.map_err(|e| e.to_string())
-                } else {
-                     Err("Chain not initialized".to_string())
-                }
-            })
+            let chain = inner_clone.lock().unwrap().clone()
+                .ok_or_else(|| "Chain not initialized".to_string())?;
+
+            rt.block_on(callbacks.scope(chain.call(inputs))).map_err(|e| e.to_string())
         });
 
-        result.map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e))
+        result.map_err(pyo3::exceptions::PyRuntimeError::new_err)
+    }
+
+    /// Runs the chain over many inputs, `concurrency` at a time. Returns one dict per input, in
+    /// order, with either "output" or "error" set. `on_progress(completed, failed, total)` is
+    /// called after each item; with `checkpoint`, finished items are recorded in that JSONL file
+    /// and skipped when the batch is rerun.
+    #[pyo3(signature = (inputs, concurrency=4, checkpoint=None, on_progress=None))]
+    fn batch(
+        &self,
+        py: Python<'_>,
+        inputs: Vec<HashMap<String, String>>,
+        concurrency: usize,
+        checkpoint: Option<String>,
+        on_progress: Option<Py<PyAny>>,
+    ) -> PyResult<Vec<HashMap<String, Option<String>>>> {
+        let mut options = BatchOptions::new().with_concurrency(concurrency);
+        if let Some(path) = checkpoint {
+            options = options.with_checkpoint(path);
+        }
+        if let Some(callback) = on_progress {
+            options = options.with_progress(move |p| {
+                Python::attach(|py| {
+                    if let Err(e) = callback.call1(py, (p.completed, p.failed, p.total)) {
+                        e.print(py);
+                    }
+                })
+            });
+        }
+        let inner_clone = self.inner.clone();
+        let callbacks = self.callbacks.lock().unwrap().clone();
+
+        let results = py.detach(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+
+            let chain = inner_clone.lock().unwrap().clone()
+                .ok_or_else(|| "Chain not initialized".to_string())?;
+
+            rt.block_on(callbacks.scope(chain.batch(inputs, &options))).map_err(|e| e.to_string())
+        }).map_err(pyo3::exceptions::PyRuntimeError::new_err)?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                Ok(output) => HashMap::from([("output".to_string(), Some(output)), ("error".to_string(), None)]),
+                Err(e) => HashMap::from([("output".to_string(), None), ("error".to_string(), Some(e.to_string()))]),
+            })
+            .collect())
     }
 }
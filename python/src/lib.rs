@@ -8,15 +8,16 @@ pub mod agent;
 pub mod utils;
 
 use llm::{SambaNovaLLM, OpenAILLM, AnthropicLLM, GoogleGenAILLM, OllamaLLM};
-use chain::{Chain, PromptTemplate};
+use chain::{Chain, ChatPromptTemplate, PromptTemplate};
 use memory::{ConversationBufferMemory, InMemoryCache};
-use rag::{Document, TextLoader, MockEmbeddings, InMemoryVectorStore};
+use rag::{Document, TextLoader, MockEmbeddings, OllamaEmbeddings, InMemoryVectorStore};
 use agent::AgentExecutor;
-use utils::TokenCalculator;
+use utils::{CostLedger, TokenCalculator};
 
 #[pymodule]
 fn mini_langchain(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PromptTemplate>()?;
+    m.add_class::<ChatPromptTemplate>()?;
     m.add_class::<InMemoryCache>()?;
     m.add_class::<Chain>()?;
     m.add_class::<SambaNovaLLM>()?;
@@ -28,8 +29,10 @@ fn mini_langchain(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Document>()?;
     m.add_class::<TextLoader>()?;
     m.add_class::<MockEmbeddings>()?;
+    m.add_class::<OllamaEmbeddings>()?;
     m.add_class::<InMemoryVectorStore>()?;
     m.add_class::<AgentExecutor>()?;
     m.add_class::<TokenCalculator>()?;
+    m.add_class::<CostLedger>()?;
     Ok(())
 }
@@ -1,10 +1,11 @@
 use pyo3::prelude::*;
 use std::sync::Arc;
+use std::collections::HashMap;
 use mini_langchain_core::llm::LLM;
 use mini_langchain_core::providers::sambanova::SambaNovaProvider;
-use mini_langchain_core::providers::openai::OpenAIProvider;
+use mini_langchain_core::providers::openai::{OpenAIProvider, OpenAIApi, ReasoningEffort};
 use mini_langchain_core::providers::anthropic::AnthropicProvider;
-use mini_langchain_core::providers::google::GoogleGenAIProvider;
+use mini_langchain_core::providers::google::{GoogleGenAIProvider, HarmCategory, HarmBlockThreshold};
 use mini_langchain_core::providers::ollama::OllamaProvider;
 use async_trait::async_trait;
 
@@ -69,17 +70,32 @@ pub struct OpenAILLM {
 #[pymethods]
 impl OpenAILLM {
     #[new]
-    #[pyo3(signature = (api_key, model, base_url=None, system_prompt=None, temperature=None, max_tokens=None))]
+    #[pyo3(signature = (api_key, model, base_url=None, system_prompt=None, temperature=None, max_tokens=None, max_completion_tokens=None, reasoning_effort=None, use_responses_api=false))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         base_url: Option<String>,
         system_prompt: Option<String>,
         temperature: Option<f64>,
         max_tokens: Option<u32>,
-    ) -> Self {
-        let provider = OpenAIProvider::new(api_key, model, base_url, system_prompt, temperature, max_tokens);
-        Self { inner: Arc::new(provider) }
+        max_completion_tokens: Option<u32>,
+        reasoning_effort: Option<String>,
+        use_responses_api: bool,
+    ) -> PyResult<Self> {
+        let mut provider = OpenAIProvider::new(api_key, model, base_url, system_prompt, temperature, max_tokens);
+        if let Some(limit) = max_completion_tokens {
+            provider = provider.with_max_completion_tokens(limit);
+        }
+        if let Some(effort) = reasoning_effort {
+            let effort = effort.parse::<ReasoningEffort>()
+                .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+            provider = provider.with_reasoning_effort(effort);
+        }
+        if use_responses_api {
+            provider = provider.with_api(OpenAIApi::Responses);
+        }
+        Ok(Self { inner: Arc::new(provider) })
     }
 }
 
@@ -95,7 +111,7 @@ impl AnthropicLLM {
     #[new]
     #[pyo3(signature = (api_key, model, system_prompt=None, max_tokens=None))]
     fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         system_prompt: Option<String>,
         max_tokens: Option<u32>,
@@ -115,15 +131,40 @@ pub struct GoogleGenAILLM {
 #[pymethods]
 impl GoogleGenAILLM {
     #[new]
-    #[pyo3(signature = (api_key, model, temperature=None, max_tokens=None))]
+    #[pyo3(signature = (api_key, model, temperature=None, max_tokens=None, system_instruction=None, top_p=None, top_k=None, stop_sequences=None, safety_settings=None))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
-        api_key: String,
+        api_key: Option<String>,
         model: String,
         temperature: Option<f64>,
         max_tokens: Option<u32>,
-    ) -> Self {
-        let provider = GoogleGenAIProvider::new(api_key, model, temperature, max_tokens);
-        Self { inner: Arc::new(provider) }
+        system_instruction: Option<String>,
+        top_p: Option<f64>,
+        top_k: Option<u32>,
+        stop_sequences: Option<Vec<String>>,
+        safety_settings: Option<HashMap<String, String>>,
+    ) -> PyResult<Self> {
+        let mut provider = GoogleGenAIProvider::new(api_key, model, temperature, max_tokens);
+        if let Some(instruction) = system_instruction {
+            provider = provider.with_system_instruction(instruction);
+        }
+        if let Some(p) = top_p {
+            provider = provider.with_top_p(p);
+        }
+        if let Some(k) = top_k {
+            provider = provider.with_top_k(k);
+        }
+        if let Some(stops) = stop_sequences {
+            provider = provider.with_stop_sequences(stops);
+        }
+        for (category, threshold) in safety_settings.unwrap_or_default() {
+            let category = category.parse::<HarmCategory>()
+                .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+            let threshold = threshold.parse::<HarmBlockThreshold>()
+                .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+            provider = provider.with_safety_setting(category, threshold);
+        }
+        Ok(Self { inner: Arc::new(provider) })
     }
 }
 
@@ -137,13 +178,52 @@ pub struct OllamaLLM {
 #[pymethods]
 impl OllamaLLM {
     #[new]
-    #[pyo3(signature = (model, base_url=None, temperature=None))]
+    #[pyo3(signature = (model, base_url=None, temperature=None, num_ctx=None, keep_alive=None))]
     fn new(
         model: String,
         base_url: Option<String>,
         temperature: Option<f64>,
+        num_ctx: Option<u32>,
+        keep_alive: Option<String>,
     ) -> Self {
-        let provider = OllamaProvider::new(model, base_url, temperature);
+        let mut provider = OllamaProvider::new(model, base_url, temperature);
+        if let Some(n) = num_ctx {
+            provider = provider.with_num_ctx(n);
+        }
+        if let Some(k) = keep_alive {
+            provider = provider.with_keep_alive(k);
+        }
         Self { inner: Arc::new(provider) }
     }
+
+    /// Names of the models installed on the Ollama server.
+    fn list_models(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        let inner = self.inner.clone();
+        let result = py.detach(move || {
+            let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            rt.block_on(inner.list_models()).map_err(|e| e.to_string())
+        });
+        result
+            .map(|models| models.into_iter().map(|m| m.name).collect())
+            .map_err(pyo3::exceptions::PyRuntimeError::new_err)
+    }
+
+    /// Pulls a model. `on_progress(status, completed, total)` is called for each progress line.
+    #[pyo3(signature = (name, on_progress=None))]
+    fn pull_model(&self, py: Python<'_>, name: String, on_progress: Option<Py<PyAny>>) -> PyResult<()> {
+        let inner = self.inner.clone();
+        let result = py.detach(move || {
+            let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            rt.block_on(inner.pull_model(&name, |p| {
+                if let Some(callback) = &on_progress {
+                    Python::attach(|py| {
+                        if let Err(e) = callback.call1(py, (p.status.clone(), p.completed, p.total)) {
+                            e.print(py);
+                        }
+                    });
+                }
+            })).map_err(|e| e.to_string())
+        });
+        result.map_err(pyo3::exceptions::PyRuntimeError::new_err)
+    }
 }
@@ -5,6 +5,7 @@ use mini_langchain_core::loader::{Loader, TextLoader as CoreTextLoader};
 use mini_langchain_core::schema::Document as CoreDocument;
 use mini_langchain_core::embedding::{Embeddings, MockEmbeddings as CoreMockEmbeddings};
 use mini_langchain_core::vectorstore::{VectorStore, InMemoryVectorStore as CoreInMemoryVectorStore};
+use mini_langchain_core::providers::ollama::OllamaEmbeddings as CoreOllamaEmbeddings;
 
 #[pyclass]
 pub struct Document {
@@ -77,6 +78,36 @@ impl MockEmbeddings {
     }
 }
 
+#[pyclass]
+pub struct OllamaEmbeddings {
+    pub(crate) inner: Arc<CoreOllamaEmbeddings>,
+}
+
+#[pymethods]
+impl OllamaEmbeddings {
+    #[new]
+    #[pyo3(signature = (model, base_url=None, keep_alive=None))]
+    fn new(model: String, base_url: Option<String>, keep_alive: Option<String>) -> Self {
+        let mut embeddings = CoreOllamaEmbeddings::new(model, base_url);
+        if let Some(k) = keep_alive {
+            embeddings = embeddings.with_keep_alive(k);
+        }
+        Self { inner: Arc::new(embeddings) }
+    }
+
+    fn embed_query(&self, text: &str) -> PyResult<Vec<f32>> {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(self.inner.embed_query(text))
+           .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+
+    fn embed_documents(&self, texts: Vec<String>) -> PyResult<Vec<Vec<f32>>> {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(self.inner.embed_documents(&texts))
+           .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+}
+
 #[pyclass]
 pub struct InMemoryVectorStore {
     inner: Arc<CoreInMemoryVectorStore>,
@@ -85,10 +116,17 @@ pub struct InMemoryVectorStore {
 #[pymethods]
 impl InMemoryVectorStore {
     #[new]
-    fn new(embeddings: &MockEmbeddings) -> Self {
-        Self {
-            inner: Arc::new(CoreInMemoryVectorStore::new(embeddings.inner.clone())),
-        }
+    fn new(embeddings: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let embeddings: Arc<dyn Embeddings> = if let Ok(mock) = embeddings.extract::<PyRef<MockEmbeddings>>() {
+            mock.inner.clone()
+        } else if let Ok(ollama) = embeddings.extract::<PyRef<OllamaEmbeddings>>() {
+            ollama.inner.clone()
+        } else {
+            return Err(pyo3::exceptions::PyTypeError::new_err("embeddings must be MockEmbeddings or OllamaEmbeddings"));
+        };
+        Ok(Self {
+            inner: Arc::new(CoreInMemoryVectorStore::new(embeddings)),
+        })
     }
 
     fn add_documents(&self, docs: Vec<PyRef<Document>>) -> PyResult<Vec<String>> {
@@ -1,4 +1,9 @@
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use mini_langchain_core::cost::{CostLedger as CoreCostLedger, CostSummary, ModelPricing};
+use mini_langchain_core::llm::TokenUsage;
 use mini_langchain_core::token::TokenCounter;
 
 #[pyclass]
@@ -16,3 +21,86 @@ impl TokenCalculator {
         TokenCounter::estimate_cost(text, rate_per_1k)
     }
 }
+
+fn summary_dict<'py>(py: Python<'py>, summary: &CostSummary) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("calls", summary.calls)?;
+    dict.set_item("prompt_tokens", summary.prompt_tokens)?;
+    dict.set_item("completion_tokens", summary.completion_tokens)?;
+    dict.set_item("cached_tokens", summary.cached_tokens)?;
+    dict.set_item("cost", summary.cost)?;
+    dict.set_item("unpriced_calls", summary.unpriced_calls)?;
+    Ok(dict)
+}
+
+fn groups_dict<'py>(py: Python<'py>, groups: &BTreeMap<String, CostSummary>) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (key, summary) in groups {
+        dict.set_item(key, summary_dict(py, summary)?)?;
+    }
+    Ok(dict)
+}
+
+/// Accumulates the actual token usage and cost of chain calls. Attach it with
+/// `Chain.set_cost_ledger`. Summaries are dicts with "calls", "prompt_tokens",
+/// "completion_tokens", "cached_tokens", "cost" (USD) and "unpriced_calls".
+#[pyclass]
+pub struct CostLedger {
+    pub(crate) inner: Arc<CoreCostLedger>,
+}
+
+#[pymethods]
+impl CostLedger {
+    /// Starts with built-in prices for common OpenAI, Anthropic and Google models.
+    #[new]
+    fn new() -> Self {
+        Self { inner: Arc::new(CoreCostLedger::new()) }
+    }
+
+    /// Sets a model's USD price per million tokens.
+    #[pyo3(signature = (model, input_per_million, output_per_million, cached_input_per_million=None))]
+    fn set_price(&self, model: &str, input_per_million: f64, output_per_million: f64, cached_input_per_million: Option<f64>) {
+        let mut pricing = ModelPricing::new(input_per_million, output_per_million);
+        if let Some(cached) = cached_input_per_million {
+            pricing = pricing.with_cached_input(cached);
+        }
+        self.inner.set_price(model, pricing);
+    }
+
+    /// USD cost of the given usage on `model`, or None when the model has no price.
+    #[pyo3(signature = (model, prompt_tokens, completion_tokens, cached_tokens=0))]
+    fn cost(&self, model: &str, prompt_tokens: u32, completion_tokens: u32, cached_tokens: u32) -> Option<f64> {
+        let usage = TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            cached_tokens: Some(cached_tokens),
+            ..Default::default()
+        };
+        self.inner.pricing().cost(model, &usage)
+    }
+
+    fn total<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        summary_dict(py, &self.inner.total())
+    }
+
+    fn by_model<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        groups_dict(py, &self.inner.by_model())
+    }
+
+    fn by_chain<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        groups_dict(py, &self.inner.by_chain())
+    }
+
+    fn by_session<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        groups_dict(py, &self.inner.by_session())
+    }
+
+    fn by_tag<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        groups_dict(py, &self.inner.by_tag())
+    }
+
+    fn reset(&self) {
+        self.inner.reset();
+    }
+}